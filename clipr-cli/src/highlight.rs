@@ -0,0 +1,40 @@
+use clipr_common::stats::detect_language;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::ThemeSet;
+use syntect::parsing::SyntaxSet;
+use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
+
+// syntect syntax token (extension) of languages guessed by detect_language
+fn token(language: &str) -> Option<&'static str> {
+    let token = match language {
+        "Rust" => "rs",
+        "Python" => "py",
+        "JavaScript" => "js",
+        "Go" => "go",
+        "C" => "c",
+        "Shell" => "sh",
+        "SQL" => "sql",
+        "HTML" => "html",
+        "JSON" => "json",
+        "Lisp" => "lisp",
+        "Ruby" => "rb",
+        "Perl" => "pl",
+        _ => return None,
+    };
+    Some(token)
+}
+
+// Terminal colored value if it looks like code, None otherwise.
+pub fn highlight(value: &str) -> Option<String> {
+    let syntaxes = SyntaxSet::load_defaults_newlines();
+    let syntax = syntaxes.find_syntax_by_token(token(detect_language(value)?)?)?;
+    let themes = ThemeSet::load_defaults();
+    let mut lines = HighlightLines::new(syntax, &themes.themes["base16-ocean.dark"]);
+    let mut highlighted = String::new();
+    for line in LinesWithEndings::from(value) {
+        let ranges = lines.highlight_line(line, &syntaxes).ok()?;
+        highlighted.push_str(&as_24_bit_terminal_escaped(&ranges, false));
+    }
+    highlighted.push_str("\x1b[0m");
+    Some(highlighted)
+}
@@ -0,0 +1,215 @@
+use anyhow::{anyhow, bail, Result};
+use clipr_common::paths;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const LAUNCHD_LABEL: &str = "com.github.jintwo.clipr";
+
+const DEFAULT_CONFIG: &str = r#"# clipr config, every value is optional
+# (CLIPR_* environment variables override them, see README)
+
+host = "127.0.0.1"
+port = 8932
+# read commands from stdin, use `clipr-cli shell` when daemon runs as service
+interactive = false
+# db = "~/.local/share/clipr/db.json"
+# changes between saves are logged next to db to survive crashes
+# wal = false
+# output of `clipr-daemon --daemonize`, pid of running daemon
+# log_file = "~/.local/share/clipr/clipr.log"
+# pid_file = "~/.local/share/clipr/clipr.pid"
+discovery = false
+# grpc_port = 8933
+# osc52_port = 8934
+# plain text protocol for `nc`, no auth
+# text_port = 8935
+# pasteboards = ["find"]
+# newest item back on pasteboard emptied by reboot
+# restore_clipboard = true
+# cli asks before deleting more items at once
+# confirm_delete = 10
+# scripts = "~/.config/clipr/scripts"
+# fetch titles of copied links
+# unfurl = true
+# keep values of items tagged "secret" in Keychain instead of db
+# keychain = true
+# copy of value already in history only moves it to top: "global",
+# "window:1h" (only if it was used within last hour) or "off"
+# dedup = "global"
+# copies from web pages are captured as Markdown
+# html_to_markdown = true
+# days deleted items stay restorable (see `trash`)
+# trash_days = 7
+# history size, least used items are dropped first
+# max_entries = 1000
+# `list` ordered by frecency instead of recency
+# list_order = "frecency"
+
+# [display]
+# preview_length = 48
+# date_format = "%Y-%m-%d %H:%M"
+# columns = ["flags", "preview", "tags", "date", "app", "title", "note"]
+# template = "{index}: {pin} {preview} [{tags}] {age}"
+# masked with ••• in listings
+# redact = ["ghp_[A-Za-z0-9]+", "(?i)password=\\S+"]
+
+# sent with every request when daemon requires tokens
+# token = "s3cret"
+
+# cli and emacs give up on daemon after timeout seconds,
+# read-only requests are retried first
+# [client]
+# timeout = 10
+# retries = 2
+# list, get and select read db file when daemon is down
+# offline = true
+
+# [http]
+# max_body_size = 16777216
+# rate_limit = 100
+# tokens = [{ token = "s3cret" }, { token = "scripts-only", access = "read" }]
+
+# [ring]
+# size = 9
+# pins = true
+
+# [[apps]]
+# bundle_id = "com.1password.1password"
+# capture = false
+
+# commands need `unlock <passphrase>` first,
+# hash is `printf %s 'passphrase' | shasum -a 256`
+# [lock]
+# passphrase_sha256 = "..."
+# timeout = 300
+
+# strip utm_*, fbclid and such from copied links, unwrap redirect links
+# [clean_urls]
+# params = ["si"]
+# follow bit.ly, t.co and other short links (request per copied link)
+# resolve_shorteners = false
+
+# periodic cleanup: expired items, hash index, save
+# [maintenance]
+# interval = 3600
+
+# empty pasteboard after copy tagged "secret" by apps rule,
+# keep = false drops it from history too
+# [clear_secrets]
+# after = 30
+
+# spill (or truncate) values of oldest items over max_bytes
+# [memory]
+# max_bytes = 67108864
+# policy = "spill"
+
+# read-only copy of another daemon's history, pasteboard isn't captured
+# [mirror]
+# primary = "desktop.local:8932"
+# interval = 60
+"#;
+
+// clipr-daemon is expected next to clipr-cli (same cargo target dir or bin dir)
+fn daemon_path() -> Result<PathBuf> {
+    let cli = std::env::current_exe()?;
+    Ok(cli.with_file_name("clipr-daemon"))
+}
+
+fn launchd_plist(daemon: &Path, config: &Path, logs: &Path) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+  <key>Label</key>
+  <string>{LAUNCHD_LABEL}</string>
+  <key>ProgramArguments</key>
+  <array>
+    <string>{}</string>
+    <string>-c</string>
+    <string>{}</string>
+  </array>
+  <key>RunAtLoad</key>
+  <true/>
+  <key>KeepAlive</key>
+  <true/>
+  <key>StandardErrorPath</key>
+  <string>{}</string>
+</dict>
+</plist>
+"#,
+        daemon.display(),
+        config.display(),
+        logs.join("daemon.log").display()
+    )
+}
+
+fn systemd_unit(daemon: &Path, config: &Path) -> String {
+    format!(
+        "[Unit]\n\
+         Description=clipr clipboard manager\n\n\
+         [Service]\n\
+         ExecStart={} -c {}\n\
+         Restart=on-failure\n\n\
+         [Install]\n\
+         WantedBy=default.target\n",
+        daemon.display(),
+        config.display()
+    )
+}
+
+fn write_new(path: &Path, contents: &str, force: bool) -> Result<()> {
+    if path.exists() && !force {
+        bail!(
+            "{} already exists, pass --force to overwrite",
+            path.display()
+        );
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, contents)?;
+    println!("wrote {}", path.display());
+    Ok(())
+}
+
+// returns command that starts installed service
+fn install_service(config: &Path, data: &Path, force: bool) -> Result<String> {
+    let home = paths::expand_tilde("~");
+    let daemon = daemon_path()?;
+    if cfg!(target_os = "macos") {
+        let plist = home.join(format!("Library/LaunchAgents/{LAUNCHD_LABEL}.plist"));
+        write_new(&plist, &launchd_plist(&daemon, config, data), force)?;
+        Ok(format!("launchctl load -w {}", plist.display()))
+    } else {
+        let unit = home.join(".config/systemd/user/clipr.service");
+        write_new(&unit, &systemd_unit(&daemon, config), force)?;
+        Ok(String::from(
+            "systemctl --user daemon-reload && systemctl --user enable --now clipr",
+        ))
+    }
+}
+
+// Writes default config and creates data dir (see paths), optionally
+// installs launchd agent (systemd user unit elsewhere).
+pub fn init(service: bool, force: bool) -> Result<()> {
+    let config = paths::config_path().ok_or_else(|| anyhow!("HOME isn't set"))?;
+    let data = paths::data_dir().ok_or_else(|| anyhow!("HOME isn't set"))?;
+
+    write_new(&config, DEFAULT_CONFIG, force)?;
+    fs::create_dir_all(&data)?;
+    println!("created {}", data.display());
+
+    let start = if service {
+        install_service(&config, &data, force)?
+    } else {
+        String::from("clipr-daemon")
+    };
+
+    println!();
+    println!("next steps:");
+    println!("  edit {}", config.display());
+    println!("  start daemon: {start}");
+    println!("  try it: clipr-cli list, clipr-cli shell");
+    Ok(())
+}
@@ -1,26 +1,303 @@
 use anyhow::{bail, Result};
-use clap::Parser;
-use clipr_common::{Args, Command, Config, Payload};
-use std::sync::Arc;
+use async_std::io::BufReader;
+use async_std::prelude::*;
+use clap::{Parser, Subcommand, ValueEnum};
+use clipr_client::Client;
+use clipr_common::display::ColorChoice;
+use clipr_common::output::Verbosity;
+use clipr_common::range::Position;
+use clipr_common::{
+    format_item, guess_mime, Command, Config, DisplayConfig, IndexOrId, Payload,
+    DEFAULT_CONFIRM_DELETE,
+};
+use std::io::{IsTerminal, Read};
+use std::path::PathBuf;
+use std::time::Instant;
 
-async fn call(config: Arc<Config>, cmd: Command) -> Result<Payload, surf::Error> {
-    let uri = format!("http://{}/command", config.listen_on());
-    let req = surf::post(uri).body_json(&cmd)?;
-    let rep: Payload = req.recv_json().await?;
-    Ok(rep)
+#[cfg(feature = "highlight")]
+mod highlight;
+mod init;
+mod offline;
+mod shell;
+mod stop;
+mod tmux;
+mod update;
+
+#[derive(Parser, Debug)]
+struct Args {
+    #[clap(short, long, value_parser)]
+    config: Option<PathBuf>,
+    // json prints serialized payloads/items (for editor integrations, scripts)
+    #[clap(long, value_enum, default_value = "text", global = true)]
+    format: Format,
+    // confirm destructive commands (clear, large range deletes)
+    #[clap(long, global = true)]
+    yes: bool,
+    // auto colors only terminal output, unless NO_COLOR is set
+    #[clap(long, value_enum, default_value = "auto", global = true)]
+    color: ColorChoice,
+    // only errors, no `ok` (see output::Verbosity)
+    #[clap(short, long, global = true)]
+    quiet: bool,
+    // request JSON and response time, -vv daemon address too
+    #[clap(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    // seconds to wait for daemon, overrides [client] config
+    #[clap(long)]
+    timeout: Option<u64>,
+    // attempts after first one for read-only commands
+    #[clap(long)]
+    retries: Option<u32>,
+    // list, get and select read db file instead of asking daemon
+    #[clap(long, global = true)]
+    offline: bool,
+    #[clap(subcommand)]
+    command: Option<CliCommand>,
 }
 
-#[async_std::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
-    let config = Arc::new(Config::load_from_args(&args)?);
+#[derive(ValueEnum, Clone, Debug)]
+enum Format {
+    Text,
+    Json,
+}
+
+// Commands handled by cli itself, everything else is sent to daemon.
+// Parsed once, clap can't flatten boxed Command.
+#[allow(clippy::large_enum_variant)]
+#[derive(Subcommand, Debug)]
+enum CliCommand {
+    // read stdin and put it on pasteboard (like pbcopy)
+    Copy,
+    // print raw item value (like pbpaste)
+    Paste {
+        index: Option<usize>,
+    },
+    // print items as they are captured
+    Watch,
+    TmuxSync {
+        // capture tmux buffers back into clipr
+        #[clap(long)]
+        capture: bool,
+    },
+    // interactive prompt talking to daemon over http (see shell)
+    Shell,
+    // write default config, create data dir
+    Init {
+        // install launchd agent (systemd user unit on linux)
+        #[clap(long)]
+        service: bool,
+        // overwrite existing files
+        #[clap(long)]
+        force: bool,
+    },
+    // install latest release binaries (see update)
+    SelfUpdate {
+        // only report available version
+        #[clap(long)]
+        check: bool,
+        // minisign public key release checksums are signed with
+        #[clap(long)]
+        pubkey: Option<String>,
+        // replace binaries without verifying checksums signature
+        #[clap(long)]
+        insecure: bool,
+    },
+    // manage background daemon
+    #[clap(name = "daemon", subcommand)]
+    Service(ServiceCommand),
+    #[clap(flatten)]
+    Daemon(Command),
+}
+
+#[derive(Subcommand, Debug)]
+enum ServiceCommand {
+    // quit running daemon, its pid is signaled when it doesn't answer
+    Stop,
+}
+
+// value is streamed to stdout as is
+async fn write_raw(client: &Client, target: IndexOrId) -> Result<()> {
+    let mut value = client.get_raw(target).await?;
+    let mut stdout = async_std::io::stdout();
+    async_std::io::copy(&mut value, &mut stdout).await?;
+    stdout.flush().await?;
+    Ok(())
+}
 
-    if let Some(cmd) = args.command {
-        match call(config, cmd).await {
-            Ok(payload) => println!("{}", String::from(&payload)),
-            Err(err) => bail!(err),
+// values that look like code are highlighted (`highlight` feature)
+fn format_payload(payload: &Payload, display: &DisplayConfig) -> String {
+    #[cfg(feature = "highlight")]
+    if let Payload::Value { value: Some(value) } = payload {
+        if let Some(highlighted) = highlight::highlight(value).filter(|_| display.color) {
+            return highlighted;
         }
     }
+    payload.format(display)
+}
+
+// Lists items of range delete larger than `confirm_delete` and asks for
+// confirmation, false if it wasn't given.
+async fn confirm_delete(
+    client: &Client,
+    cmd: &Command,
+    config: &Config,
+    display: &DisplayConfig,
+) -> Result<bool> {
+    let Command::Del {
+        from_index,
+        to_index,
+        expected_hash,
+        ..
+    } = cmd
+    else {
+        return Ok(true);
+    };
+    if to_index.is_none() && !matches!(from_index, Position::Range(..)) {
+        return Ok(true);
+    }
+    let dry_run = Command::Del {
+        from_index: *from_index,
+        to_index: *to_index,
+        expected_hash: *expected_hash,
+        dry_run: true,
+    };
+    let payload = client.call(dry_run).await?;
+    let count = match &payload {
+        Payload::List { value, .. } => value.len(),
+        // conflict and such are reported by the real call
+        _ => return Ok(true),
+    };
+    if count <= config.confirm_delete.unwrap_or(DEFAULT_CONFIRM_DELETE) {
+        return Ok(true);
+    }
+    if !std::io::stdin().is_terminal() {
+        bail!("del would delete {count} items, pass --yes to confirm");
+    }
+    eprintln!("{}", payload.format(display));
+    eprint!("delete {count} items? [y/N] ");
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
 
+fn print_payload(payload: &Payload, format: &Format, display: &DisplayConfig) -> Result<()> {
+    match format {
+        Format::Text => println!("{}", format_payload(payload, display)),
+        Format::Json => println!("{}", serde_json::to_string(payload)?),
+    }
     Ok(())
 }
+
+async fn run(client: &Client, args: Args, config: &Config, display: &DisplayConfig) -> Result<()> {
+    match args.command {
+        Some(CliCommand::Daemon(cmd)) if args.offline => {
+            print_payload(&offline::call(config, &cmd)?, &args.format, display)?
+        }
+        Some(CliCommand::Copy) => {
+            let mut value = String::new();
+            std::io::stdin().read_to_string(&mut value)?;
+            client.call(Command::Add { value: vec![value] }).await?;
+        }
+        Some(CliCommand::Paste { index }) => {
+            write_raw(client, index.unwrap_or(0).into()).await?;
+        }
+        Some(CliCommand::Daemon(Command::Get {
+            target, raw: true, ..
+        })) => {
+            write_raw(client, target).await?;
+        }
+        // file is read here, so it doesn't have to be accessible by daemon
+        Some(CliCommand::Daemon(Command::Insert { filename, mime, .. })) => {
+            let mime = mime.unwrap_or_else(|| guess_mime(&filename).to_string());
+            if filename == "-" {
+                let stdin = BufReader::new(async_std::io::stdin());
+                client.insert(stdin, &filename, &mime).await?;
+            } else {
+                let file = BufReader::new(async_std::fs::File::open(&filename).await?);
+                client.insert(file, &filename, &mime).await?;
+            }
+        }
+        Some(CliCommand::Watch) => {
+            let mut items = client.subscribe().await?;
+            while let Some(item) = items.next().await {
+                let item = item?;
+                match args.format {
+                    Format::Text => {
+                        println!("{}", format_item(&item, true, None, display))
+                    }
+                    Format::Json => println!("{}", serde_json::to_string(&item)?),
+                }
+            }
+        }
+        Some(CliCommand::TmuxSync { capture }) => tmux::sync(client.clone(), capture).await?,
+        Some(CliCommand::Shell) => bail!("already in shell"),
+        Some(CliCommand::Init { service, force }) => init::init(service, force)?,
+        Some(CliCommand::Service(ServiceCommand::Stop)) => stop::stop(client, config).await?,
+        Some(CliCommand::SelfUpdate {
+            check,
+            pubkey,
+            insecure,
+        }) => update::self_update(check, pubkey, insecure).await?,
+        Some(CliCommand::Daemon(Command::Clear { .. })) if !args.yes => {
+            bail!("clear wipes whole history, pass --yes to confirm");
+        }
+        Some(CliCommand::Daemon(ref cmd @ Command::Del { dry_run: false, .. }))
+            if !args.yes && !confirm_delete(client, cmd, config, display).await? =>
+        {
+            bail!("nothing deleted");
+        }
+        Some(CliCommand::Daemon(cmd)) => {
+            let verbosity = Verbosity::new(args.quiet, args.verbose);
+            verbosity.request(&cmd, Some(client.base_url()));
+            let started = Instant::now();
+            let payload = client.call(cmd).await?;
+            verbosity.response(started.elapsed());
+            if verbosity.shows(&payload) {
+                print_payload(&payload, &args.format, display)?;
+            }
+        }
+        None => {}
+    }
+
+    Ok(())
+}
+
+#[async_std::main]
+async fn main() -> Result<()> {
+    let mut args = Args::parse();
+    // existing config may be broken, it's rewritten with --force
+    if let Some(CliCommand::Init { service, force }) = args.command {
+        return init::init(service, force);
+    }
+    let mut config = Config::load(args.config.as_deref())?;
+    let client_config = config.client.get_or_insert_with(Default::default);
+    client_config.timeout = args.timeout.or(client_config.timeout);
+    client_config.retries = args.retries.or(client_config.retries);
+    let client = Client::from_config(&config)?;
+    let mut display = config.display();
+    display.color = args.color.enabled();
+    // set-pin is bound to hotkeys, one request only
+    if !args.offline
+        && !matches!(
+            args.command,
+            None | Some(CliCommand::SelfUpdate { .. })
+                | Some(CliCommand::Service(_))
+                | Some(CliCommand::Daemon(Command::SetPin { .. }))
+        )
+    {
+        if let Err(err) = client.check_version().await {
+            let fallback =
+                matches!(&args.command, Some(CliCommand::Daemon(cmd)) if offline::supports(cmd));
+            if !(fallback && config.client().offline()) {
+                return Err(err);
+            }
+            eprintln!("daemon unreachable: {err:#}");
+            args.offline = true;
+        }
+    }
+
+    match args.command {
+        Some(CliCommand::Shell) => shell::run_shell(client, config, display).await,
+        _ => run(&client, args, &config, &display).await,
+    }
+}
@@ -0,0 +1,79 @@
+use anyhow::{bail, Context, Result};
+use clipr_common::{template, Command, Config, Payload, State};
+use clipr_core::storage::{FileStorage, Storage};
+use clipr_core::{keychain, read_call};
+use std::time::SystemTime;
+
+// commands answered from db file while daemon is down
+pub fn supports(cmd: &Command) -> bool {
+    matches!(
+        cmd,
+        Command::List { .. } | Command::Get { .. } | Command::Select { set: false, .. }
+    )
+}
+
+// Runs read-only cmd against history as daemon last saved it (see --offline),
+// db is never written. Snapshot age goes to stderr, so it's not mistaken for
+// live history.
+pub fn call(config: &Config, cmd: &Command) -> Result<Payload> {
+    if !supports(cmd) {
+        bail!("only list, get and select work offline");
+    }
+    let path = config.db_path();
+    let storage = FileStorage::new(path.clone());
+    let storage = if config.wal() {
+        storage.with_wal()
+    } else {
+        storage
+    };
+    let mut state = State::new(config.clone());
+    state.spill_dir = storage.spill_dir();
+    let mut entries = storage
+        .load()
+        .with_context(|| format!("can't read {}", path.display()))?;
+    if !entries.is_consistent() {
+        entries.rebuild(|item| keychain::hash(&state, item), config.dedup());
+    }
+    let saved = std::fs::metadata(&path)?.modified()?;
+    eprintln!(
+        "offline: snapshot of {} saved {} ago, may be stale",
+        path.display(),
+        template::age(saved, SystemTime::now())
+    );
+    *state.entries.write().unwrap() = entries;
+    Ok(read_call(&state, cmd))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clipr_common::{Entries, IndexOrId};
+
+    #[test]
+    fn test_call_replays_wal() {
+        let dir = std::env::temp_dir().join(format!("clipr-offline-{}", std::process::id()));
+        let db = dir.join("db.json");
+        let storage = FileStorage::new(db.clone()).with_wal();
+        let mut entries = Entries::new();
+        entries.insert(String::from("saved"));
+        storage.save(&entries).unwrap();
+        entries.take_changes();
+        // logged by running daemon, not in db yet
+        entries.insert(String::from("unsaved"));
+        let changes = entries.take_changes();
+        storage.log(&entries, &changes).unwrap();
+
+        let config = Config {
+            db: Some(db.display().to_string()),
+            ..Config::default()
+        };
+        let get = Command::Get {
+            target: IndexOrId::Index(0),
+            raw: false,
+            stats: false,
+        };
+        let payload = call(&config, &get).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(payload, Payload::Value { value: Some(value) } if value == "unsaved"));
+    }
+}
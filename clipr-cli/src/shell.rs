@@ -0,0 +1,136 @@
+use crate::{run, Args};
+use anyhow::Result;
+use clap::{CommandFactory, Parser};
+use clipr_client::Client;
+use clipr_common::{Command, Config, DisplayConfig, Payload};
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Context, Editor, Helper};
+
+// Same completion as daemon repl, tags are fetched from daemon before every prompt.
+struct ShellHelper {
+    tags: Vec<String>,
+}
+
+impl ShellHelper {
+    fn candidates(&self, words: &[&str], word: &str) -> Vec<String> {
+        let names = match words {
+            [] => Args::command()
+                .get_subcommands()
+                .map(|cmd| cmd.get_name().to_string())
+                .filter(|name| name != "shell")
+                .collect(),
+            [.., "--tag"] | ["tag" | "untag", _] => self.tags.clone(),
+            _ => vec![],
+        };
+        names
+            .into_iter()
+            .filter(|name| name.starts_with(word))
+            .collect()
+    }
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let words = line[..start].split_whitespace().collect::<Vec<&str>>();
+        let pairs = self
+            .candidates(&words, &line[start..])
+            .into_iter()
+            .map(|name| Pair {
+                display: name.clone(),
+                replacement: format!("{name} "),
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Validator for ShellHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        match shellwords::split(ctx.input()) {
+            Err(shellwords::MismatchedQuotes) => Ok(ValidationResult::Incomplete),
+            Ok(_) => Ok(ValidationResult::Valid(None)),
+        }
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+async fn fetch_tags(client: &Client) -> Vec<String> {
+    match client.call(Command::Tags).await {
+        Ok(Payload::Tags { value }) => value.into_iter().map(|(tag, _)| tag).collect(),
+        _ => vec![],
+    }
+}
+
+// Lines are parsed as cli arguments, so cli-only commands (paste, watch, ...)
+// work too. Ctrl-D/Ctrl-C leaves shell, `quit` stops daemon.
+pub async fn run_shell(client: Client, config: Config, display: DisplayConfig) -> Result<()> {
+    let mut rl = Editor::<ShellHelper>::new()?;
+    rl.set_helper(Some(ShellHelper { tags: vec![] }));
+    let history = clipr_common::paths::repl_history_path();
+    if let Some(path) = &history {
+        // there is no history on first run
+        let _ = rl.load_history(path);
+    }
+    loop {
+        let tags = fetch_tags(&client).await;
+        if let Some(helper) = rl.helper_mut() {
+            helper.tags = tags;
+        }
+        let line = match rl.readline(":> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        // `unlock <passphrase>` isn't kept, not even in memory
+        if !clipr_common::lock::has_passphrase(&line) {
+            rl.add_history_entry(line.as_str());
+            if let Some(path) = &history {
+                let saved = path
+                    .parent()
+                    .map_or(Ok(()), std::fs::create_dir_all)
+                    .map_err(ReadlineError::from)
+                    .and_then(|_| rl.save_history(path));
+                if let Err(err) = saved {
+                    eprintln!("shell history isn't saved: {err}");
+                }
+            }
+        }
+
+        // quotes are balanced, see ShellHelper::validate
+        let mut cmd_line = shellwords::split(line.as_str()).unwrap();
+        cmd_line.insert(0, String::from("clipr-cli"));
+        match Args::try_parse_from(cmd_line) {
+            Ok(args) => {
+                if let Err(err) = run(&client, args, &config, &display).await {
+                    println!("error: {err}");
+                }
+            }
+            // prints help too
+            Err(err) => err.print()?,
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use anyhow::{bail, Result};
+use async_std::task;
+use clipr_client::Client;
+use clipr_common::{Command, Config};
+use std::time::Duration;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+// daemon saves history before exiting, that may take a moment
+const EXIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn read_pid(config: &Config) -> Option<libc::pid_t> {
+    std::fs::read_to_string(config.pid_path())
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+fn is_alive(pid: libc::pid_t) -> bool {
+    unsafe { libc::kill(pid, 0) == 0 }
+}
+
+// false if daemon is still around after EXIT_TIMEOUT, without pid it's gone
+// once it stops answering
+async fn wait_exit(client: &Client, pid: Option<libc::pid_t>) -> bool {
+    let attempts = EXIT_TIMEOUT.as_millis() / POLL_INTERVAL.as_millis();
+    for _ in 0..attempts {
+        let gone = match pid {
+            Some(pid) => !is_alive(pid),
+            None => client.version().await.is_err(),
+        };
+        if gone {
+            return true;
+        }
+        task::sleep(POLL_INTERVAL).await;
+    }
+    false
+}
+
+// `quit` over API first, SIGTERM to pid from pid file when daemon doesn't
+// answer or doesn't exit (it quits on SIGTERM the same way).
+pub async fn stop(client: &Client, config: &Config) -> Result<()> {
+    let pid = read_pid(config).filter(|pid| is_alive(*pid));
+    if client.version().await.is_ok() {
+        // daemon exits without replying
+        let _ = client.call(Command::Quit).await;
+        if wait_exit(client, pid).await {
+            return Ok(());
+        }
+    }
+    let Some(pid) = pid else {
+        bail!(
+            "daemon isn't running (nothing answers at {}, no pid in {})",
+            client.base_url(),
+            config.pid_path().display()
+        );
+    };
+    eprintln!("daemon doesn't answer, sending SIGTERM to pid {pid}");
+    if unsafe { libc::kill(pid, libc::SIGTERM) } == -1 {
+        bail!("can't signal {pid}: {}", std::io::Error::last_os_error());
+    }
+    if !wait_exit(client, Some(pid)).await {
+        bail!("daemon (pid {pid}) didn't stop");
+    }
+    Ok(())
+}
@@ -0,0 +1,68 @@
+use anyhow::{bail, Result};
+use async_std::prelude::*;
+use async_std::task;
+use clipr_client::Client;
+use clipr_common::{calculate_hash, Command};
+use std::process::Command as Process;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const CAPTURE_INTERVAL: Duration = Duration::from_millis(500);
+
+fn tmux(args: &[&str]) -> Result<String> {
+    let output = Process::new("tmux").args(args).output()?;
+    if !output.status.success() {
+        bail!(
+            "tmux {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+// Polls the most recent tmux buffer and adds new ones to clipr.
+async fn capture(client: Client, last_hash: Arc<Mutex<u64>>) -> Result<()> {
+    loop {
+        task::sleep(CAPTURE_INTERVAL).await;
+        // no buffers yet
+        let Ok(value) = tmux(&["show-buffer"]) else {
+            continue;
+        };
+        let hash = calculate_hash(&value);
+        if value.is_empty() || *last_hash.lock().unwrap() == hash {
+            continue;
+        }
+        *last_hash.lock().unwrap() = hash;
+        client.call(Command::Add { value: vec![value] }).await?;
+    }
+}
+
+// Mirrors every item captured by daemon into tmux paste buffers.
+pub async fn sync(client: Client, with_capture: bool) -> Result<()> {
+    // last value seen on either side, so values don't bounce back and forth
+    let current = tmux(&["show-buffer"]).map_or(0, |value| calculate_hash(&value));
+    let last_hash = Arc::new(Mutex::new(current));
+
+    if with_capture {
+        let (client, last_hash) = (client.clone(), last_hash.clone());
+        task::spawn(async move {
+            if let Err(err) = capture(client, last_hash).await {
+                eprintln!("tmux capture stopped: {err}");
+            }
+        });
+    }
+
+    let mut items = client.subscribe().await?;
+    while let Some(item) = items.next().await {
+        let item = item?;
+        let hash = calculate_hash(&item.value);
+        if *last_hash.lock().unwrap() == hash {
+            continue;
+        }
+        *last_hash.lock().unwrap() = hash;
+        tmux(&["set-buffer", "--", &item.value])?;
+    }
+
+    Ok(())
+}
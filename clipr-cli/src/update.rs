@@ -0,0 +1,169 @@
+use anyhow::{anyhow, bail, Result};
+use clipr_common::version;
+use minisign_verify::{PublicKey, Signature};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+const RELEASES_URL: &str = "https://api.github.com/repos/jintwo/clipr/releases/latest";
+const CHECKSUMS: &str = "SHA256SUMS";
+// minisign signature of CHECKSUMS
+const CHECKSUMS_SIGNATURE: &str = "SHA256SUMS.minisig";
+const BINARIES: [&str; 2] = ["clipr-cli", "clipr-daemon"];
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    fn asset(&self, name: &str) -> Result<&Asset> {
+        self.assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .ok_or_else(|| anyhow!("release {} has no {name}", self.tag_name))
+    }
+}
+
+// release assets are plain binaries named like `clipr-cli-macos-aarch64`
+fn asset_name(binary: &str) -> String {
+    format!(
+        "{binary}-{}-{}",
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    )
+}
+
+async fn get(url: &str) -> Result<surf::Response> {
+    let res = surf::get(url)
+        // required by GitHub API
+        .header("User-Agent", "clipr-cli")
+        .middleware(surf::middleware::Redirect::default())
+        .await
+        .map_err(|err| anyhow!(err))?;
+    if !res.status().is_success() {
+        bail!("{url}: {}", res.status());
+    }
+    Ok(res)
+}
+
+async fn download(asset: &Asset) -> Result<Vec<u8>> {
+    let mut res = get(&asset.browser_download_url).await?;
+    res.body_bytes().await.map_err(|err| anyhow!(err))
+}
+
+// `<sha256 hex>  <file name>` lines, as written by `shasum -a 256`
+fn checksum<'a>(checksums: &'a str, name: &str) -> Option<&'a str> {
+    checksums.lines().find_map(|line| {
+        let (hash, file) = line.split_once(char::is_whitespace)?;
+        (file.trim().trim_start_matches('*') == name).then_some(hash)
+    })
+}
+
+// new binary is written next to old one and renamed over it,
+// so running daemon keeps its file until restart
+fn replace(path: &Path, data: &[u8]) -> Result<()> {
+    let tmp = path.with_extension("new");
+    std::fs::write(&tmp, data)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o755))?;
+    }
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+// Replaces clipr-cli and clipr-daemon (when installed next to it) with
+// binaries of latest GitHub release. SHA256SUMS is always checked and its
+// minisign signature is verified with public key given by --pubkey or
+// CLIPR_UPDATE_PUBKEY; without one nothing is replaced unless --insecure.
+pub async fn self_update(check: bool, pubkey: Option<String>, insecure: bool) -> Result<()> {
+    let current = version::VERSION;
+    let release: Release = get(RELEASES_URL)
+        .await?
+        .body_json()
+        .await
+        .map_err(|err| anyhow!(err))?;
+    if version::parse(&release.tag_name)? <= version::parse(current)? {
+        println!("clipr {current} is up to date");
+        return Ok(());
+    }
+    println!(
+        "clipr {} is available (current {current})",
+        release.tag_name
+    );
+    if check {
+        return Ok(());
+    }
+    let pubkey = pubkey.or_else(|| std::env::var("CLIPR_UPDATE_PUBKEY").ok());
+    if pubkey.is_none() && !insecure {
+        bail!("no public key to verify {CHECKSUMS} signature, pass --pubkey (or --insecure to skip verification)");
+    }
+
+    let checksums = String::from_utf8(download(release.asset(CHECKSUMS)?).await?)?;
+    match pubkey {
+        Some(pubkey) => {
+            let pubkey = PublicKey::from_base64(&pubkey)?;
+            let signature = download(release.asset(CHECKSUMS_SIGNATURE)?).await?;
+            let signature = Signature::decode(&String::from_utf8(signature)?)?;
+            pubkey.verify(checksums.as_bytes(), &signature, false)?;
+        }
+        None => eprintln!("--insecure, {CHECKSUMS} signature isn't verified"),
+    }
+
+    // everything is verified before first binary is replaced
+    let cli = std::env::current_exe()?;
+    let mut updates = vec![];
+    for binary in BINARIES {
+        let path = cli.with_file_name(binary);
+        if !path.exists() {
+            continue;
+        }
+        let name = asset_name(binary);
+        let expected = checksum(&checksums, &name)
+            .ok_or_else(|| anyhow!("{name} is missing in {CHECKSUMS}"))?;
+        let data = download(release.asset(&name)?).await?;
+        if hex::encode(Sha256::digest(&data)) != expected.to_lowercase() {
+            bail!("{name} checksum mismatch");
+        }
+        updates.push((path, data));
+    }
+    for (path, data) in updates {
+        replace(&path, &data)?;
+        println!("updated {}", path.display());
+    }
+    println!("restart clipr-daemon to run new version");
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_checksum() {
+        let checksums = "\
+aaa  clipr-cli-macos-aarch64
+bbb *clipr-daemon-macos-aarch64
+ccc\tclipr-cli-linux-x86_64  
+";
+        assert_eq!(checksum(checksums, "clipr-cli-macos-aarch64"), Some("aaa"));
+        // binary mode marker
+        assert_eq!(
+            checksum(checksums, "clipr-daemon-macos-aarch64"),
+            Some("bbb")
+        );
+        // tab separator and trailing whitespace
+        assert_eq!(checksum(checksums, "clipr-cli-linux-x86_64"), Some("ccc"));
+        assert_eq!(checksum(checksums, "clipr-cli"), None);
+        assert_eq!(checksum("", "clipr-cli-macos-aarch64"), None);
+    }
+}
@@ -0,0 +1,342 @@
+use anyhow::{anyhow, Result};
+use async_std::io::{BufRead as AsyncBufRead, BufReader};
+use async_std::prelude::*;
+use async_std::stream::Stream;
+use async_std::{future, task};
+use clipr_common::range::Position;
+use clipr_common::stats::Stats;
+use clipr_common::version::{self, VersionInfo, MIN_DAEMON_VERSION};
+use clipr_common::{
+    ClientConfig, Command, Config, ErrorBody, Filter, IndexOrId, Item, ItemSummary, Payload,
+};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::time::Duration;
+
+pub type Items = Vec<(usize, ItemSummary)>;
+
+// Typed HTTP client for clipr-daemon.
+// Clones share underlying HTTP client (and its connection pool).
+#[derive(Clone, Debug)]
+pub struct Client {
+    base_url: String,
+    http: surf::Client,
+    config: ClientConfig,
+}
+
+// first retry waits that long, every next one twice as long
+const RETRY_BACKOFF: Duration = Duration::from_millis(200);
+
+fn unexpected(payload: Payload) -> anyhow::Error {
+    match payload {
+        Payload::Message { value } => anyhow!(value),
+        other @ (Payload::NotFound { .. }
+        | Payload::Conflict { .. }
+        | Payload::Locked
+        | Payload::Ambiguous { .. }
+        | Payload::InvalidArgs { .. }) => anyhow!(String::from(&other)),
+        other => anyhow!("unexpected payload: {}", String::from(&other)),
+    }
+}
+
+// non-2xx responses carry ErrorBody
+async fn check_status(res: &mut surf::Response) -> Result<()> {
+    if !res.status().is_success() {
+        let body: ErrorBody = res.body_json().await.map_err(|err| err.into_inner())?;
+        return Err(anyhow!(body.detail));
+    }
+    Ok(())
+}
+
+impl Client {
+    pub fn new(listen_on: &str) -> Self {
+        Self {
+            base_url: format!("http://{listen_on}"),
+            http: surf::Client::new(),
+            config: ClientConfig::default(),
+        }
+    }
+
+    pub fn with_config(self, config: ClientConfig) -> Self {
+        Self { config, ..self }
+    }
+
+    // every request carries `Authorization: Bearer <token>` (see HttpConfig::tokens)
+    pub fn with_token(self, token: &str) -> Result<Self> {
+        let http = surf::Config::new()
+            .add_header("Authorization", format!("Bearer {token}"))
+            .map_err(|err| err.into_inner())?
+            .try_into()
+            .map_err(|err| anyhow!("{err}"))?;
+        Ok(Self { http, ..self })
+    }
+
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let client = Self::new(&config.listen_on()).with_config(config.client());
+        match &config.token {
+            Some(token) => client.with_token(token),
+            None => Ok(client),
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn not_responding(&self) -> anyhow::Error {
+        anyhow!(
+            "daemon not responding at {} (no reply in {}s)",
+            self.base_url,
+            self.config.timeout().as_secs_f64()
+        )
+    }
+
+    // Request (rebuilt for each attempt) is given ClientConfig::timeout to
+    // reply, failed or timed out idempotent ones are retried with backoff.
+    // Others are sent once, daemon may have run them already.
+    async fn send(
+        &self,
+        idempotent: bool,
+        req: impl Fn() -> surf::Result<surf::RequestBuilder>,
+    ) -> Result<surf::Response> {
+        let attempts = if idempotent {
+            self.config.retries() + 1
+        } else {
+            1
+        };
+        let mut backoff = RETRY_BACKOFF;
+        for attempt in 1.. {
+            let err = match future::timeout(
+                self.config.timeout(),
+                req().map_err(|err| err.into_inner())?,
+            )
+            .await
+            {
+                Ok(Ok(res)) => return Ok(res),
+                Ok(Err(err)) => err.into_inner(),
+                Err(_) => self.not_responding(),
+            };
+            if attempt >= attempts {
+                return Err(err);
+            }
+            task::sleep(backoff).await;
+            backoff *= 2;
+        }
+        unreachable!()
+    }
+
+    // body of reply, under the same timeout
+    async fn read_json<T: DeserializeOwned>(&self, mut res: surf::Response) -> Result<T> {
+        let body = async {
+            check_status(&mut res).await?;
+            res.body_json().await.map_err(|err| err.into_inner())
+        };
+        future::timeout(self.config.timeout(), body)
+            .await
+            .map_err(|_| self.not_responding())?
+    }
+
+    pub async fn version(&self) -> Result<VersionInfo> {
+        let uri = format!("{}/version", self.base_url);
+        let res = self.send(true, || Ok(self.http.get(&uri))).await?;
+        // route was added along with handshake
+        if res.status() == surf::StatusCode::NotFound {
+            return Err(anyhow!(
+                "daemon predates version handshake, client needs >={MIN_DAEMON_VERSION}"
+            ));
+        }
+        self.read_json(res).await
+    }
+
+    // Fails with readable error when daemon is too old for this client,
+    // instead of payload deserialization errors later on.
+    pub async fn check_version(&self) -> Result<()> {
+        version::check_daemon(&self.version().await?.version)
+    }
+
+    pub async fn call(&self, cmd: Command) -> Result<Payload> {
+        let uri = format!("{}/command", self.base_url);
+        let res = self
+            .send(cmd.is_read_only(), || self.http.post(&uri).body_json(&cmd))
+            .await?;
+        self.read_json(res).await
+    }
+
+    async fn call_ok(&self, cmd: Command) -> Result<()> {
+        match self.call(cmd).await? {
+            Payload::Ok => Ok(()),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    async fn call_list(&self, cmd: Command) -> Result<Items> {
+        match self.call(cmd).await? {
+            Payload::List { value, .. } => Ok(value),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    pub async fn list(
+        &self,
+        from_index: Option<usize>,
+        to_index: Option<usize>,
+        preview_length: Option<usize>,
+    ) -> Result<Items> {
+        self.call_list(Command::List {
+            from_index: from_index.map(Position::from),
+            to_index: to_index.map(|index| index as i64),
+            preview_length,
+            favorites: false,
+            ranked: false,
+            template: None,
+            filter: Filter::default(),
+        })
+        .await
+    }
+
+    pub async fn get(&self, target: impl Into<IndexOrId>) -> Result<String> {
+        let target = target.into();
+        let cmd = Command::Get {
+            target,
+            raw: false,
+            stats: false,
+        };
+        match self.call(cmd).await? {
+            Payload::Value { value: Some(value) } => Ok(value),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    pub async fn stats(&self, target: impl Into<IndexOrId>) -> Result<Stats> {
+        let cmd = Command::Get {
+            target: target.into(),
+            raw: false,
+            stats: true,
+        };
+        match self.call(cmd).await? {
+            Payload::Stats { value } => Ok(value),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    // one call for several values, None for missing items
+    pub async fn get_many(&self, targets: Vec<IndexOrId>) -> Result<Vec<Option<String>>> {
+        match self.call(Command::GetMany { targets }).await? {
+            Payload::Values { value } => Ok(value),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    // newest item pinned to pin and/or tagged tag, None if there is none
+    pub async fn get_by(&self, pin: Option<char>, tag: Option<&str>) -> Result<Option<String>> {
+        let cmd = Command::GetBy {
+            pin,
+            tag: tag.map(String::from),
+        };
+        match self.call(cmd).await? {
+            Payload::Value { value } => Ok(value),
+            Payload::NotFound { .. } => Ok(None),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    pub async fn set_pin(&self, pin: char) -> Result<()> {
+        let cmd = Command::SetPin {
+            pin,
+            pasteboard: None,
+        };
+        match self.call(cmd).await? {
+            Payload::Ok => Ok(()),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    // Value is streamed as is, response implements AsyncRead.
+    pub async fn get_raw(&self, target: impl Into<IndexOrId>) -> Result<surf::Response> {
+        let uri = format!("{}/items/{}/raw", self.base_url, target.into());
+        let mut res = self.send(true, || Ok(self.http.get(&uri))).await?;
+        check_status(&mut res).await?;
+        Ok(res)
+    }
+
+    // expected_hash guards against history changes since item was listed
+    pub async fn set(
+        &self,
+        target: impl Into<IndexOrId>,
+        expected_hash: Option<u64>,
+    ) -> Result<()> {
+        self.call_ok(Command::Set {
+            target: target.into(),
+            pasteboard: None,
+            expected_hash,
+            transform: None,
+        })
+        .await
+    }
+
+    // Contents are streamed to daemon and put on pasteboard as text or image
+    // (see mime), without timeout, upload may take a while.
+    pub async fn insert(
+        &self,
+        data: impl AsyncBufRead + Unpin + Send + Sync + 'static,
+        filename: &str,
+        mime: &str,
+    ) -> Result<()> {
+        let uri = format!("{}/insert", self.base_url);
+        let query = HashMap::from([("filename", filename)]);
+        let req = self
+            .http
+            .post(uri)
+            .query(&query)
+            .map_err(|err| err.into_inner())?
+            .header("Content-Type", mime)
+            .body(surf::Body::from_reader(data, None));
+        let mut res = req.await.map_err(|err| err.into_inner())?;
+        check_status(&mut res).await?;
+        match res.body_json().await.map_err(|err| err.into_inner())? {
+            Payload::Ok => Ok(()),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    pub async fn select(&self, filter: Filter) -> Result<Items> {
+        self.call_list(Command::Select {
+            set: false,
+            all: false,
+            filter,
+        })
+        .await
+    }
+
+    pub async fn tag(&self, target: impl Into<IndexOrId>, tag: &str) -> Result<()> {
+        self.call_ok(Command::Tag {
+            target: target.into(),
+            tag: tag.to_string(),
+        })
+        .await
+    }
+
+    pub async fn untag(&self, target: impl Into<IndexOrId>, tag: &str) -> Result<()> {
+        self.call_ok(Command::Untag {
+            target: target.into(),
+            tag: tag.to_string(),
+        })
+        .await
+    }
+
+    // Items captured by daemon from now on (server-sent events on /events).
+    pub async fn subscribe(&self) -> Result<Pin<Box<dyn Stream<Item = Result<Item>> + Send>>> {
+        let uri = format!("{}/events", self.base_url);
+        let response = self.http.get(uri).await.map_err(|err| err.into_inner())?;
+        let items = BufReader::new(response)
+            .lines()
+            .filter_map(|line| match line {
+                Ok(line) => line
+                    .strip_prefix("data:")
+                    .map(|data| serde_json::from_str(data.trim()).map_err(anyhow::Error::from)),
+                Err(err) => Some(Err(err.into())),
+            });
+        Ok(Box::pin(items))
+    }
+}
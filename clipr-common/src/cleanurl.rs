@@ -0,0 +1,192 @@
+use serde::Deserialize;
+
+// Query params dropped from copied links, `*` suffix matches by prefix.
+pub const TRACKING_PARAMS: &[&str] = &[
+    "utm_*", "fbclid", "gclid", "dclid", "msclkid", "yclid", "igshid", "mc_cid", "mc_eid",
+    "_hsenc", "_hsmi", "ref_src",
+];
+
+// (host, path, param holding target link)
+const REDIRECTORS: &[(&str, &str, &str)] = &[
+    ("www.google.com", "/url", "q"),
+    ("www.google.com", "/url", "url"),
+    ("l.facebook.com", "/l.php", "u"),
+    ("lm.facebook.com", "/l.php", "u"),
+    ("l.instagram.com", "/", "u"),
+    ("www.youtube.com", "/redirect", "q"),
+    ("out.reddit.com", "/", "url"),
+    ("slack-redir.net", "/link", "url"),
+    ("t.umblr.com", "/redirect", "z"),
+];
+
+// resolved only with `resolve_shorteners = true` (see clipr_core::cleanurl)
+pub const SHORTENERS: &[&str] = &[
+    "bit.ly",
+    "t.co",
+    "tinyurl.com",
+    "goo.gl",
+    "ow.ly",
+    "buff.ly",
+    "is.gd",
+    "lnkd.in",
+    "rebrand.ly",
+];
+
+// `[clean_urls]` section enables cleanup of copied links, `apps` rules
+// turn it on or off per application (see AppRule::clean_urls).
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct CleanUrlsConfig {
+    // dropped in addition to TRACKING_PARAMS
+    pub params: Option<Vec<String>>,
+    // follow links of SHORTENERS hosts (request per copied link)
+    pub resolve_shorteners: Option<bool>,
+}
+
+// used by `apps` rules when there is no `[clean_urls]` section
+pub const DEFAULT_CONFIG: CleanUrlsConfig = CleanUrlsConfig {
+    params: None,
+    resolve_shorteners: None,
+};
+
+impl CleanUrlsConfig {
+    fn is_tracking(&self, name: &str) -> bool {
+        TRACKING_PARAMS
+            .iter()
+            .copied()
+            .chain(self.params.iter().flatten().map(String::as_str))
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == pattern,
+            })
+    }
+
+    // None if link is clean already (or isn't a link)
+    pub fn clean(&self, value: &str) -> Option<String> {
+        if !crate::unfurl::is_url(value) {
+            return None;
+        }
+        let mut url = value.trim().to_string();
+        // redirectors may wrap each other
+        for _ in 0..3 {
+            match unwrap_redirect(&url) {
+                Some(target) => url = target,
+                None => break,
+            }
+        }
+        let url = self.strip_params(&url);
+        Some(url).filter(|url| url != value.trim())
+    }
+
+    fn strip_params(&self, url: &str) -> String {
+        let (url, fragment) = match url.split_once('#') {
+            Some((url, fragment)) => (url, Some(fragment)),
+            None => (url, None),
+        };
+        let mut clean = match url.split_once('?') {
+            Some((base, query)) => {
+                let query = query
+                    .split('&')
+                    .filter(|param| {
+                        let name = param.split('=').next().unwrap_or_default();
+                        !param.is_empty() && !self.is_tracking(name)
+                    })
+                    .collect::<Vec<&str>>()
+                    .join("&");
+                if query.is_empty() {
+                    base.to_string()
+                } else {
+                    format!("{base}?{query}")
+                }
+            }
+            None => url.to_string(),
+        };
+        if let Some(fragment) = fragment {
+            clean.push('#');
+            clean.push_str(fragment);
+        }
+        clean
+    }
+}
+
+// "host" of http(s) link, without port and userinfo
+pub fn host(url: &str) -> Option<&str> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?;
+    Some(host.split(':').next().unwrap_or(host))
+}
+
+fn unwrap_redirect(url: &str) -> Option<String> {
+    let host = host(url)?;
+    let (base, query) = url.split('#').next()?.split_once('?')?;
+    let path = base.split_once(host).map_or("/", |(_, path)| path);
+    let path = if path.is_empty() { "/" } else { path };
+    REDIRECTORS
+        .iter()
+        .filter(|(h, p, _)| *h == host && *p == path)
+        .find_map(|(_, _, name)| {
+            query
+                .split('&')
+                .filter_map(|param| param.split_once('='))
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| percent_decode(value))
+        })
+        .filter(|target| crate::unfurl::is_url(target))
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_clean() {
+        let config = CleanUrlsConfig {
+            params: Some(vec![String::from("si")]),
+            resolve_shorteners: None,
+        };
+        assert_eq!(
+            config
+                .clean("https://example.com/a?utm_source=x&id=1&fbclid=abc&si=q#top")
+                .as_deref(),
+            Some("https://example.com/a?id=1#top")
+        );
+        assert_eq!(
+            config
+                .clean("https://www.google.com/url?sa=t&url=https%3A%2F%2Fexample.com%2F%3Futm_medium%3Dx")
+                .as_deref(),
+            Some("https://example.com/")
+        );
+        assert_eq!(config.clean("https://example.com/?id=1"), None);
+        assert_eq!(config.clean("not a link"), None);
+        assert_eq!(host("https://user@t.co:443/abc"), Some("t.co"));
+    }
+}
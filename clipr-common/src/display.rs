@@ -0,0 +1,71 @@
+use clap::ValueEnum;
+use std::io::IsTerminal;
+
+// ANSI SGR sequences for list output
+pub const PIN: &str = "\x1b[1;33m";
+pub const TAGS: &str = "\x1b[36m";
+pub const DATE: &str = "\x1b[2m";
+pub const MATCH: &str = "\x1b[1;31m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum ColorChoice {
+    // only when stdout is terminal and NO_COLOR isn't set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            // https://no-color.org
+            ColorChoice::Auto => {
+                std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                    && std::io::stdout().is_terminal()
+            }
+        }
+    }
+}
+
+pub fn paint(s: &str, color: &str, enabled: bool) -> String {
+    if enabled && !s.trim().is_empty() {
+        format!("{color}{s}{RESET}")
+    } else {
+        s.to_string()
+    }
+}
+
+// paints byte ranges (sorted, non-overlapping, see ItemSummary::matches) with MATCH
+pub fn highlight(s: &str, matches: &[(usize, usize)], enabled: bool) -> String {
+    if !enabled || matches.is_empty() {
+        return s.to_string();
+    }
+    let mut painted = String::new();
+    let mut last = 0;
+    for &(start, end) in matches.iter().filter(|(_, end)| *end <= s.len()) {
+        painted.push_str(&s[last..start]);
+        painted.push_str(&paint(&s[start..end], MATCH, true));
+        last = end;
+    }
+    painted.push_str(&s[last..]);
+    painted
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_highlight() {
+        assert_eq!(
+            highlight("a-b-c", &[(1, 2), (3, 4)], true),
+            "a\x1b[1;31m-\x1b[0mb\x1b[1;31m-\x1b[0mc"
+        );
+        assert_eq!(highlight("a-b", &[(1, 2)], false), "a-b");
+        assert_eq!(paint("  ", PIN, true), "  ");
+    }
+}
@@ -1,22 +1,64 @@
 use anyhow::Result;
 use async_std::channel::{bounded, Sender};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
 use chrono::prelude::*;
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use display::{highlight, paint};
+use redact::Redactor;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::hash_map::DefaultHasher;
-use std::collections::{HashSet, LinkedList};
+use std::collections::{BTreeMap, HashMap, HashSet, LinkedList, VecDeque};
+use std::convert::Infallible;
+use std::fmt;
 use std::fs::File;
-use std::hash::{Hash, Hasher};
+use std::hash::Hash;
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
-use std::time::SystemTime;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock, RwLock};
+use std::time::{Duration, SystemTime};
+use ulid::Ulid;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+use xxhash_rust::xxh3::xxh3_64_with_seed;
+
+pub mod cleanurl;
+pub mod display;
+pub mod lock;
+pub mod mqtt;
+pub mod osc52;
+pub mod output;
+pub mod paths;
+pub mod plugin;
+pub mod range;
+pub mod redact;
+pub mod relay;
+pub mod rules;
+pub mod stats;
+pub mod template;
+pub mod transform;
+pub mod unfurl;
+pub mod version;
 
 pub const HEADER_LEN: usize = 8;
 
+pub enum MirrorUpdate {
+    // whole history, newest first
+    Snapshot(Vec<Item>),
+    Item(Box<Item>),
+}
+
 pub enum Request {
-    Sync(String),
+    // value and its kind (files are captured with their paths as value),
+    // pasteboard change count for captures of system pasteboard
+    Sync(String, ItemKind, Option<SourceApp>, Option<i64>),
     Command(Command, Sender<Response>),
+    // receives Response::NewItem for every captured item
+    Subscribe(Sender<Response>),
+    // history of primary daemon, see MirrorConfig
+    Mirror(MirrorUpdate),
     Quit,
 }
 
@@ -31,8 +73,10 @@ impl Request {
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "kebab-case")]
 pub enum Response {
-    NewItem(String),
+    NewItem(Box<Item>),
     Payload(Payload),
+    // command failed inside daemon
+    Error(String),
     Ok,
     Stop,
 }
@@ -42,217 +86,1121 @@ pub enum Response {
 pub enum Payload {
     Ok,
     List {
-        value: Vec<(usize, Item)>,
+        value: Vec<(usize, ItemSummary)>,
         preview_length: Option<usize>,
+        // requested by command, `display.template` otherwise
+        #[serde(default)]
+        template: Option<String>,
     },
     Value {
         value: Option<String>,
     },
+    // `get --stats`
+    Stats {
+        value: stats::Stats,
+    },
+    // `get-many`, None for missing items
+    Values {
+        value: Vec<Option<String>>,
+    },
     Message {
         // TODO: drop me?
         value: String,
     },
+    Status {
+        listen_on: String,
+        count: usize,
+        peers: Option<Vec<Peer>>,
+    },
+    NotFound {
+        target: IndexOrId,
+    },
+    // item at index isn't the one client expected (see `expected_hash`)
+    Conflict {
+        index: usize,
+        // actual hash, None if there is no item
+        hash: Option<u64>,
+    },
+    // groups of duplicates, kept item goes first
+    Groups {
+        value: Vec<Vec<(usize, Item)>>,
+    },
+    // newest day first
+    History {
+        value: Vec<Day>,
+    },
+    // (tag, items count) sorted by tag
+    Tags {
+        value: Vec<(String, usize)>,
+    },
+    // plugin owned payload
+    Plugin {
+        name: String,
+        value: serde_json::Value,
+    },
+    // command needs `unlock` first
+    Locked,
+    // `select --set` matched more than one item, nothing is set
+    Ambiguous {
+        value: Vec<(usize, ItemSummary)>,
+        preview_length: Option<usize>,
+    },
+    // command arguments are rejected, e.g. `select` without filter
+    InvalidArgs {
+        reason: String,
+    },
     Stop,
 }
 
-#[derive(Debug, Subcommand, Serialize, Deserialize)]
-#[serde(tag = "type", rename_all = "kebab-case")]
+// Items accessed during one calendar day (local time),
+// `items` are only filled for requested day.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Day {
+    pub day: NaiveDate,
+    pub count: usize,
+    pub items: Vec<(usize, ItemSummary)>,
+}
+
+// HTTP API error body, `error` is one of "invalid-request", "unauthorized",
+// "forbidden", "not-found", "too-large", "locked", "rate-limited",
+// "unavailable" or "internal"
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorBody {
+    pub error: String,
+    pub detail: String,
+}
+
+// Application that owned the pasteboard when value was captured.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SourceApp {
+    pub name: String,
+    pub bundle_id: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Peer {
+    pub name: String,
+    pub address: String,
+}
+
+// Item position (shifts on every copy) or its stable id.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum IndexOrId {
+    Index(usize),
+    // negative, counted from history end (-1 is the oldest item)
+    FromEnd(i64),
+    Id(String),
+}
+
+impl FromStr for IndexOrId {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match (s.parse(), s.parse()) {
+            (Ok(index), _) => Self::Index(index),
+            (_, Ok(index)) => Self::FromEnd(index),
+            _ => Self::Id(s.to_string()),
+        })
+    }
+}
+
+impl From<usize> for IndexOrId {
+    fn from(index: usize) -> Self {
+        Self::Index(index)
+    }
+}
+
+impl From<&str> for IndexOrId {
+    fn from(id: &str) -> Self {
+        Self::Id(id.to_string())
+    }
+}
+
+impl fmt::Display for IndexOrId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Index(index) => write!(f, "{index}"),
+            Self::FromEnd(index) => write!(f, "{index}"),
+            Self::Id(id) => write!(f, "{id}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Subcommand, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
 pub enum Command {
     Add {
         #[clap(last = true)]
         value: Vec<String>,
     },
     Del {
-        from_index: usize,
-        to_index: Option<usize>,
+        // index or range, see range::Position
+        #[clap(allow_hyphen_values = true)]
+        from_index: range::Position,
+        #[clap(allow_hyphen_values = true)]
+        to_index: Option<i64>,
+
+        // hash of item at from_index, command is rejected if it differs
+        #[clap(long)]
+        #[serde(default)]
+        expected_hash: Option<u64>,
+
+        // only list items that would be deleted
+        #[clap(long)]
+        #[serde(default)]
+        dry_run: bool,
     },
     List {
-        from_index: Option<usize>,
-        to_index: Option<usize>,
+        #[clap(allow_hyphen_values = true)]
+        from_index: Option<range::Position>,
+        #[clap(allow_hyphen_values = true)]
+        to_index: Option<i64>,
         preview_length: Option<usize>,
+
+        // only favorite items
+        #[clap(long)]
+        #[serde(default)]
+        favorites: bool,
+
+        // order by frecency (see Config::list_order)
+        #[clap(long)]
+        #[serde(default)]
+        ranked: bool,
+
+        // text output line, e.g. `{index}: {pin} {preview} [{tags}] {age}` (see template)
+        #[clap(long)]
+        #[serde(default)]
+        template: Option<String>,
+
+        // same as select, range applies to matching items then
+        #[clap(flatten)]
+        #[serde(flatten)]
+        filter: Filter,
     },
     Get {
-        index: usize,
+        #[serde(alias = "index")]
+        #[clap(allow_hyphen_values = true)]
+        target: IndexOrId,
+
+        // cli streams value as is (GET /items/:index/raw), daemon ignores it
+        #[clap(long)]
+        #[serde(default)]
+        raw: bool,
+
+        // counts (and guessed language) instead of value
+        #[clap(long)]
+        #[serde(default)]
+        stats: bool,
+    },
+    // values of several items in one call (e.g. for scripts), in order of
+    // targets, null for missing ones
+    GetMany {
+        #[clap(required = true)]
+        #[serde(alias = "indices")]
+        targets: Vec<IndexOrId>,
+    },
+    // value of newest item pinned to `pin` and/or tagged `tag`, without
+    // listing to find its index first
+    GetBy {
+        #[clap(long)]
+        #[serde(default)]
+        pin: Option<char>,
+
+        #[clap(long)]
+        #[serde(default)]
+        tag: Option<String>,
     },
     Set {
-        index: usize,
+        #[serde(alias = "index")]
+        #[clap(allow_hyphen_values = true)]
+        target: IndexOrId,
+        // "general" by default, see Config::pasteboards
+        #[clap(long)]
+        pasteboard: Option<String>,
+
+        // hash of item value, command is rejected if it differs
+        #[clap(long)]
+        #[serde(default)]
+        expected_hash: Option<u64>,
+
+        // converted value is put on pasteboard, history item stays as is
+        #[clap(long, value_enum)]
+        #[serde(default)]
+        transform: Option<transform::Transform>,
     },
+    // file is read by daemon unless its contents are sent along (see POST /insert)
     Insert {
         filename: String,
+        // guessed from filename extension if not set, text/* and image/* are supported
+        #[clap(long)]
+        #[serde(default)]
+        mime: Option<String>,
+        #[clap(skip)]
+        #[serde(skip)]
+        data: Option<Vec<u8>>,
     },
     Tag {
-        index: usize,
+        #[serde(alias = "index")]
+        #[clap(allow_hyphen_values = true)]
+        target: IndexOrId,
         tag: String,
     },
     Untag {
-        index: usize,
+        #[serde(alias = "index")]
+        #[clap(allow_hyphen_values = true)]
+        target: IndexOrId,
         tag: String,
     },
     Pin {
-        index: usize,
+        #[serde(alias = "index")]
+        #[clap(allow_hyphen_values = true)]
+        target: IndexOrId,
         pin: char,
     },
     Unpin {
-        index: usize,
+        #[serde(alias = "index")]
+        #[clap(allow_hyphen_values = true)]
+        target: IndexOrId,
+    },
+    // puts item pinned to `pin` on pasteboard, shortcut of
+    // `select --pin P --set` for hotkey daemons (cli skips version check)
+    SetPin {
+        pin: char,
+        #[clap(long)]
+        pasteboard: Option<String>,
     },
     Tags,
     Count,
+    Status {
+        #[clap(long)]
+        peers: bool,
+    },
     Save,
     Load,
     Select {
         #[clap(long)]
         set: bool,
 
-        // #[clap(last = true)]
-        // value: Vec<String>,
+        // select everything when no filter is given (rejected otherwise)
         #[clap(long)]
-        pin: Option<String>,
+        #[serde(default)]
+        all: bool,
 
-        #[clap(long, action = clap::ArgAction::Append)]
-        tag: Vec<String>,
+        #[clap(flatten)]
+        #[serde(flatten)]
+        filter: Filter,
+    },
+    // appends items matching filter (oldest first) to Markdown file on
+    // daemon host, e.g. daily note collecting today's research links
+    ExportNote {
+        // `.md` file, created if missing, `~` is expanded
+        path: String,
+
+        #[clap(flatten)]
+        #[serde(flatten)]
+        filter: Filter,
+
+        // per item, DEFAULT_NOTE_TEMPLATE by default, placeholders as in
+        // list template plus `{value}`
+        #[clap(long)]
+        #[serde(default)]
+        template: Option<String>,
+    },
+    // favorites survive expiration and range deletion
+    Fav {
+        #[serde(alias = "index")]
+        #[clap(allow_hyphen_values = true)]
+        target: IndexOrId,
+    },
+    Unfav {
+        #[serde(alias = "index")]
+        #[clap(allow_hyphen_values = true)]
+        target: IndexOrId,
+    },
+    // delete items matching selector (favorites are kept)
+    Prune {
+        #[clap(flatten)]
+        #[serde(flatten)]
+        selector: Filter,
 
+        // only list items that would be deleted
         #[clap(long)]
-        value: Option<String>,
+        #[serde(default)]
+        dry_run: bool,
+    },
+    // items grouped by day of last access, only per-day counts without `--day`
+    History {
+        // YYYY-MM-DD, local time
+        #[clap(long)]
+        #[serde(default)]
+        day: Option<NaiveDate>,
+    },
+    // recompute hash index from values, merging exact duplicates
+    Rebuild,
+    // items removed by `del`, newest first
+    Trash,
+    // bring item back from trash
+    Restore {
+        // index in `trash` listing or item id
+        #[serde(alias = "id")]
+        #[clap(allow_hyphen_values = true)]
+        target: IndexOrId,
+    },
+    // merge items differing only in whitespace/line endings
+    Dedup {
+        // only report duplicate groups
+        #[clap(long)]
+        #[serde(default)]
+        dry_run: bool,
     },
+    // wipe whole history
+    Clear {
+        // keep pinned and favorite items
+        #[clap(long)]
+        #[serde(default)]
+        keep_pinned: bool,
+
+        // clear system pasteboard too
+        #[clap(long)]
+        #[serde(default)]
+        system: bool,
+    },
+    // attach note to item (empty text removes it)
+    Note {
+        #[serde(alias = "index")]
+        #[clap(allow_hyphen_values = true)]
+        target: IndexOrId,
+        text: Vec<String>,
+    },
+    // most frequently copied (and pinned) items
+    Ring,
+    // items copied during session (seconds, 15 minutes by default) are
+    // kept only in memory and dropped when it ends
+    Incognito {
+        duration: Option<u64>,
+        // end session right away
+        #[clap(long)]
+        stop: bool,
+    },
+    // items are put on pasteboard one by one (e.g. to fill form fields),
+    // first right away, then each `queue --next` (bind it to a hotkey, macOS
+    // doesn't tell when pasteboard is pasted from); lists what's left
+    // without arguments
+    Queue {
+        #[serde(default, alias = "indices")]
+        targets: Vec<IndexOrId>,
+        // put next queued item on pasteboard
+        #[clap(long)]
+        #[serde(default)]
+        next: bool,
+        // drop the rest of queue
+        #[clap(long)]
+        #[serde(default)]
+        stop: bool,
+    },
+    // copied text is joined into one item (separated by newline by
+    // default) until stopped, e.g. to collect quotes from several sources
+    AppendMode {
+        separator: Option<String>,
+        // stop joining, joined item stays in history
+        #[clap(long)]
+        #[serde(default)]
+        stop: bool,
+    },
+    // required first when `[lock]` is configured, expires after its timeout
+    Unlock {
+        passphrase: String,
+    },
+    // lock right away
+    Lock,
+    // run command provided by daemon plugin
+    Plugin {
+        name: String,
+        #[clap(allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    // list loaded plugins
+    Plugins,
     Help,
     Quit,
 }
 
-pub fn format_item(item: &Item, short: bool, preview_length: Option<usize>) -> String {
-    let val = if short {
-        shorten(&item.value, preview_length)
-    } else {
-        item.value.clone()
-    };
+// Read-only commands don't touch entries or pasteboard.
+impl Command {
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Command::List { .. }
+                | Command::Get { .. }
+                | Command::GetMany { .. }
+                | Command::GetBy { .. }
+                | Command::Count
+                | Command::History { .. }
+                | Command::Select { set: false, .. }
+        )
+    }
 
-    let tags = match &item.tags {
-        Some(tags) => {
-            let mut ts = tags.iter().map(|v| v.as_str()).collect::<Vec<&str>>();
-            ts.sort();
-            ts.join(",")
+    // allowed for `read` http tokens, see HttpConfig::tokens
+    pub fn is_read_access(&self) -> bool {
+        self.is_read_only()
+            || matches!(
+                self,
+                Command::Tags
+                    | Command::Status { .. }
+                    | Command::Ring
+                    | Command::Plugins
+                    | Command::Help
+            )
+    }
+
+    // everything else is rejected by read-only mirror (see MirrorConfig)
+    pub fn is_allowed_mirror(&self) -> bool {
+        self.is_read_access()
+            || matches!(
+                self,
+                Command::Save
+                    | Command::ExportNote { .. }
+                    | Command::Lock
+                    | Command::Unlock { .. }
+                    | Command::Quit
+            )
+    }
+
+    // answered while daemon is locked (see lock::LockConfig)
+    pub fn is_allowed_locked(&self) -> bool {
+        matches!(
+            self,
+            Command::Unlock { .. } | Command::Lock | Command::Status { .. } | Command::Help
+        )
+    }
+}
+
+#[derive(Clone, Debug, Default, clap::Args, Serialize, Deserialize)]
+pub struct Filter {
+    // #[clap(last = true)]
+    // value: Vec<String>,
+    // any of given pins, `--pin 123` matches items pinned 1, 2 or 3
+    #[clap(long)]
+    pub pin: Option<String>,
+
+    #[clap(long, action = clap::ArgAction::Append)]
+    #[serde(default)]
+    pub tag: Vec<String>,
+
+    #[clap(long)]
+    pub value: Option<String>,
+
+    // source application name (case insensitive)
+    #[clap(long)]
+    pub app: Option<String>,
+
+    // note substring (case insensitive)
+    #[clap(long)]
+    pub note: Option<String>,
+
+    // page title substring (case insensitive), see `unfurl`
+    #[clap(long)]
+    #[serde(default)]
+    pub title: Option<String>,
+
+    #[clap(long)]
+    pub regex: Option<String>,
+
+    // seconds since last access, `90s`, `30m`, `12h` and `7d` are accepted too
+    #[clap(long, value_parser = parse_age)]
+    pub older_than: Option<u64>,
+
+    // accessed within that many seconds (same formats as older_than)
+    #[clap(long, value_parser = parse_age)]
+    #[serde(default)]
+    pub newer_than: Option<u64>,
+
+    #[clap(long, value_enum)]
+    #[serde(default)]
+    pub kind: Option<KindFilter>,
+}
+
+// `--kind` of filter, url is text item holding a single link
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KindFilter {
+    Text,
+    Url,
+    Files,
+    Image,
+}
+
+impl KindFilter {
+    pub fn matches(self, item: &Item) -> bool {
+        match (self, &item.kind) {
+            (KindFilter::Text, ItemKind::Text) => true,
+            (KindFilter::Url, ItemKind::Text) => unfurl::is_url(&item.value),
+            (KindFilter::Files, ItemKind::Files(_)) => true,
+            (KindFilter::Image, ItemKind::Image { .. }) => true,
+            _ => false,
         }
-        None => "".to_string(),
+    }
+}
+
+pub fn parse_age(s: &str) -> Result<u64, String> {
+    let (value, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let value: u64 = value.parse().map_err(|_| format!("invalid age: {s:?}"))?;
+    let secs = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("invalid age unit: {unit:?}")),
     };
+    Ok(value * secs)
+}
+
+impl Filter {
+    pub fn is_empty(&self) -> bool {
+        self.pin.is_none()
+            && self.tag.is_empty()
+            && self.value.is_none()
+            && self.app.is_none()
+            && self.note.is_none()
+            && self.title.is_none()
+            && self.regex.is_none()
+            && self.older_than.is_none()
+            && self.newer_than.is_none()
+            && self.kind.is_none()
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if let Some(regex) = &self.regex {
+            Regex::new(regex)?;
+        }
+        Ok(())
+    }
+
+    // sorted, merged byte ranges of value and regex matches in text
+    pub fn match_offsets(&self, text: &str) -> Vec<(usize, usize)> {
+        let mut offsets = vec![];
+        if let Some(value) = self.value.as_deref().filter(|value| !value.is_empty()) {
+            offsets.extend(
+                text.match_indices(value)
+                    .map(|(start, m)| (start, start + m.len())),
+            );
+        }
+        if let Some(regex) = self.regex.as_deref().and_then(|re| Regex::new(re).ok()) {
+            offsets.extend(
+                regex
+                    .find_iter(text)
+                    .filter(|m| !m.is_empty())
+                    .map(|m| (m.start(), m.end())),
+            );
+        }
+        offsets.sort();
+        offsets
+            .into_iter()
+            .fold(vec![], |mut merged, (start, end)| {
+                match merged.last_mut() {
+                    Some((_, last)) if start <= *last => *last = end.max(*last),
+                    _ => merged.push((start, end)),
+                }
+                merged
+            })
+    }
+
+    pub fn mark_matches(&self, items: &mut [(usize, ItemSummary)]) {
+        for (_, item) in items.iter_mut() {
+            item.matches = self.match_offsets(&item.preview);
+        }
+    }
 
-    let dt: DateTime<Local> = item.accessed_at.into();
-    let max_len = preview_length.unwrap_or(MAX_LEN);
+    // pins are stored in upper case
+    pub fn pin_chars(&self) -> Option<HashSet<char>> {
+        self.pin
+            .as_ref()
+            .map(|s| s.to_uppercase().chars().collect())
+    }
+}
 
-    format!(
-        "[{:1}] {:<max_len$} #[{:<16}] @[{:<10}] ",
-        item.pin.unwrap_or(' '),
-        val,
-        tags,
-        dt.format("%d-%m-%Y")
-    )
+pub fn format_item(
+    item: &Item,
+    short: bool,
+    preview_length: Option<usize>,
+    display: &DisplayConfig,
+) -> String {
+    let preview_length = preview_length.or(display.preview_length);
+    let item = display.redactor().item(item);
+    let mut summary = ItemSummary::new(&item, 0, preview_length);
+    if !short {
+        summary.preview = item.value.clone();
+    }
+    format_summary(&summary, short, preview_length, display)
 }
 
-fn _has_newlines(s: &str) -> Option<usize> {
-    s.as_bytes()
+pub fn format_summary(
+    item: &ItemSummary,
+    short: bool,
+    preview_length: Option<usize>,
+    display: &DisplayConfig,
+) -> String {
+    let max_len = preview_length.or(display.preview_length).unwrap_or(MAX_LEN);
+    let color = display.color;
+
+    display
+        .columns()
         .iter()
-        .enumerate()
-        .find(|&(_, c)| *c == b'\n')
-        .map(|(i, _)| i)
+        .map(|column| match column {
+            // `~` marks incognito items
+            Column::Flags => format!(
+                "{}[{}]",
+                match (item.favorite, item.ephemeral) {
+                    (true, _) => '*',
+                    (_, true) => '~',
+                    _ => ' ',
+                },
+                paint(&item.pin.unwrap_or(' ').to_string(), display::PIN, color)
+            ),
+            // padded first, so escapes don't break alignment
+            Column::Preview => highlight(&pad(&item.preview, max_len), &item.matches, color),
+            Column::Tags => {
+                let mut tags = item.tags.iter().flatten().cloned().collect::<Vec<String>>();
+                tags.sort();
+                let column = format!("#[{:<16}]", tags.join(","));
+                paint(&column, display::TAGS, color && !tags.is_empty())
+            }
+            Column::Date => {
+                let dt: DateTime<Local> = item.accessed_at.into();
+                let column = format!("@[{:<10}]", dt.format(display.date_format()));
+                paint(&column, display::DATE, color)
+            }
+            Column::App => item.source_app.clone().unwrap_or_default(),
+            Column::Title => match &item.title {
+                Some(title) if short => format!("({})", shorten(title, Some(NOTE_PREVIEW_LEN))),
+                Some(title) => format!("({title})"),
+                None => "".to_string(),
+            },
+            Column::Note => match &item.note {
+                Some(note) if short => format!("// {}", shorten(note, Some(NOTE_PREVIEW_LEN))),
+                Some(note) => format!("// {note}"),
+                None => "".to_string(),
+            },
+        })
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<String>>()
+        .join(" ")
 }
 
 pub const MAX_LEN: usize = 64;
-const SPACER_LEN: usize = 4;
+pub const NOTE_PREVIEW_LEN: usize = 32;
+const SPACER: &str = "...";
 const PREFIX_LEN: usize = 16;
 
+// Single line preview: long values keep PREFIX_LEN columns from both ends,
+// everything after first line break is replaced with spacer.
+// Widths are display columns (CJK and emoji take two), values are only cut
+// between grapheme clusters. Result is never wider than max_len (spacer is
+// cut too when it doesn't fit) and shortening it again doesn't change it.
 pub fn shorten(s: &str, max_len: Option<usize>) -> String {
-    let chars = s.chars();
-    let length = s.chars().count();
     let max_len = max_len.unwrap_or(MAX_LEN);
-
-    // TODO:
-    // 0. if has length > MAX_LEN -> S[0...PREFIX_LEN]...S[-PREFIX_LEN...]
-    // 1. if has whitespaces until prefix-len -> S[0...PREFIX_LEN]...
-    // 2. if has whitespaces after spacer -> S[0...PREFIX_LEN]...
-
-    let mut short = if length > max_len {
-        chars.enumerate().fold(String::new(), |acc, (i, c)| {
-            if i < PREFIX_LEN || i > length - PREFIX_LEN {
-                format!("{acc}{c}")
-            } else if i > PREFIX_LEN && i < (PREFIX_LEN + SPACER_LEN) {
-                format!("{acc}.")
-            } else {
-                acc
-            }
-        })
-    } else {
-        chars.collect::<String>()
+    let spacer = &SPACER[..SPACER.len().min(max_len)];
+    let graphemes = s.graphemes(true).collect::<Vec<&str>>();
+    let (line, rest) = match graphemes.iter().position(|g| g.contains(['\n', '\r'])) {
+        Some(newline) => graphemes.split_at(newline),
+        None => (graphemes.as_slice(), &[][..]),
     };
 
-    let newline_offset = _has_newlines(short.as_str()).unwrap_or(short.len());
-    let rest = short.split_off(newline_offset);
-    if rest.chars().any(|c| !c.is_whitespace()) {
-        format!("{short}...")
-    } else {
-        short.to_string()
+    // trailing blank lines are just dropped
+    if rest.iter().any(|g| !g.trim().is_empty()) {
+        let head = take_width(line.iter().copied(), max_len - spacer.len());
+        return format!("{}{spacer}", head.concat());
+    }
+    if line.iter().map(|g| g.width()).sum::<usize>() <= max_len {
+        return line.concat();
     }
+    let side = PREFIX_LEN.min((max_len - spacer.len()) / 2);
+    let head = take_width(line.iter().copied(), side);
+    let mut tail = take_width(line.iter().rev().copied(), side);
+    tail.reverse();
+    format!("{}{spacer}{}", head.concat(), tail.concat())
+}
+
+fn take_width<'a>(graphemes: impl Iterator<Item = &'a str>, width: usize) -> Vec<&'a str> {
+    let mut taken = 0;
+    graphemes
+        .take_while(|g| {
+            taken += g.width();
+            taken <= width
+        })
+        .collect()
+}
+
+// pads to display width, unlike `{:<width$}` which counts chars
+pub fn pad(s: &str, width: usize) -> String {
+    format!("{s}{}", " ".repeat(width.saturating_sub(s.width())))
+}
+
+fn format_list(
+    value: &[(usize, ItemSummary)],
+    preview_length: Option<usize>,
+    template: Option<&str>,
+    display: &DisplayConfig,
+) -> String {
+    let places = value.len().to_string().len();
+    value
+        .iter()
+        .map(|(index, val)| match template {
+            Some(template) => template::render(template, *index, val, display),
+            None => format!(
+                "{:>places$}: {}",
+                index,
+                format_summary(val, true, preview_length, display)
+            ),
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
 }
 
 impl From<&Payload> for String {
     fn from(payload: &Payload) -> Self {
-        match payload {
+        payload.format(&DisplayConfig::default())
+    }
+}
+
+impl Payload {
+    // text output, see `display` config
+    pub fn format(&self, display: &DisplayConfig) -> String {
+        match self {
             Payload::Ok => "ok".to_string(),
             Payload::Stop => "stop".to_string(),
+            Payload::Locked => "locked, run `unlock <passphrase>` first".to_string(),
+            Payload::InvalidArgs { reason } => format!("invalid args: {reason}"),
             Payload::List {
                 value,
                 preview_length,
-            } => {
-                let places = value.len().to_string().len();
-                value
-                    .iter()
-                    .map(|(index, val)| {
-                        format!(
-                            "{:>places$}: {}",
-                            index,
-                            format_item(val, true, *preview_length)
-                        )
-                    })
-                    .collect::<Vec<String>>()
-                    .join("\n")
-            }
+                template,
+            } => format_list(
+                value,
+                *preview_length,
+                template.as_deref().or(display.template.as_deref()),
+                display,
+            ),
+            Payload::Ambiguous {
+                value,
+                preview_length,
+            } => format!(
+                "{} items match, narrow filter down or set one by index:\n{}",
+                value.len(),
+                format_list(value, *preview_length, None, display)
+            ),
             Payload::Value { value } => match value {
                 Some(v) => v.to_owned(),
                 _ => "".to_string(),
             },
+            Payload::Stats { value } => value.to_string(),
+            Payload::Values { value } => value
+                .iter()
+                .map(|v| v.as_deref().unwrap_or_default())
+                .collect::<Vec<&str>>()
+                .join("\n"),
             Payload::Message { value } => value.to_string(),
+            Payload::Status {
+                listen_on,
+                count,
+                peers,
+            } => {
+                let mut lines = vec![format!("listen on: {listen_on}"), format!("items: {count}")];
+                if let Some(peers) = peers {
+                    lines.push(format!("peers: {}", peers.len()));
+                    lines.extend(
+                        peers
+                            .iter()
+                            .map(|peer| format!("  {} ({})", peer.name, peer.address)),
+                    );
+                }
+                lines.join("\n")
+            }
+            Payload::NotFound { target } => format!("item at {target} not found"),
+            Payload::Conflict { index, .. } => format!("item at {index} changed"),
+            Payload::Groups { value } => value
+                .iter()
+                .map(|group| {
+                    group
+                        .iter()
+                        .enumerate()
+                        .map(|(i, (index, item))| {
+                            format!(
+                                "{} {}: {}",
+                                if i == 0 { "keep" } else { "drop" },
+                                index,
+                                format_item(item, true, None, display)
+                            )
+                        })
+                        .collect::<Vec<String>>()
+                        .join("\n")
+                })
+                .collect::<Vec<String>>()
+                .join("\n\n"),
+            Payload::History { value } => value
+                .iter()
+                .flat_map(|day| {
+                    let places = day.items.len().to_string().len();
+                    std::iter::once(format!("{}: {}", day.day, day.count)).chain(
+                        day.items.iter().map(move |(index, item)| {
+                            format!(
+                                "  {:>places$}: {}",
+                                index,
+                                format_summary(item, true, None, display)
+                            )
+                        }),
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join("\n"),
+            Payload::Tags { value } => value
+                .iter()
+                .map(|(tag, _)| tag.as_str())
+                .collect::<Vec<&str>>()
+                .join(":"),
+            Payload::Plugin { value, .. } => match value {
+                serde_json::Value::String(s) => s.to_owned(),
+                other => serde_json::to_string_pretty(other).unwrap_or_default(),
+            },
         }
     }
 }
 
-pub fn calculate_hash<T: Hash>(v: &T) -> u64 {
-    let mut h = DefaultHasher::new();
-    v.hash(&mut h);
-    h.finish()
+// collapses whitespace runs (incl. line endings) into single space
+pub fn normalize(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+// Stable content hash (same across processes, platforms and Rust versions),
+// it's persisted with items and compared by clients (see expected_hash).
+pub fn calculate_hash(value: &str) -> u64 {
+    xxh3_64_with_seed(value.as_bytes(), HASH_SEED)
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Item {
+    // stable across inserts, unlike index
+    #[serde(default = "new_id")]
+    pub id: String,
     pub value: String,
     pub access_counter: u32,
     pub accessed_at: SystemTime,
     pub tags: Option<HashSet<String>>,
     pub pin: Option<char>,
+    pub source_app: Option<String>,
+    pub expires_at: Option<SystemTime>,
+    pub note: Option<String>,
+    #[serde(default)]
+    pub favorite: bool,
+    #[serde(default)]
+    pub kind: ItemKind,
+    // page title of copied link (see `unfurl`)
+    #[serde(default)]
+    pub title: Option<String>,
+    // copied link before cleanup (see `clean_urls`)
+    #[serde(default)]
+    pub original_url: Option<String>,
+    // copied in incognito session, never saved (see Entries::persistent)
+    #[serde(default)]
+    pub ephemeral: bool,
+    // value is a placeholder, real one is in system keychain under id
+    #[serde(default)]
+    pub keychain: bool,
+    // value is a placeholder, real one is in spill dir (see MemoryConfig)
+    #[serde(default)]
+    pub spilled: bool,
+    // times it was put on pasteboard by `set` (its capture isn't counted)
+    #[serde(default)]
+    pub set_count: u32,
+    // calculate_hash of value, real one for keychain items
+    #[serde(default)]
+    pub hash: u64,
+    // frecency as of accessed_at (see Item::frecency)
+    #[serde(default)]
+    pub score: Option<f64>,
+    // pasteboard changeCount of its last capture, None for relayed,
+    // imported and such
+    #[serde(default)]
+    pub change_count: Option<i64>,
+}
+
+pub fn new_id() -> String {
+    Ulid::new().to_string()
+}
+
+// what was on pasteboard, value of Files item is newline separated paths
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ItemKind {
+    #[default]
+    Text,
+    Files(Vec<PathBuf>),
+    // value is base64 encoded image data
+    Image {
+        mime: String,
+    },
+}
+
+pub fn files_value(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| path.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn image_value(data: &[u8]) -> String {
+    STANDARD.encode(data)
+}
+
+pub fn image_data(value: &str) -> Result<Vec<u8>> {
+    Ok(STANDARD.decode(value)?)
+}
+
+// by filename extension, anything unknown is treated as text
+pub fn guess_mime(filename: &str) -> &'static str {
+    let extension = Path::new(filename)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+    match extension.as_deref() {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("tif" | "tiff") => "image/tiff",
+        Some("bmp") => "image/bmp",
+        _ => "text/plain",
+    }
 }
 
 impl Item {
     pub fn new(value: String) -> Self {
+        let hash = calculate_hash(&value);
         Self {
+            id: new_id(),
             value,
             access_counter: 1,
             accessed_at: SystemTime::now(),
             tags: None,
             pin: None,
+            source_app: None,
+            expires_at: None,
+            note: None,
+            favorite: false,
+            kind: ItemKind::Text,
+            title: None,
+            original_url: None,
+            ephemeral: false,
+            keychain: false,
+            spilled: false,
+            set_count: 0,
+            score: Some(1.0),
+            hash,
+            change_count: None,
         }
     }
 
     pub fn touch(&mut self) {
-        self.accessed_at = SystemTime::now();
+        let now = SystemTime::now();
+        self.score = Some(self.frecency(now) + 1.0);
+        self.accessed_at = now;
         self.access_counter += 1;
     }
+
+    // access count decayed by age (halved every FRECENCY_HALF_LIFE), old
+    // items without score start from their access_counter
+    pub fn frecency(&self, now: SystemTime) -> f64 {
+        let age = now.duration_since(self.accessed_at).unwrap_or_default();
+        let score = self.score.unwrap_or(self.access_counter as f64);
+        score * 0.5f64.powf(age.as_secs_f64() / FRECENCY_HALF_LIFE.as_secs_f64())
+    }
+
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
+}
+
+// Item without full value (which may be megabytes long), used by list payloads.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ItemSummary {
+    pub id: String,
+    // single line, see shorten
+    pub preview: String,
+    // hash of full value (see expected_hash)
+    pub hash: u64,
+    pub access_counter: u32,
+    pub accessed_at: SystemTime,
+    pub tags: Option<HashSet<String>>,
+    pub pin: Option<char>,
+    pub source_app: Option<String>,
+    pub expires_at: Option<SystemTime>,
+    pub note: Option<String>,
+    pub favorite: bool,
+    #[serde(default)]
+    pub kind: ItemKind,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub ephemeral: bool,
+    #[serde(default)]
+    pub set_count: u32,
+    #[serde(default)]
+    pub change_count: Option<i64>,
+    // guessed for code snippets (see stats::detect_language)
+    #[serde(default)]
+    pub language: Option<String>,
+    // byte ranges [start, end) of select value/regex matches in preview
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub matches: Vec<(usize, usize)>,
+}
+
+impl ItemSummary {
+    pub fn new(item: &Item, hash: u64, preview_length: Option<usize>) -> Self {
+        let preview = match &item.kind {
+            ItemKind::Text => shorten(&item.value, preview_length),
+            ItemKind::Files(_) => shorten(
+                &format!("[files] {}", item.value.replace('\n', " ")),
+                preview_length,
+            ),
+            ItemKind::Image { mime } => format!("[{mime}]"),
+        };
+        Self {
+            id: item.id.clone(),
+            preview,
+            hash,
+            access_counter: item.access_counter,
+            accessed_at: item.accessed_at,
+            tags: item.tags.clone(),
+            pin: item.pin,
+            source_app: item.source_app.clone(),
+            expires_at: item.expires_at,
+            note: item.note.clone(),
+            favorite: item.favorite,
+            kind: item.kind.clone(),
+            title: item.title.clone(),
+            ephemeral: item.ephemeral,
+            set_count: item.set_count,
+            change_count: item.change_count,
+            language: (item.kind == ItemKind::Text)
+                .then(|| stats::detect_language(&item.value))
+                .flatten()
+                .map(String::from),
+            matches: vec![],
+        }
+    }
+}
+
+pub fn summarize(
+    items: &[(usize, Item)],
+    preview_length: Option<usize>,
+) -> Vec<(usize, ItemSummary)> {
+    items
+        .iter()
+        .map(|(index, item)| (*index, ItemSummary::new(item, item.hash, preview_length)))
+        .collect()
 }
 
 impl From<String> for Item {
@@ -264,7 +1212,33 @@ impl From<String> for Item {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Entries {
     pub values: LinkedList<Item>,
+    // calculate_hash of values, dedups inserts; saved along and checked on
+    // load, so reloaded history dedups next copy of existing value
     pub hashes: LinkedList<u64>,
+    // older dbs are migrated on load (see is_consistent)
+    #[serde(default)]
+    pub version: u32,
+    // items removed by `del`, newest first (see Config::trash_days)
+    #[serde(default)]
+    pub trash: Vec<Trashed>,
+    #[serde(skip)]
+    changes: Changes,
+}
+
+// What changed since last Entries::take_changes (see wal in clipr-core):
+// ids of items changed in place or added (trashed ones included) and whether
+// order of history or trash changed.
+#[derive(Debug, Default)]
+pub struct Changes {
+    pub items: HashSet<String>,
+    pub reordered: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Trashed {
+    pub item: Item,
+    pub deleted_at: SystemTime,
 }
 
 impl Default for Entries {
@@ -273,13 +1247,6 @@ impl Default for Entries {
     }
 }
 
-fn _drop_list_values<T>(from_index: usize, to_index: Option<usize>, list: &mut LinkedList<T>) {
-    let to_index = to_index.unwrap_or(from_index + 1);
-    let mut upper = list.split_off(from_index);
-    let mut rest = upper.split_off(to_index - from_index);
-    list.append(&mut rest);
-}
-
 fn _find_list_element<T>(value: &T, list: &LinkedList<T>) -> Option<usize>
 where
     T: PartialEq<T>,
@@ -295,14 +1262,44 @@ impl Entries {
         Entries {
             values: LinkedList::new(),
             hashes: LinkedList::new(),
+            version: ENTRIES_VERSION,
+            trash: vec![],
+            changes: Changes::default(),
         }
     }
 
+    // for changes made through pub fields (see Changes)
+    pub fn mark(&mut self, id: &str) {
+        self.changes.items.insert(String::from(id));
+    }
+
+    pub fn take_changes(&mut self) -> Changes {
+        std::mem::take(&mut self.changes)
+    }
+
     // INFO: values + hashes should be consistent. in the name of DOD ;)
-    pub fn insert(&mut self, value: String) {
+    // returns inserted (or touched) item, it's always in front
+    pub fn insert(&mut self, value: String) -> &mut Item {
+        self.insert_dedup(value, DedupMode::Global)
+    }
+
+    // same value already in history is touched instead of inserted,
+    // unless mode says otherwise (see Config::dedup)
+    pub fn insert_dedup(&mut self, value: String, mode: DedupMode) -> &mut Item {
         let hash = calculate_hash(&value);
+        let duplicate = match mode {
+            DedupMode::Global => _find_list_element(&hash, &self.hashes),
+            DedupMode::Window(window) => {
+                let since = SystemTime::now() - window;
+                self.values
+                    .iter()
+                    .zip(&self.hashes)
+                    .position(|(item, item_hash)| *item_hash == hash && item.accessed_at >= since)
+            }
+            DedupMode::Off => None,
+        };
 
-        if let Some(index) = _find_list_element(&hash, &self.hashes) {
+        if let Some(index) = duplicate {
             let mut values_tail = self.values.split_off(index);
             if let Some(mut elt) = values_tail.pop_front() {
                 elt.touch();
@@ -319,61 +1316,195 @@ impl Entries {
             self.hashes.push_front(hash);
             self.values.push_front(value.into());
         }
+
+        self.changes.reordered = true;
+        let item = self.values.front_mut().unwrap();
+        self.changes.items.insert(item.id.clone());
+        item
     }
 
+    // ranges keep favorite items, single item is removed anyway
+    // removed items go to trash (incognito ones are dropped)
     pub fn delete(&mut self, from_index: usize, to_index: Option<usize>) {
-        _drop_list_values(from_index, to_index, &mut self.values);
-        _drop_list_values(from_index, to_index, &mut self.hashes);
+        let deleted_at = SystemTime::now();
+        self.changes.reordered = true;
+        let values = std::mem::take(&mut self.values);
+        let hashes = std::mem::take(&mut self.hashes);
+        for (index, (item, hash)) in values.into_iter().zip(hashes).enumerate() {
+            let removed = match to_index {
+                Some(to_index) => !item.favorite && (from_index..to_index).contains(&index),
+                None => index == from_index,
+            };
+            if !removed {
+                self.values.push_back(item);
+                self.hashes.push_back(hash);
+            } else if !item.ephemeral {
+                self.changes.items.insert(item.id.clone());
+                self.trash.insert(0, Trashed { item, deleted_at });
+            }
+        }
     }
 
-    pub fn get(&mut self, index: usize) -> Option<&mut Item> {
-        self.values
-            .iter_mut()
-            .enumerate()
-            .find(|(i, _)| index == *i)
-            .map(|(_, item)| item)
+    pub fn trash_index(&self, target: &IndexOrId) -> Option<usize> {
+        match target {
+            IndexOrId::Index(index) => (*index < self.trash.len()).then_some(*index),
+            IndexOrId::FromEnd(index) => range::resolve(*index, self.trash.len()),
+            IndexOrId::Id(id) => self.trash.iter().position(|t| &t.item.id == id),
+        }
     }
 
-    pub fn get_value(&mut self, index: usize) -> Option<String> {
-        self.get(index).map(|item| item.value.clone())
+    // item copied from primary daemon goes to top as is (replacing item with
+    // the same id, primary moves touched items to top too)
+    pub fn mirror(&mut self, item: Item) {
+        self.retain(|_, i| i.id != item.id);
+        self.changes.reordered = true;
+        self.changes.items.insert(item.id.clone());
+        self.hashes.push_front(item.hash);
+        self.values.push_front(item);
     }
 
-    pub fn select_by_range(
-        &self,
-        from_index: Option<usize>,
-        to_index: Option<usize>,
-    ) -> Vec<(usize, Item)> {
-        let from_index = from_index.unwrap_or(0);
-        let to_index = to_index.unwrap_or(self.values.len());
+    // Moves item from trash to history front, or to its copy already in
+    // history. Returns its history index.
+    pub fn restore(&mut self, trash_index: usize) -> usize {
+        let Trashed { item, .. } = self.trash.remove(trash_index);
+        self.changes.reordered = true;
+        if let Some(index) = _find_list_element(&item.hash, &self.hashes) {
+            return index;
+        }
+        self.changes.items.insert(item.id.clone());
+        self.hashes.push_front(item.hash);
+        self.values.push_front(item);
+        0
+    }
 
-        self.values
-            .iter()
+    // returns number of purged items
+    pub fn purge_trash(&mut self, deleted_before: SystemTime) -> usize {
+        let len = self.trash.len();
+        self.trash.retain(|t| t.deleted_at >= deleted_before);
+        self.changes.reordered |= len != self.trash.len();
+        len - self.trash.len()
+    }
+
+    // item is taken as changed (see Changes)
+    pub fn get(&mut self, index: usize) -> Option<&mut Item> {
+        let item = self.values.iter_mut().nth(index)?;
+        self.changes.items.insert(item.id.clone());
+        Some(item)
+    }
+
+    pub fn get_item(&self, index: usize) -> Option<&Item> {
+        self.values.iter().nth(index)
+    }
+
+    pub fn get_value(&self, index: usize) -> Option<String> {
+        self.get_item(index).map(|item| item.value.clone())
+    }
+
+    pub fn hash_at(&self, index: usize) -> Option<u64> {
+        self.hashes.iter().nth(index).copied()
+    }
+
+    // current index of item, None if there is no such item
+    pub fn resolve(&self, target: &IndexOrId) -> Option<usize> {
+        match target {
+            IndexOrId::Index(index) => Some(*index).filter(|index| *index < self.values.len()),
+            IndexOrId::FromEnd(index) => range::resolve(*index, self.values.len()),
+            IndexOrId::Id(id) => self.values.iter().position(|item| &item.id == id),
+        }
+    }
+
+    // values are never cloned, only their previews
+    pub fn select_by_range(
+        &self,
+        from_index: Option<usize>,
+        to_index: Option<usize>,
+        preview_length: Option<usize>,
+    ) -> Vec<(usize, ItemSummary)> {
+        let from_index = from_index.unwrap_or(0);
+        let to_index = to_index.unwrap_or(self.values.len());
+
+        self.values
+            .iter()
+            .zip(self.hashes.iter())
             .enumerate()
-            .filter(|(index, _item)| *index >= from_index && *index < to_index)
-            .map(|(index, item)| (index, item.clone()))
+            .skip(from_index)
+            .take(to_index.saturating_sub(from_index))
+            .map(|(index, (item, hash))| (index, ItemSummary::new(item, *hash, preview_length)))
             .collect()
     }
 
-    pub fn select(
+    // like select_by_range, but range is taken from items ordered by
+    // frecency (indices are still history positions)
+    pub fn select_ranked(
         &self,
-        pin: Option<char>,
-        tag: Vec<String>,
-        value: Option<String>,
-    ) -> Vec<(usize, Item)> {
-        // return ALL or NONE?
-        if pin.is_none() && tag.is_empty() && value.is_none() {
-            return vec![];
-        };
+        from_index: Option<usize>,
+        to_index: Option<usize>,
+        preview_length: Option<usize>,
+    ) -> Vec<(usize, ItemSummary)> {
+        let from_index = from_index.unwrap_or(0);
+        let to_index = to_index.unwrap_or(self.values.len());
 
-        if let Some(c) = pin {
-            return self.select_by_pin(c);
+        let mut items: Vec<(usize, (&Item, &u64))> = self
+            .values
+            .iter()
+            .zip(self.hashes.iter())
+            .enumerate()
+            .collect();
+        let now = SystemTime::now();
+        items.sort_by(|(_, (a, _)), (_, (b, _))| b.frecency(now).total_cmp(&a.frecency(now)));
+        items
+            .into_iter()
+            .skip(from_index)
+            .take(to_index.saturating_sub(from_index))
+            .map(|(index, (item, hash))| (index, ItemSummary::new(item, *hash, preview_length)))
+            .collect()
+    }
+
+    // newest day first, items are listed only when day is requested
+    pub fn history(&self, day: Option<NaiveDate>, preview_length: Option<usize>) -> Vec<Day> {
+        let mut days: BTreeMap<NaiveDate, Day> = BTreeMap::new();
+        for (index, (item, hash)) in self.values.iter().zip(self.hashes.iter()).enumerate() {
+            let date = DateTime::<Local>::from(item.accessed_at).date_naive();
+            if day.is_some_and(|day| day != date) {
+                continue;
+            }
+            let entry = days.entry(date).or_insert_with(|| Day {
+                day: date,
+                count: 0,
+                items: vec![],
+            });
+            entry.count += 1;
+            if day.is_some() {
+                entry
+                    .items
+                    .push((index, ItemSummary::new(item, *hash, preview_length)));
+            }
         }
+        days.into_values().rev().collect()
+    }
+
+    pub fn select_all(&self) -> Vec<(usize, Item)> {
+        self.values.iter().cloned().enumerate().collect()
+    }
+
+    pub fn select(&self, filter: &Filter) -> Vec<(usize, Item)> {
+        // nothing for empty filter, everything is asked for explicitly (see
+        // select_all and `select --all`)
+        if filter.is_empty() {
+            return vec![];
+        };
 
         let mut items_iter: Box<dyn Iterator<Item = (usize, &Item)>> =
             Box::new(self.values.iter().enumerate());
 
-        if !tag.is_empty() {
-            let tags_set: HashSet<String> = tag.into_iter().collect();
+        if let Some(pins) = filter.pin_chars() {
+            items_iter = Box::new(
+                items_iter.filter(move |(_, item)| item.pin.is_some_and(|pin| pins.contains(&pin))),
+            );
+        }
+
+        if !filter.tag.is_empty() {
+            let tags_set: HashSet<String> = filter.tag.iter().cloned().collect();
             items_iter = Box::new(items_iter.filter(move |(_, item)| {
                 if let Some(tags) = &item.tags {
                     tags.is_superset(&tags_set)
@@ -383,8 +1514,61 @@ impl Entries {
             }));
         }
 
-        if let Some(value) = value {
-            items_iter = Box::new(items_iter.filter(move |(_, item)| item.value.contains(&value)));
+        if let Some(value) = &filter.value {
+            items_iter = Box::new(items_iter.filter(move |(_, item)| item.value.contains(value)));
+        }
+
+        if let Some(app) = &filter.app {
+            items_iter = Box::new(items_iter.filter(move |(_, item)| {
+                item.source_app
+                    .as_ref()
+                    .is_some_and(|source| source.eq_ignore_ascii_case(app))
+            }));
+        }
+
+        if let Some(note) = &filter.note {
+            let note = note.to_lowercase();
+            items_iter = Box::new(items_iter.filter(move |(_, item)| {
+                item.note
+                    .as_ref()
+                    .is_some_and(|n| n.to_lowercase().contains(&note))
+            }));
+        }
+
+        if let Some(title) = &filter.title {
+            let title = title.to_lowercase();
+            items_iter = Box::new(items_iter.filter(move |(_, item)| {
+                item.title
+                    .as_ref()
+                    .is_some_and(|t| t.to_lowercase().contains(&title))
+            }));
+        }
+
+        if let Some(regex) = &filter.regex {
+            let Ok(regex) = Regex::new(regex) else {
+                return vec![];
+            };
+            items_iter = Box::new(items_iter.filter(move |(_, item)| regex.is_match(&item.value)));
+        }
+
+        if let Some(secs) = filter.older_than {
+            let now = SystemTime::now();
+            items_iter = Box::new(items_iter.filter(move |(_, item)| {
+                now.duration_since(item.accessed_at)
+                    .is_ok_and(|age| age.as_secs() > secs)
+            }));
+        }
+
+        if let Some(secs) = filter.newer_than {
+            let now = SystemTime::now();
+            items_iter = Box::new(items_iter.filter(move |(_, item)| {
+                now.duration_since(item.accessed_at)
+                    .map_or(true, |age| age.as_secs() <= secs)
+            }));
+        }
+
+        if let Some(kind) = filter.kind {
+            items_iter = Box::new(items_iter.filter(move |(_, item)| kind.matches(item)));
         }
 
         items_iter
@@ -392,6 +1576,91 @@ impl Entries {
             .collect()
     }
 
+    // Merges duplicate groups into most accessed item, returns groups (kept
+    // item first). Keychain and spilled items are left out, their values are
+    // placeholders.
+    pub fn dedup(&mut self, dry_run: bool) -> Vec<Vec<(usize, Item)>> {
+        self.merge_duplicates(
+            |_, item| (!item.keychain && !item.spilled).then(|| normalize(&item.value)),
+            dry_run,
+        )
+    }
+
+    // items with same key are merged into most accessed one (see dedup),
+    // ones without key are never merged
+    fn merge_duplicates<K: Hash + Eq>(
+        &mut self,
+        key_of: impl Fn(usize, &Item) -> Option<K>,
+        dry_run: bool,
+    ) -> Vec<Vec<(usize, Item)>> {
+        let mut keys: HashMap<K, usize> = HashMap::new();
+        let mut groups: Vec<Vec<(usize, Item)>> = vec![];
+        for (index, item) in self.values.iter().enumerate() {
+            let Some(key) = key_of(index, item) else {
+                continue;
+            };
+            let group = *keys.entry(key).or_insert_with(|| {
+                groups.push(vec![]);
+                groups.len() - 1
+            });
+            groups[group].push((index, item.clone()));
+        }
+        groups.retain(|group| group.len() > 1);
+        for group in groups.iter_mut() {
+            group.sort_by(|(ia, a), (ib, b)| {
+                b.access_counter.cmp(&a.access_counter).then(ia.cmp(ib))
+            });
+        }
+
+        if !dry_run {
+            let mut dropped: HashSet<usize> = HashSet::new();
+            for group in groups.iter() {
+                let (kept_index, kept) = &group[0];
+                let mut merged = kept.clone();
+                for (index, item) in group.iter().skip(1) {
+                    if let Some(tags) = &item.tags {
+                        merged
+                            .tags
+                            .get_or_insert_with(HashSet::new)
+                            .extend(tags.iter().cloned());
+                    }
+                    merged.pin = merged.pin.or(item.pin);
+                    merged.note = merged.note.or_else(|| item.note.clone());
+                    merged.title = merged.title.or_else(|| item.title.clone());
+                    merged.favorite |= item.favorite;
+                    dropped.insert(*index);
+                }
+                if let Some(item) = self.get(*kept_index) {
+                    *item = merged;
+                }
+            }
+            self.retain(|index, _| !dropped.contains(&index));
+        }
+
+        groups
+    }
+
+    pub fn clear(&mut self, keep_pinned: bool) {
+        if keep_pinned {
+            self.retain(|_, item| item.favorite || item.pin.is_some());
+        } else {
+            self.values.clear();
+            self.hashes.clear();
+            self.changes.reordered = true;
+        }
+    }
+
+    // removes selected items (favorites are kept), returns removed ones
+    pub fn prune(&mut self, filter: &Filter, dry_run: bool) -> Vec<(usize, Item)> {
+        let mut items = self.select(filter);
+        items.retain(|(_, item)| !item.favorite);
+        if !dry_run {
+            let indexes: HashSet<usize> = items.iter().map(|(index, _)| *index).collect();
+            self.retain(|index, _| !indexes.contains(&index));
+        }
+        items
+    }
+
     pub fn select_by_value(&self, value: String) -> Vec<(usize, Item)> {
         let val = value.as_str();
 
@@ -419,16 +1688,12 @@ impl Entries {
     }
 
     pub fn select_by_pin(&self, pin: char) -> Vec<(usize, Item)> {
-        if let Some((index, item)) = self
-            .values
+        self.values
             .iter()
             .enumerate()
-            .find(|(_, item)| item.pin.unwrap_or(' ') == pin)
-        {
-            vec![(index, item.clone())]
-        } else {
-            vec![]
-        }
+            .filter(|(_, item)| item.pin == Some(pin))
+            .map(|(index, item)| (index, item.clone()))
+            .collect()
     }
     pub fn tag(&mut self, index: usize, tag: String) -> bool {
         if let Some(item) = self.get(index) {
@@ -441,6 +1706,24 @@ impl Entries {
         }
     }
 
+    pub fn favorite(&mut self, index: usize, favorite: bool) -> bool {
+        if let Some(item) = self.get(index) {
+            item.favorite = favorite;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn note(&mut self, index: usize, note: Option<String>) -> bool {
+        if let Some(item) = self.get(index) {
+            item.note = note;
+            true
+        } else {
+            false
+        }
+    }
+
     pub fn untag(&mut self, index: usize, tag: String) -> bool {
         if let Some(item) = self.get(index) {
             match item.tags.as_mut() {
@@ -462,10 +1745,71 @@ impl Entries {
         result
     }
 
+    pub fn get_tag_counts(&self) -> Vec<(String, usize)> {
+        let mut counts: HashMap<&String, usize> = HashMap::new();
+        for tag in self
+            .values
+            .iter()
+            .filter_map(|item| item.tags.as_ref())
+            .flatten()
+        {
+            *counts.entry(tag).or_default() += 1;
+        }
+        let mut result: Vec<(String, usize)> = counts
+            .into_iter()
+            .map(|(tag, count)| (tag.clone(), count))
+            .collect();
+        result.sort();
+        result
+    }
+
+    // most frequently (and recently) copied items, pinned ones first
+    pub fn ring(&self, size: usize) -> Vec<(usize, Item)> {
+        let now = SystemTime::now();
+        let mut items: Vec<(usize, &Item)> = self.values.iter().enumerate().collect();
+        items.sort_by(|(ia, a), (ib, b)| {
+            a.pin
+                .is_none()
+                .cmp(&b.pin.is_none())
+                .then(a.pin.cmp(&b.pin))
+                .then(b.frecency(now).total_cmp(&a.frecency(now)))
+                .then(ia.cmp(ib))
+        });
+        items
+            .into_iter()
+            .take(size)
+            .map(|(index, item)| (index, item.clone()))
+            .collect()
+    }
+
+    // numbered pins ('1'..'9') follow most frequently copied items,
+    // items pinned by hand (non digit pins) are left alone
+    pub fn pin_ring(&mut self, size: usize) {
+        let mut items: Vec<(usize, &mut Item)> = self
+            .values
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, item)| item.pin.is_none_or(|pin| pin.is_ascii_digit()))
+            .collect();
+        let now = SystemTime::now();
+        items.sort_by(|(ia, a), (ib, b)| {
+            b.frecency(now).total_cmp(&a.frecency(now)).then(ia.cmp(ib))
+        });
+        let mut pins = ('1'..='9').take(size);
+        for (_, item) in items {
+            let pin = pins.next();
+            if item.pin != pin {
+                item.pin = pin;
+                self.changes.items.insert(item.id.clone());
+            }
+        }
+    }
+
     pub fn pin(&mut self, index: usize, pin: char) {
         self.values.iter_mut().for_each(|item| match item.pin {
             Some(p) if p == pin => {
                 item.pin.take();
+                self.changes.items.insert(item.id.clone());
             }
             _ => {}
         });
@@ -481,13 +1825,183 @@ impl Entries {
         }
     }
 
+    // returns number of removed items
+    pub fn remove_expired(&mut self, now: SystemTime) -> usize {
+        let len = self.len();
+        self.retain(|_, item| item.favorite || !item.is_expired(now));
+        len - self.len()
+    }
+
+    // append mode capture: first value is inserted as usual, later ones
+    // replace previous joined item (if it's still there) with a longer one
+    pub fn insert_joined(&mut self, append: &mut Append, value: String) -> &mut Item {
+        if let Some(id) = append.id.take() {
+            self.retain(|_, item| item.id != id);
+        }
+        let first = append.value.is_none();
+        let item = self.insert(append.join(value));
+        append.id = (!first).then(|| item.id.clone());
+        item
+    }
+
+    // false if there is no item at index or another one has that value
+    pub fn replace_value(&mut self, index: usize, value: String) -> bool {
+        let hash = calculate_hash(&value);
+        if self.hashes.contains(&hash) {
+            return false;
+        }
+        let Some(item) = self.values.iter_mut().nth(index) else {
+            return false;
+        };
+        item.value = value;
+        item.hash = hash;
+        self.changes.items.insert(item.id.clone());
+        if let Some(item_hash) = self.hashes.iter_mut().nth(index) {
+            *item_hash = hash;
+        }
+        true
+    }
+
+    // drops items with lowest frecency until at most max_entries are left,
+    // favorite and pinned ones are kept
+    pub fn evict(&mut self, max_entries: usize) -> usize {
+        let now = SystemTime::now();
+        let mut candidates: Vec<(usize, f64)> = self
+            .values
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| !item.favorite && item.pin.is_none())
+            .map(|(index, item)| (index, item.frecency(now)))
+            .collect();
+        candidates.sort_by(|(ia, a), (ib, b)| a.total_cmp(b).then(ib.cmp(ia)));
+        let evicted: HashSet<usize> = candidates
+            .into_iter()
+            .take(self.len().saturating_sub(max_entries))
+            .map(|(index, _)| index)
+            .collect();
+        self.retain(|index, _| !evicted.contains(&index));
+        evicted.len()
+    }
+
+    // incognito items are dropped even if favorite
+    pub fn remove_ephemeral(&mut self) -> usize {
+        let len = self.len();
+        self.retain(|_, item| !item.ephemeral);
+        len - self.len()
+    }
+
+    // copy without incognito items, that's what gets saved
+    pub fn persistent(&self) -> Entries {
+        let (values, hashes) = self
+            .values
+            .iter()
+            .zip(&self.hashes)
+            .filter(|(item, _)| !item.ephemeral)
+            .map(|(item, hash)| (item.clone(), *hash))
+            .unzip();
+        Entries {
+            values,
+            hashes,
+            version: self.version,
+            trash: self.trash.clone(),
+            changes: Changes::default(),
+        }
+    }
+
+    // hashes are current and in step with values, values of keychain and
+    // spilled items aren't checked (they are placeholders)
+    pub fn is_consistent(&self) -> bool {
+        self.version == ENTRIES_VERSION
+            && self.values.len() == self.hashes.len()
+            && self.values.iter().zip(&self.hashes).all(|(item, hash)| {
+                item.hash == *hash
+                    && (item.keychain || item.spilled || calculate_hash(&item.value) == *hash)
+            })
+    }
+
+    // bytes of values kept in memory, trash included
+    pub fn size(&self) -> usize {
+        self.values
+            .iter()
+            .chain(self.trash.iter().map(|trashed| &trashed.item))
+            .map(|item| item.value.len())
+            .sum()
+    }
+
+    // Recomputes hashes from values (`hash_of` gets real value of keychain
+    // items) and merges items left with same hash unless duplicates are
+    // kept on purpose (see DedupMode), see Command::Rebuild. Items whose
+    // value can't be read keep their hash and aren't merged.
+    pub fn rebuild(
+        &mut self,
+        hash_of: impl Fn(&Item) -> Result<u64>,
+        mode: DedupMode,
+    ) -> RebuildReport {
+        let old: Vec<u64> = self.hashes.iter().copied().collect();
+        let rehashed: Vec<Option<u64>> = self
+            .values
+            .iter()
+            .map(|item| {
+                hash_of(item)
+                    .map_err(|err| eprintln!("{} isn't rehashed: {err:#}", item.id))
+                    .ok()
+            })
+            .collect();
+        let hashes: Vec<u64> = self
+            .values
+            .iter()
+            .zip(&rehashed)
+            .map(|(item, hash)| hash.unwrap_or(item.hash))
+            .collect();
+        let mut report = RebuildReport {
+            values: self.values.len(),
+            hashes: old.len(),
+            rehashed: hashes
+                .iter()
+                .enumerate()
+                .filter(|(index, hash)| old.get(*index) != Some(hash))
+                .count(),
+            merged: 0,
+        };
+        self.hashes = hashes.iter().copied().collect();
+        for (item, hash) in self.values.iter_mut().zip(&hashes) {
+            if item.hash != *hash {
+                item.hash = *hash;
+                self.changes.items.insert(item.id.clone());
+            }
+        }
+        self.version = ENTRIES_VERSION;
+        if mode == DedupMode::Global {
+            report.merged = self
+                .merge_duplicates(|index, _| rehashed[index], false)
+                .iter()
+                .map(|group| group.len() - 1)
+                .sum();
+        }
+        report
+    }
+
+    fn retain(&mut self, f: impl Fn(usize, &Item) -> bool) {
+        let len = self.values.len();
+        let values = std::mem::take(&mut self.values);
+        let hashes = std::mem::take(&mut self.hashes);
+        (self.values, self.hashes) = values
+            .into_iter()
+            .zip(hashes)
+            .enumerate()
+            .filter(|(index, (item, _))| f(*index, item))
+            .map(|(_, pair)| pair)
+            .unzip();
+        self.changes.reordered |= self.values.len() != len;
+    }
+
     pub fn len(&self) -> usize {
         let values_len = self.values.len();
         let hashes_len = self.hashes.len();
         if values_len != hashes_len {
             eprintln!(
-                "Inconsistent state ({} values against {} hashes). Need to rebuild index.",
-                hashes_len, values_len
+                "Inconsistent state ({} values against {} hashes). Need to rebuild index (see `rebuild`).",
+                values_len, hashes_len
             )
         }
         values_len
@@ -498,38 +2012,575 @@ impl Entries {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 pub struct Config {
     pub interactive: Option<bool>,
     pub host: Option<String>,
     pub port: Option<u16>,
+    // `~` is expanded, see db_path
     pub db: Option<String>,
+    // changes between saves are logged to `<db>.wal`, on by default
+    pub wal: Option<bool>,
+    // stdout/stderr of `clipr-daemon --daemonize` (clipr.log in data dir)
+    pub log_file: Option<String>,
+    // pid of running daemon (clipr.pid in data dir)
+    pub pid_file: Option<String>,
+    pub relay: Option<relay::RelayConfig>,
+    pub discovery: Option<bool>,
+    pub grpc_port: Option<u16>,
+    pub osc52_port: Option<u16>,
+    // plain text line protocol (commands as typed in REPL), no auth
+    pub text_port: Option<u16>,
+    // monitored in addition to general one ("find", "font", "ruler", "drag" or custom name)
+    pub pasteboards: Option<Vec<String>>,
+    // cli asks before deleting more items than that at once (10 by default)
+    pub confirm_delete: Option<usize>,
+    // put newest item back on general pasteboard found empty at startup
+    // (e.g. after reboot)
+    pub restore_clipboard: Option<bool>,
+    // per-application capture rules, matched by bundle id
+    pub apps: Option<Vec<rules::AppRule>>,
+    // directory with rhai scripts (daemon built with `scripting` feature)
+    pub scripts: Option<String>,
+    pub ring: Option<RingConfig>,
+    pub http: Option<HttpConfig>,
+    // sent by clients (cli, emacs), see HttpConfig::tokens
+    pub token: Option<String>,
+    // how long clients wait for daemon
+    pub client: Option<ClientConfig>,
+    // fetch page titles of copied links
+    pub unfurl: Option<bool>,
+    // keep values of items tagged SECRET_TAG in system keychain instead of db
+    pub keychain: Option<bool>,
+    // history size, items with lowest frecency are evicted (favorite and
+    // pinned ones are kept)
+    pub max_entries: Option<usize>,
+    // `list` order, "recent" (default) or "frecency"
+    pub list_order: Option<ListOrder>,
+    // "global" (default), "window:<age>" (e.g. "window:1h", see parse_age)
+    // or "off" - when copy of value already in history is merged into it
+    pub dedup: Option<DedupMode>,
+    // capture HTML flavor of copies (web pages, rich text) as Markdown
+    pub html_to_markdown: Option<bool>,
+    // strip tracking params of copied links
+    pub clean_urls: Option<cleanurl::CleanUrlsConfig>,
+    // passphrase protected access (see Command::Unlock)
+    pub lock: Option<lock::LockConfig>,
+    // periodic cleanup run by daemon
+    pub maintenance: Option<MaintenanceConfig>,
+    // follow history of another daemon instead of capturing pasteboard
+    pub mirror: Option<MirrorConfig>,
+    // new items to broker, commands from it (daemon built with `mqtt` feature)
+    pub mqtt: Option<mqtt::MqttConfig>,
+    // wipe pasteboard some time after secret is copied
+    pub clear_secrets: Option<ClearSecretsConfig>,
+    // deleted items are kept in trash for that long (7 by default)
+    pub trash_days: Option<u64>,
+    // cap on memory taken by values
+    pub memory: Option<MemoryConfig>,
+    pub display: Option<DisplayConfig>,
+}
+
+pub const DEFAULT_RING_SIZE: usize = 9;
+pub const DEFAULT_MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+pub const DEFAULT_RATE_LIMIT: u32 = 100;
+pub const DEFAULT_INCOGNITO_DURATION: u64 = 15 * 60;
+pub const DEFAULT_APPEND_SEPARATOR: &str = "\n";
+pub const DEFAULT_MAINTENANCE_INTERVAL: u64 = 60 * 60;
+pub const DEFAULT_TRASH_DAYS: u64 = 7;
+pub const DEFAULT_MIRROR_INTERVAL: u64 = 60;
+pub const DEFAULT_CLEAR_SECRETS_AFTER: u64 = 30;
+pub const DEFAULT_CONFIRM_DELETE: usize = 10;
+pub const DEFAULT_CLIENT_TIMEOUT: u64 = 10;
+pub const DEFAULT_CLIENT_RETRIES: u32 = 2;
+pub const DEFAULT_MEMORY_MIN_SIZE: usize = 4096;
+pub const DEFAULT_NOTE_TEMPLATE: &str = "### {date} [{tags}]\n\n{value}\n";
+pub const FRECENCY_HALF_LIFE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+// bumped when saved Entries need migration, 1 is xxh3 hashes
+pub const ENTRIES_VERSION: u32 = 1;
+// "clipr"
+const HASH_SEED: u64 = 0x0063_6c69_7072;
+pub const SECRET_TAG: &str = "secret";
+
+pub const DEFAULT_DATE_FORMAT: &str = "%d-%m-%Y";
+
+// Text output defaults, preview_length is applied by daemon
+// when command doesn't set it.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct DisplayConfig {
+    pub preview_length: Option<usize>,
+    // chrono format string
+    pub date_format: Option<String>,
+    // shown columns in given order, all by default
+    pub columns: Option<Vec<Column>>,
+    // list line template, replaces columns (see template)
+    pub template: Option<String>,
+    // set by cli/repl (see display::ColorChoice)
+    #[serde(skip)]
+    pub color: bool,
+    // regexes masked in listings (see redact)
+    pub redact: Option<Vec<String>>,
+    #[serde(skip)]
+    redactor: OnceLock<Redactor>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Column {
+    // favorite mark and pin
+    Flags,
+    Preview,
+    Tags,
+    Date,
+    App,
+    Title,
+    Note,
+}
+
+impl DisplayConfig {
+    // patterns are validated by Config::load_config
+    pub fn redactor(&self) -> &Redactor {
+        self.redactor.get_or_init(|| {
+            Redactor::new(self.redact.as_deref().unwrap_or_default()).unwrap_or_default()
+        })
+    }
+
+    pub fn date_format(&self) -> &str {
+        self.date_format.as_deref().unwrap_or(DEFAULT_DATE_FORMAT)
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        self.columns.as_deref().unwrap_or(&[
+            Column::Flags,
+            Column::Preview,
+            Column::Tags,
+            Column::Date,
+            Column::App,
+            Column::Title,
+            Column::Note,
+        ])
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct HttpConfig {
+    // bytes
+    pub max_body_size: Option<usize>,
+    // requests per second per client address, 0 disables limit
+    pub rate_limit: Option<u32>,
+    // when set, every request needs one of them (`Authorization: Bearer <token>`)
+    pub tokens: Option<Vec<HttpToken>>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct HttpToken {
+    pub token: String,
+    #[serde(default)]
+    pub access: Access,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Access {
+    // listing and reading commands only (see Command::is_read_access)
+    Read,
+    #[default]
+    Full,
+}
+
+impl HttpConfig {
+    pub fn max_body_size(&self) -> usize {
+        self.max_body_size.unwrap_or(DEFAULT_MAX_BODY_SIZE)
+    }
+
+    pub fn rate_limit(&self) -> u32 {
+        self.rate_limit.unwrap_or(DEFAULT_RATE_LIMIT)
+    }
+
+    // None for unknown (or missing) token, anyone has full access without tokens
+    pub fn access(&self, token: Option<&str>) -> Option<Access> {
+        let Some(tokens) = &self.tokens else {
+            return Some(Access::Full);
+        };
+        let token = token?;
+        tokens
+            .iter()
+            .find(|known| known.token == token)
+            .map(|known| known.access)
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct RingConfig {
+    pub size: Option<usize>,
+    // mirror ring to numbered pins ('1'..'9')
+    pub pins: Option<bool>,
+}
+
+// what Entries::rebuild repaired
+#[derive(Debug, Default)]
+pub struct RebuildReport {
+    // lengths before rebuild
+    pub values: usize,
+    pub hashes: usize,
+    // hashes that differed from recomputed ones (or were missing)
+    pub rehashed: usize,
+    // items dropped as duplicates of other ones
+    pub merged: usize,
+}
+
+impl fmt::Display for RebuildReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} values, {} hashes, {} rehashed, {} duplicates merged",
+            self.values, self.hashes, self.rehashed, self.merged
+        )
+    }
+}
+
+// When values in memory (history and trash) take more than `max_bytes`,
+// ones longer than `min_size` bytes are spilled to files (read back by
+// `get`, `set`) or truncated to `min_size` (text only), oldest first.
+// Pinned, favorite and secret items are left alone.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct MemoryConfig {
+    pub max_bytes: Option<usize>,
+    pub policy: Option<MemoryPolicy>,
+    // 4096 by default
+    pub min_size: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MemoryPolicy {
+    #[default]
+    Spill,
+    Truncate,
+}
+
+impl MemoryConfig {
+    pub fn min_size(&self) -> usize {
+        self.min_size.unwrap_or(DEFAULT_MEMORY_MIN_SIZE)
+    }
+}
+
+// Every run drops expired items, rebuilds hash index if it's out of step
+// with values and saves (compacts) history, report goes to stderr.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct MaintenanceConfig {
+    // seconds between runs, hourly by default
+    pub interval: Option<u64>,
+    // merge whitespace duplicates too (see Command::Dedup)
+    pub dedup: Option<bool>,
+}
+
+// Read-only copy of primary daemon history: items arrive over its event
+// stream, whole history is fetched again every `interval` (and on reconnect)
+// to catch up on deletions. Local pasteboard isn't captured or written,
+// commands changing history are rejected. Secret items aren't mirrored.
+#[derive(Clone, Debug, Deserialize)]
+pub struct MirrorConfig {
+    // host:port of primary daemon
+    pub primary: String,
+    pub token: Option<String>,
+    // seconds between full refreshes, 60 by default
+    pub interval: Option<u64>,
+}
+
+// Copies tagged SECRET_TAG (by app rules or plugins) are wiped from general
+// pasteboard `after` seconds, unless something else was copied meanwhile,
+// like password managers do.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ClearSecretsConfig {
+    // seconds, 30 by default
+    pub after: Option<u64>,
+    // false drops item from history too (bypassing trash), kept by default
+    pub keep: Option<bool>,
+}
+
+// Requests daemon doesn't answer in `timeout` seconds fail with "daemon not
+// responding", read-only ones are retried `retries` times first.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ClientConfig {
+    pub timeout: Option<u64>,
+    pub retries: Option<u32>,
+    // cli falls back to reading db file when daemon is unreachable
+    pub offline: Option<bool>,
+}
+
+impl ClientConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout.unwrap_or(DEFAULT_CLIENT_TIMEOUT))
+    }
+
+    pub fn retries(&self) -> u32 {
+        self.retries.unwrap_or(DEFAULT_CLIENT_RETRIES)
+    }
+
+    pub fn offline(&self) -> bool {
+        self.offline == Some(true)
+    }
+}
+
+impl ClearSecretsConfig {
+    pub fn after(&self) -> Duration {
+        Duration::from_secs(self.after.unwrap_or(DEFAULT_CLEAR_SECRETS_AFTER))
+    }
+
+    pub fn keep(&self) -> bool {
+        self.keep != Some(false)
+    }
+}
+
+impl MirrorConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval.unwrap_or(DEFAULT_MIRROR_INTERVAL))
+    }
+}
+
+impl MaintenanceConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval.unwrap_or(DEFAULT_MAINTENANCE_INTERVAL))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ListOrder {
+    #[default]
+    Recent,
+    Frecency,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum DedupMode {
+    // copy of any value in history only touches it
+    #[default]
+    Global,
+    // only values accessed within window are merged
+    Window(Duration),
+    // every copy is a new item, exact timeline
+    Off,
+}
+
+impl FromStr for DedupMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "global" => Ok(DedupMode::Global),
+            "off" => Ok(DedupMode::Off),
+            _ => match s.strip_prefix("window:") {
+                Some(age) => Ok(DedupMode::Window(Duration::from_secs(parse_age(age)?))),
+                None => Err(format!(
+                    "invalid dedup mode {s:?}, expected global, window:<age> or off"
+                )),
+            },
+        }
+    }
+}
+
+impl TryFrom<String> for DedupMode {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
 }
 
 impl Config {
+    pub fn dedup(&self) -> DedupMode {
+        self.dedup.unwrap_or_default()
+    }
+
+    pub fn trash_retention(&self) -> Duration {
+        Duration::from_secs(self.trash_days.unwrap_or(DEFAULT_TRASH_DAYS) * 24 * 60 * 60)
+    }
+
+    pub fn ring_size(&self) -> usize {
+        self.ring
+            .as_ref()
+            .and_then(|ring| ring.size)
+            .unwrap_or(DEFAULT_RING_SIZE)
+    }
+
+    pub fn display(&self) -> DisplayConfig {
+        self.display.clone().unwrap_or_default()
+    }
+
+    pub fn client(&self) -> ClientConfig {
+        self.client.clone().unwrap_or_default()
+    }
+
+    pub fn preview_length(&self) -> Option<usize> {
+        self.display
+            .as_ref()
+            .and_then(|display| display.preview_length)
+    }
+
     pub fn listen_on(&self) -> String {
         format!("{}:{}", self.host.as_ref().unwrap(), self.port.unwrap())
     }
+
+    pub fn app_rule(&self, app: Option<&SourceApp>) -> Option<&rules::AppRule> {
+        let bundle_id = app?.bundle_id.as_deref()?;
+        rules::find_rule(self.apps.as_deref()?, bundle_id)
+    }
+
+    // link cleanup settings for copies matched by rule, None if it's off
+    pub fn clean_urls(&self, rule: Option<&rules::AppRule>) -> Option<&cleanurl::CleanUrlsConfig> {
+        match rule.and_then(|rule| rule.clean_urls) {
+            Some(false) => None,
+            Some(true) => Some(
+                self.clean_urls
+                    .as_ref()
+                    .unwrap_or(&cleanurl::DEFAULT_CONFIG),
+            ),
+            None => self.clean_urls.as_ref(),
+        }
+    }
 }
 
 pub struct State {
     pub config: Config,
-    pub entries: Mutex<Entries>,
+    // read-only commands take read lock outside of event loop
+    pub entries: RwLock<Entries>,
+    pub peers: Mutex<Vec<Peer>>,
+    pub plugins: plugin::Plugins,
+    // end of incognito session (see Command::Incognito)
+    pub incognito_until: Mutex<Option<SystemTime>>,
+    // ids of items with value in keychain as of last save
+    pub keychain_ids: Mutex<HashSet<String>>,
+    // end of unlocked period (see Command::Unlock)
+    pub unlocked_until: Mutex<Option<SystemTime>>,
+    // hash of last value daemon put on pasteboard (None is general one)
+    pub own_writes: Mutex<HashMap<Option<String>, u64>>,
+    // ids of items left in paste queue (see Command::Queue)
+    pub queue: Mutex<VecDeque<String>>,
+    // set while captures are joined (see Command::AppendMode)
+    pub append: Mutex<Option<Append>>,
+    // bumped on every change of general pasteboard, captured or written
+    pub pasteboard_changes: AtomicU64,
+    // where values are spilled (see Storage::spill_dir), None keeps them
+    // all in memory
+    pub spill_dir: Option<PathBuf>,
+}
+
+pub struct Append {
+    pub separator: String,
+    // joined so far, None until first capture
+    pub value: Option<String>,
+    // item holding joined value, first capture stays a separate item
+    pub id: Option<String>,
+    pub count: usize,
+}
+
+impl Append {
+    pub fn new(separator: String) -> Self {
+        Self {
+            separator,
+            value: None,
+            id: None,
+            count: 0,
+        }
+    }
+
+    // value to put in history for captured one
+    pub fn join(&mut self, value: String) -> String {
+        self.count += 1;
+        let joined = match self.value.take() {
+            Some(joined) => format!("{joined}{}{value}", self.separator),
+            None => value,
+        };
+        self.value = Some(joined.clone());
+        joined
+    }
 }
 
 impl State {
     pub fn new(config: Config) -> Self {
         Self {
             config,
-            entries: Mutex::new(Entries::new()),
+            entries: RwLock::new(Entries::new()),
+            peers: Mutex::new(vec![]),
+            plugins: vec![],
+            incognito_until: Mutex::new(None),
+            keychain_ids: Mutex::new(HashSet::new()),
+            unlocked_until: Mutex::new(None),
+            own_writes: Mutex::new(HashMap::new()),
+            queue: Mutex::new(VecDeque::new()),
+            append: Mutex::new(None),
+            pasteboard_changes: AtomicU64::new(0),
+            spill_dir: None,
         }
     }
+
+    pub fn record_write(&self, pasteboard: Option<&str>, value: &str) {
+        if pasteboard.is_none() {
+            self.pasteboard_changes.fetch_add(1, Ordering::SeqCst);
+        }
+        self.own_writes
+            .lock()
+            .unwrap()
+            .insert(pasteboard.map(String::from), calculate_hash(value));
+    }
+
+    // true if pasteboard change is daemon's own write, every change
+    // consumes pending write
+    pub fn take_own_write(&self, pasteboard: Option<&str>, hash: u64) -> bool {
+        self.own_writes
+            .lock()
+            .unwrap()
+            .remove(&pasteboard.map(String::from))
+            == Some(hash)
+    }
+
+    // always false without `[lock]` config
+    pub fn is_locked(&self, now: SystemTime) -> bool {
+        self.config.lock.is_some()
+            && !self
+                .unlocked_until
+                .lock()
+                .unwrap()
+                .is_some_and(|until| now < until)
+    }
+
+    // Payload::Locked for commands not allowed while locked
+    pub fn check_lock(&self, cmd: &Command) -> Option<Payload> {
+        (!cmd.is_allowed_locked() && self.is_locked(SystemTime::now())).then_some(Payload::Locked)
+    }
+
+    pub fn is_incognito(&self, now: SystemTime) -> bool {
+        self.incognito_until
+            .lock()
+            .unwrap()
+            .is_some_and(|until| now < until)
+    }
 }
 
 #[derive(Parser, Debug)]
 pub struct Args {
     #[clap(short, long, value_parser)]
     pub config: Option<PathBuf>,
+    // repl output
+    #[clap(long, value_enum, default_value = "auto")]
+    pub color: display::ColorChoice,
+    // repl output, see output::Verbosity
+    #[clap(short, long, global = true)]
+    pub quiet: bool,
+    #[clap(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+    // append incoming requests to file as JSON lines (see --replay)
+    #[clap(long, value_parser)]
+    pub record: Option<PathBuf>,
+    // run recorded requests against fresh state, print resulting history
+    #[clap(long, value_parser)]
+    pub replay: Option<PathBuf>,
+    // detach from terminal, output goes to log_file (no REPL)
+    #[clap(long)]
+    pub daemonize: bool,
     #[clap(subcommand)]
     pub command: Option<Command>,
 }
@@ -540,7 +2591,39 @@ impl Default for Config {
             host: Some(String::from("127.0.0.1")),
             port: Some(8932),
             interactive: Some(true),
-            db: Some(String::from("./db.json")),
+            db: None,
+            wal: None,
+            log_file: None,
+            pid_file: None,
+            relay: None,
+            discovery: Some(false),
+            grpc_port: None,
+            osc52_port: None,
+            text_port: None,
+            pasteboards: None,
+            restore_clipboard: None,
+            confirm_delete: None,
+            apps: None,
+            scripts: None,
+            ring: None,
+            http: None,
+            token: None,
+            client: None,
+            unfurl: Some(false),
+            keychain: None,
+            max_entries: None,
+            list_order: None,
+            dedup: None,
+            html_to_markdown: None,
+            clean_urls: None,
+            lock: None,
+            maintenance: None,
+            mirror: None,
+            mqtt: None,
+            clear_secrets: None,
+            trash_days: None,
+            memory: None,
+            display: None,
         }
     }
 }
@@ -551,17 +2634,91 @@ impl Config {
         let mut buffer = String::new();
         file.read_to_string(&mut buffer)?;
 
-        let config: Config = toml::from_str(buffer.as_str())?;
+        let mut config: Config = toml::from_str(buffer.as_str())?;
+        if let Some(patterns) = config.display.as_ref().and_then(|d| d.redact.as_ref()) {
+            Redactor::new(patterns)?;
+        }
+        let expand = |path: &String| paths::expand_tilde(path).to_string_lossy().into_owned();
+        config.db = config.db.as_ref().map(expand);
+        config.scripts = config.scripts.as_ref().map(expand);
 
         Ok(config)
     }
 
     pub fn load_from_args(args: &Args) -> Result<Self> {
-        Ok(if let Some(filename) = args.config.as_deref() {
-            Self::load_config(filename)?
+        Self::load(args.config.as_deref())
+    }
+
+    // without explicit filename config is taken from CLIPR_CONFIG or looked up
+    // in standard locations (see paths::config_dirs), defaults are used if there
+    // is none, CLIPR_* variables override loaded values (see apply_env)
+    pub fn load(filename: Option<&Path>) -> Result<Self> {
+        let filename = filename
+            .map(|filename| filename.to_path_buf())
+            .or_else(|| std::env::var_os("CLIPR_CONFIG").map(PathBuf::from))
+            .or_else(paths::find_config);
+        let mut config = if let Some(filename) = filename {
+            Self::load_config(&filename)?
         } else {
             Self::default()
-        })
+        };
+        config.apply_env(|name| std::env::var(name).ok())?;
+        Ok(config)
+    }
+
+    // CLIPR_HOST, CLIPR_PORT, CLIPR_DB, CLIPR_INTERACTIVE, CLIPR_DISCOVERY,
+    // CLIPR_GRPC_PORT, CLIPR_OSC52_PORT, CLIPR_TEXT_PORT, CLIPR_SCRIPTS, CLIPR_UNFURL,
+    // CLIPR_TOKEN
+    fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<()> {
+        fn parse<T: FromStr>(
+            name: &str,
+            var: &impl Fn(&str) -> Option<String>,
+        ) -> Result<Option<T>> {
+            var(name)
+                .map(|value| {
+                    value
+                        .parse()
+                        .map_err(|_| anyhow::anyhow!("invalid {name} value: {value}"))
+                })
+                .transpose()
+        }
+        let path =
+            |name| var(name).map(|path| paths::expand_tilde(&path).to_string_lossy().into_owned());
+
+        self.host = var("CLIPR_HOST").or(self.host.take());
+        self.port = parse("CLIPR_PORT", &var)?.or(self.port);
+        self.db = path("CLIPR_DB").or(self.db.take());
+        self.interactive = parse("CLIPR_INTERACTIVE", &var)?.or(self.interactive);
+        self.discovery = parse("CLIPR_DISCOVERY", &var)?.or(self.discovery);
+        self.grpc_port = parse("CLIPR_GRPC_PORT", &var)?.or(self.grpc_port);
+        self.osc52_port = parse("CLIPR_OSC52_PORT", &var)?.or(self.osc52_port);
+        self.text_port = parse("CLIPR_TEXT_PORT", &var)?.or(self.text_port);
+        self.scripts = path("CLIPR_SCRIPTS").or(self.scripts.take());
+        self.unfurl = parse("CLIPR_UNFURL", &var)?.or(self.unfurl);
+        self.token = var("CLIPR_TOKEN").or(self.token.take());
+        Ok(())
+    }
+
+    pub fn db_path(&self) -> PathBuf {
+        self.db
+            .as_deref()
+            .map_or_else(paths::default_db_path, paths::expand_tilde)
+    }
+
+    pub fn wal(&self) -> bool {
+        self.wal != Some(false)
+    }
+
+    pub fn log_path(&self) -> PathBuf {
+        self.log_file
+            .as_deref()
+            .map_or_else(paths::default_log_path, paths::expand_tilde)
+    }
+
+    pub fn pid_path(&self) -> PathBuf {
+        self.pid_file
+            .as_deref()
+            .map_or_else(paths::default_pid_path, paths::expand_tilde)
     }
 }
 
@@ -577,6 +2734,553 @@ mod test {
         assert_eq!(entries.hashes.len(), 1);
     }
 
+    #[test]
+    fn test_files_summary() {
+        let paths = vec![PathBuf::from("/tmp/a.txt"), PathBuf::from("/tmp/b.png")];
+        let mut item = Item::new(files_value(&paths));
+        item.kind = ItemKind::Files(paths);
+        let summary = ItemSummary::new(&item, 0, None);
+        assert_eq!(summary.preview, "[files] /tmp/a.txt /tmp/b.png");
+    }
+
+    #[test]
+    fn test_guess_mime() {
+        assert_eq!(guess_mime("shot.PNG"), "image/png");
+        assert_eq!(guess_mime("-"), "text/plain");
+    }
+
+    #[test]
+    fn test_entries_history() {
+        let mut entries = Entries::default();
+        let day = |d| Local.with_ymd_and_hms(2024, 5, d, 12, 0, 0).unwrap();
+        entries.insert(String::from("a")).accessed_at = day(1).into();
+        entries.insert(String::from("b")).accessed_at = day(2).into();
+        entries.insert(String::from("c")).accessed_at = day(2).into();
+        let days = entries.history(None, None);
+        assert_eq!(days.len(), 2);
+        assert_eq!((days[0].count, days[0].items.len()), (2, 0));
+        let days = entries.history(Some(day(1).date_naive()), None);
+        assert_eq!(days.len(), 1);
+        assert_eq!(days[0].items[0].0, 2);
+    }
+
+    #[test]
+    fn test_format_columns() {
+        let mut item = Item::new(String::from("hello"));
+        item.accessed_at = Local.with_ymd_and_hms(2024, 5, 2, 12, 0, 0).unwrap().into();
+        let display = DisplayConfig {
+            preview_length: Some(8),
+            date_format: Some(String::from("%Y-%m-%d")),
+            columns: Some(vec![Column::Preview, Column::Date, Column::Note]),
+            ..DisplayConfig::default()
+        };
+        assert_eq!(
+            format_item(&item, true, None, &display),
+            "hello    @[2024-05-02]"
+        );
+    }
+
+    #[test]
+    fn test_shorten() {
+        assert_eq!(shorten("hello\nworld", None), "hello...");
+        assert_eq!(shorten("hello\n  ", None), "hello");
+        // first line fits, but not with spacer
+        assert_eq!(shorten("abcdefghi\nx", Some(10)), "abcdefg...");
+        assert_eq!(shorten("hello world", Some(2)), "..");
+        let cjk = "漢字".repeat(20);
+        let short = shorten(&cjk, None);
+        assert_eq!(short, format!("{0}...{0}", "漢字".repeat(4)));
+        assert!(short.width() <= MAX_LEN);
+        let family = "👨‍👩‍👧".repeat(40);
+        let short = shorten(&family, Some(20)).replace(SPACER, "");
+        assert!(!short.is_empty());
+        assert!(short.graphemes(true).all(|g| g == "👨‍👩‍👧"));
+        assert_eq!(pad("漢", 4).width(), 4);
+    }
+
+    // printable text mixed with line breaks, combining marks, ZWJ and wide chars
+    const ODD_TEXT: &str = "(\\PC|[\n\r\t \u{200d}\u{301}\u{fe0f}漢👨])*";
+
+    proptest::proptest! {
+        #[test]
+        fn prop_shorten(s in ODD_TEXT, max_len in proptest::option::of(0usize..80)) {
+            let short = shorten(&s, max_len);
+            let max_len_or_default = max_len.unwrap_or(MAX_LEN);
+            proptest::prop_assert!(short.width() <= max_len_or_default);
+            proptest::prop_assert!(!short.contains(['\n', '\r']));
+            proptest::prop_assert_eq!(&shorten(&short, max_len), &short);
+            if !s.contains(['\n', '\r']) && s.width() <= max_len_or_default {
+                proptest::prop_assert_eq!(&short, &s);
+            }
+        }
+
+        #[test]
+        fn prop_format_item(
+            value in ODD_TEXT,
+            note in ODD_TEXT,
+            preview_length in proptest::option::of(0usize..80),
+        ) {
+            let mut item = Item::new(value);
+            item.note = Some(note.clone());
+            item.title = Some(note);
+            let display = DisplayConfig::default();
+            let line = format_item(&item, true, preview_length, &display);
+            proptest::prop_assert!(!line.contains('\n'));
+            format_item(&item, false, preview_length, &display);
+        }
+    }
+
+    #[test]
+    fn test_match_offsets() {
+        let filter = Filter {
+            value: Some(String::from("ab")),
+            regex: Some(String::from("b+c")),
+            ..Filter::default()
+        };
+        assert_eq!(filter.match_offsets("xabbc ab"), vec![(1, 5), (6, 8)]);
+    }
+
+    #[test]
+    fn test_entries_select_by_app() {
+        let mut entries = Entries::default();
+        entries.insert(String::from("hello")).source_app = Some(String::from("Slack"));
+        entries.insert(String::from("world"));
+        let filter = Filter {
+            app: Some(String::from("slack")),
+            ..Filter::default()
+        };
+        let items = entries.select(&filter);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].0, 1);
+    }
+
+    #[test]
+    fn test_entries_select_by_kind() {
+        let mut entries = Entries::default();
+        entries.insert(String::from("https://example.com"));
+        entries.insert(String::from("see https://example.com"));
+        entries
+            .insert(files_value(&[PathBuf::from("/tmp/a.txt")]))
+            .kind = ItemKind::Files(vec![PathBuf::from("/tmp/a.txt")]);
+        let select = |kind| {
+            let filter = Filter {
+                kind: Some(kind),
+                ..Filter::default()
+            };
+            entries
+                .select(&filter)
+                .into_iter()
+                .map(|(index, _)| index)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(select(KindFilter::Url), vec![2]);
+        assert_eq!(select(KindFilter::Text), vec![1, 2]);
+        assert_eq!(select(KindFilter::Files), vec![0]);
+    }
+
+    #[test]
+    fn test_entries_remove_expired() {
+        let mut entries = Entries::default();
+        entries.insert(String::from("hello"));
+        entries.insert(String::from("secret")).expires_at = Some(SystemTime::UNIX_EPOCH);
+        assert_eq!(entries.remove_expired(SystemTime::now()), 1);
+        assert_eq!(entries.hashes.len(), 1);
+        assert_eq!(entries.get(0).unwrap().value, "hello");
+    }
+
+    #[test]
+    fn test_entries_get_tag_counts() {
+        let mut entries = Entries::default();
+        entries.insert(String::from("hello"));
+        entries.insert(String::from("world"));
+        entries.tag(0, String::from("b"));
+        entries.tag(1, String::from("b"));
+        entries.tag(1, String::from("a"));
+        assert_eq!(
+            entries.get_tag_counts(),
+            vec![(String::from("a"), 1), (String::from("b"), 2)]
+        );
+    }
+
+    #[test]
+    fn test_entries_ring() {
+        let mut entries = Entries::default();
+        for value in ["a", "b", "b", "c", "c", "c", "d"] {
+            entries.insert(String::from(value));
+        }
+        entries.pin(3, 'X');
+        let ring: Vec<String> = entries
+            .ring(3)
+            .into_iter()
+            .map(|(_, item)| item.value)
+            .collect();
+        assert_eq!(ring, vec!["a", "c", "b"]);
+
+        entries.pin_ring(2);
+        assert_eq!(entries.select_by_pin('1')[0].1.value, "c");
+        assert_eq!(entries.select_by_pin('2')[0].1.value, "b");
+        assert!(entries.get(0).unwrap().pin.is_none());
+        assert_eq!(entries.select_by_pin('X')[0].1.value, "a");
+    }
+
+    #[test]
+    fn test_rebuild() {
+        let mut entries = Entries::default();
+        for value in ["a", "b", "c"] {
+            entries.insert(String::from(value));
+        }
+        assert!(entries.is_consistent());
+        entries.hashes.pop_back();
+        entries.get(0).unwrap().value = String::from("b");
+        entries.get(0).unwrap().favorite = true;
+        assert!(!entries.is_consistent());
+        let report = entries.rebuild(|item| Ok(calculate_hash(&item.value)), DedupMode::Global);
+        assert_eq!(
+            (report.values, report.hashes, report.rehashed, report.merged),
+            (3, 2, 2, 1)
+        );
+        assert!(entries.is_consistent());
+        assert_eq!(entries.get_value(0).as_deref(), Some("b"));
+        assert!(entries.get_item(0).unwrap().favorite);
+        assert_eq!(entries.get_value(1).as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn test_hash_migration() {
+        let mut entries = Entries::default();
+        entries.insert(String::from("a"));
+        let mut old = serde_json::to_value(&entries).unwrap();
+        old.as_object_mut().unwrap().remove("version");
+        old["values"][0].as_object_mut().unwrap().remove("hash");
+        old["hashes"][0] = serde_json::json!(42);
+        let mut loaded: Entries = serde_json::from_value(old).unwrap();
+        assert!(!loaded.is_consistent());
+        loaded.rebuild(|item| Ok(calculate_hash(&item.value)), DedupMode::Global);
+        assert!(loaded.is_consistent());
+        assert_eq!(
+            loaded.get_item(0).unwrap().hash,
+            entries.hash_at(0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_reloaded_dedup() {
+        let mut entries = Entries::default();
+        entries.insert(String::from("a"));
+        entries.insert(String::from("b"));
+        let data = serde_json::to_string(&entries.persistent()).unwrap();
+        let mut loaded: Entries = serde_json::from_str(&data).unwrap();
+        assert!(loaded.is_consistent());
+        loaded.insert(String::from("a"));
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded.get_item(0).unwrap().access_counter, 2);
+    }
+
+    #[test]
+    fn test_own_writes() {
+        let state = State::new(Config::default());
+        let hash = calculate_hash(&String::from("value"));
+        state.record_write(None, "value");
+        assert!(!state.take_own_write(Some("find"), hash));
+        assert!(state.take_own_write(None, hash));
+        assert!(!state.take_own_write(None, hash));
+        // any other change drops pending write
+        state.record_write(None, "value");
+        assert!(!state.take_own_write(None, 0));
+        assert!(!state.take_own_write(None, hash));
+    }
+
+    #[test]
+    fn test_entries_select_pins() {
+        let mut entries = Entries::default();
+        for value in ["a", "b", "c"] {
+            entries.insert(String::from(value));
+        }
+        entries.pin(0, '1');
+        entries.pin(1, '2');
+        entries.pin(2, 'X');
+        entries.tag(1, String::from("work"));
+        let values = |filter: &Filter| -> Vec<String> {
+            entries
+                .select(filter)
+                .into_iter()
+                .map(|(_, item)| item.value)
+                .collect()
+        };
+        let mut filter = Filter {
+            pin: Some(String::from("12x")),
+            ..Filter::default()
+        };
+        assert_eq!(values(&filter), vec!["c", "b", "a"]);
+        filter.tag = vec![String::from("work")];
+        assert_eq!(values(&filter), vec!["b"]);
+        filter.pin = Some(String::from("1"));
+        assert!(values(&filter).is_empty());
+    }
+
+    #[test]
+    fn test_entries_select_by_note() {
+        let mut entries = Entries::default();
+        entries.insert(String::from("a1b2c3"));
+        entries.insert(String::from("hello"));
+        assert!(entries.note(1, Some(String::from("Staging API token"))));
+        let filter = Filter {
+            note: Some(String::from("api")),
+            ..Filter::default()
+        };
+        let items = entries.select(&filter);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].1.value, "a1b2c3");
+    }
+
+    #[test]
+    fn test_entries_delete_keeps_favorites() {
+        let mut entries = Entries::default();
+        for value in ["a", "b", "c", "d"] {
+            entries.insert(String::from(value));
+        }
+        entries.favorite(1, true);
+        entries.delete(0, Some(3));
+        assert_eq!(entries.hashes.len(), 2);
+        assert_eq!(entries.get(0).unwrap().value, "c");
+        entries.delete(0, None);
+        assert_eq!(entries.get(0).unwrap().value, "a");
+        assert!(entries.is_consistent());
+    }
+
+    #[test]
+    fn test_entries_trash() {
+        let mut entries = Entries::default();
+        for value in ["a", "b", "c"] {
+            entries.insert(String::from(value));
+        }
+        entries.delete(0, Some(2));
+        let trashed: Vec<&str> = entries
+            .trash
+            .iter()
+            .map(|t| t.item.value.as_str())
+            .collect();
+        assert_eq!(trashed, vec!["b", "c"]);
+        let index = entries
+            .trash_index(&IndexOrId::Id(entries.trash[1].item.id.clone()))
+            .unwrap();
+        assert_eq!(entries.restore(index), 0);
+        assert_eq!(entries.get_value(0).as_deref(), Some("c"));
+        assert!(entries.is_consistent());
+        // copy captured again meanwhile
+        entries.insert(String::from("b"));
+        assert_eq!(entries.restore(0), 0);
+        assert_eq!(entries.len(), 3);
+        entries.delete(0, None);
+        assert_eq!(
+            entries.purge_trash(SystemTime::now() + Duration::from_secs(1)),
+            1
+        );
+        assert!(entries.trash.is_empty());
+    }
+
+    #[test]
+    fn test_append_join() {
+        let mut entries = Entries::new();
+        entries.insert("old".to_string());
+        let mut append = Append::new(" / ".to_string());
+        for value in ["a", "b", "c"] {
+            entries.insert_joined(&mut append, value.to_string());
+        }
+        assert_eq!(append.count, 3);
+        assert_eq!(entries.get_value(0).as_deref(), Some("a / b / c"));
+        assert_eq!(entries.get_value(1).as_deref(), Some("a"));
+        assert_eq!(entries.get_value(2).as_deref(), Some("old"));
+        assert_eq!(entries.len(), 3);
+        assert!(entries.is_consistent());
+    }
+
+    #[test]
+    fn test_insert_dedup() {
+        let mut entries = Entries::new();
+        entries.insert(String::from("a")).accessed_at =
+            SystemTime::now() - Duration::from_secs(7200);
+        entries.insert(String::from("b"));
+        let window = "window:1h".parse::<DedupMode>().unwrap();
+        entries.insert_dedup(String::from("b"), window);
+        assert_eq!(entries.len(), 2);
+        entries.insert_dedup(String::from("a"), window);
+        assert_eq!(entries.len(), 3);
+        entries.insert_dedup(String::from("a"), DedupMode::Off);
+        assert_eq!(entries.len(), 4);
+        entries.insert(String::from("b"));
+        assert_eq!(entries.get_value(0).as_deref(), Some("b"));
+        assert_eq!(entries.len(), 4);
+        assert!(entries.is_consistent());
+        assert!("window:1w".parse::<DedupMode>().is_err());
+        assert_eq!("off".parse(), Ok(DedupMode::Off));
+    }
+
+    #[test]
+    fn test_frecency_evict() {
+        let mut entries = Entries::new();
+        let old = entries.insert(String::from("old"));
+        old.access_counter = 10;
+        old.score = Some(10.0);
+        old.accessed_at = SystemTime::now() - FRECENCY_HALF_LIFE * 4;
+        entries.insert(String::from("fresh")).touch();
+        entries.insert(String::from("new"));
+        let ranked: Vec<usize> = entries
+            .select_ranked(None, None, None)
+            .into_iter()
+            .map(|(index, _)| index)
+            .collect();
+        assert_eq!(ranked, vec![1, 0, 2]);
+        entries.get(2).unwrap().favorite = true;
+        assert_eq!(entries.evict(2), 1);
+        assert_eq!(entries.get_value(0).as_deref(), Some("fresh"));
+        assert_eq!(entries.get_value(1).as_deref(), Some("old"));
+        assert!(entries.is_consistent());
+    }
+
+    #[test]
+    fn test_entries_mirror() {
+        let mut entries = Entries::new();
+        let a = Item::new(String::from("a"));
+        let mut b = Item::new(String::from("b"));
+        entries.mirror(a.clone());
+        entries.mirror(b.clone());
+        b.note = Some(String::from("touched on primary"));
+        entries.mirror(a);
+        entries.mirror(b);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries.get_value(0).as_deref(), Some("b"));
+        assert_eq!(
+            entries.get_item(0).and_then(|item| item.note.as_deref()),
+            Some("touched on primary")
+        );
+        assert!(entries.is_consistent());
+        assert!(Command::Ring.is_allowed_mirror());
+        assert!(!Command::Rebuild.is_allowed_mirror());
+    }
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("90"), Ok(90));
+        assert_eq!(parse_age("30m"), Ok(1800));
+        assert_eq!(parse_age("7d"), Ok(604800));
+        assert!(parse_age("7w").is_err());
+        assert!(parse_age("d").is_err());
+    }
+
+    #[test]
+    fn test_entries_prune() {
+        let mut entries = Entries::default();
+        for value in ["foo1", "bar", "foo2", "foo3"] {
+            entries.insert(String::from(value));
+        }
+        entries.favorite(0, true);
+        let filter = Filter {
+            regex: Some(String::from("^foo\\d$")),
+            ..Filter::default()
+        };
+        assert_eq!(entries.prune(&filter, true).len(), 2);
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries.prune(&filter, false).len(), 2);
+        let values: Vec<&str> = entries.values.iter().map(|i| i.value.as_str()).collect();
+        assert_eq!(values, vec!["foo3", "bar"]);
+    }
+
+    #[test]
+    fn test_entries_clear() {
+        let mut entries = Entries::default();
+        for value in ["a", "b", "c"] {
+            entries.insert(String::from(value));
+        }
+        entries.pin(0, 'A');
+        entries.favorite(2, true);
+        entries.clear(true);
+        assert_eq!(entries.hashes.len(), 2);
+        entries.clear(false);
+        assert!(entries.is_empty());
+    }
+
+    #[test]
+    fn test_entries_dedup() {
+        let mut entries = Entries::default();
+        for value in ["a\r\nb", "c", "a b", "a b", "a\nb "] {
+            entries.insert(String::from(value));
+        }
+        entries.tag(3, String::from("old"));
+        let groups = entries.dedup(true);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0][0].1.value, "a b");
+        assert_eq!(entries.len(), 4);
+
+        entries.dedup(false);
+        assert_eq!(entries.len(), 2);
+        let item = entries.get(0).unwrap();
+        assert_eq!(item.value, "a b");
+        assert!(item.tags.as_ref().unwrap().contains("old"));
+    }
+
+    #[test]
+    fn test_dedup_keychain() {
+        let mut entries = Entries::default();
+        for value in ["secret", "password"] {
+            entries.insert(String::from(value));
+            let item = entries.get(0).unwrap();
+            item.value = String::from("<keychain>");
+            item.keychain = true;
+        }
+        entries.dedup(false);
+        assert_eq!(entries.len(), 2);
+        // keychain is locked, placeholders must not be hashed and merged
+        let report = entries.rebuild(|_| anyhow::bail!("locked"), DedupMode::Global);
+        assert_eq!(report.merged, 0);
+        assert_eq!(entries.len(), 2);
+        assert!(entries.is_consistent());
+
+        for value in ["large", "larger"] {
+            entries.insert(String::from(value));
+            let item = entries.get(0).unwrap();
+            item.value = String::from("<spilled>");
+            item.spilled = true;
+        }
+        entries.dedup(false);
+        assert_eq!(entries.len(), 4);
+    }
+
+    #[test]
+    fn test_entries_resolve() {
+        let mut entries = Entries::default();
+        entries.insert(String::from("a"));
+        let id = entries.get(0).unwrap().id.clone();
+        entries.insert(String::from("b"));
+        assert_eq!(entries.resolve(&IndexOrId::from(id.as_str())), Some(1));
+        assert_eq!(entries.resolve(&"1".parse().unwrap()), Some(1));
+        assert_eq!(entries.resolve(&IndexOrId::Index(2)), None);
+        assert_eq!(entries.resolve(&IndexOrId::from("unknown")), None);
+    }
+
+    #[test]
+    fn test_entries_hash_at() {
+        let mut entries = Entries::default();
+        entries.insert(String::from("a"));
+        entries.insert(String::from("b"));
+        assert_eq!(entries.hash_at(1), Some(calculate_hash(&String::from("a"))));
+        assert_eq!(entries.hash_at(2), None);
+    }
+
+    #[test]
+    fn test_entries_select_by_range() {
+        let mut entries = Entries::default();
+        for value in ["a", "b\nsecond line", "c"] {
+            entries.insert(String::from(value));
+        }
+        let items = entries.select_by_range(Some(1), None, None);
+        assert_eq!(items.len(), 2);
+        let (index, item) = &items[0];
+        assert_eq!(*index, 1);
+        assert_eq!(item.preview, "b...");
+        assert_eq!(item.hash, calculate_hash(&String::from("b\nsecond line")));
+    }
+
     #[test]
     fn test_entries_get() {
         let value = String::from("hello");
@@ -584,4 +3288,110 @@ mod test {
         entries.insert(value.clone());
         assert_eq!(entries.get(0).unwrap().value, value);
     }
+
+    #[test]
+    fn test_config_apply_env() {
+        let vars = HashMap::from([
+            ("CLIPR_PORT", "1600"),
+            ("CLIPR_DB", "/tmp/clipr.json"),
+            ("CLIPR_UNFURL", "true"),
+        ]);
+        let mut config = Config::default();
+        config
+            .apply_env(|name| vars.get(name).map(|value| value.to_string()))
+            .unwrap();
+        assert_eq!(config.port, Some(1600));
+        assert_eq!(config.host.as_deref(), Some("127.0.0.1"));
+        assert_eq!(config.db_path(), Path::new("/tmp/clipr.json"));
+        assert_eq!(config.unfurl, Some(true));
+
+        let err = config
+            .apply_env(|name| (name == "CLIPR_PORT").then(|| String::from("http")))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "invalid CLIPR_PORT value: http");
+    }
+
+    #[test]
+    fn test_http_access() {
+        let config: HttpConfig =
+            toml::from_str(r#"tokens = [{ token = "a" }, { token = "r", access = "read" }]"#)
+                .unwrap();
+        assert_eq!(config.access(Some("a")), Some(Access::Full));
+        assert_eq!(config.access(Some("r")), Some(Access::Read));
+        assert_eq!(config.access(Some("x")), None);
+        assert_eq!(config.access(None), None);
+        assert_eq!(HttpConfig::default().access(None), Some(Access::Full));
+        assert!(Command::Tags.is_read_access());
+        assert!(!Command::Quit.is_read_access());
+    }
+
+    #[test]
+    fn test_entries_persistent() {
+        let mut entries = Entries::default();
+        entries.insert(String::from("saved"));
+        let item = entries.insert(String::from("secret"));
+        item.ephemeral = true;
+        item.favorite = true;
+        let persistent = entries.persistent();
+        assert_eq!(persistent.len(), 1);
+        assert_eq!(persistent.values.front().unwrap().value, "saved");
+        assert_eq!(entries.remove_ephemeral(), 1);
+        assert_eq!(entries.len(), 1);
+    }
+
+    // cli, emacs module and text protocol parse command lines into Command,
+    // client and daemon exchange it as JSON, so both forms have to agree
+    #[test]
+    fn test_command_wire_format() {
+        let parse = |line: &str| {
+            Args::try_parse_from(format!("clipr {line}").split_whitespace())
+                .unwrap()
+                .command
+                .unwrap()
+        };
+        let list = serde_json::to_value(parse("list 0 10")).unwrap();
+        assert_eq!(
+            list,
+            serde_json::json!({
+                "type": "list",
+                "from_index": 0,
+                "to_index": 10,
+                "preview_length": null,
+                "favorites": false,
+                "ranked": false,
+                "template": null,
+                "pin": null,
+                "tag": [],
+                "value": null,
+                "app": null,
+                "note": null,
+                "title": null,
+                "regex": null,
+                "older_than": null,
+                "newer_than": null,
+                "kind": null
+            })
+        );
+        for line in [
+            "list --tag work --kind url",
+            "get 3",
+            "get-many 1 2",
+            "select --app Slack",
+            "tag 0 secret",
+            "del 0 5",
+        ] {
+            let json = serde_json::to_value(parse(line)).unwrap();
+            let cmd: Command = serde_json::from_value(json.clone()).unwrap();
+            assert_eq!(serde_json::to_value(cmd).unwrap(), json, "{line}");
+        }
+        // older clients send `index`
+        let get: Command = serde_json::from_str(r#"{"type": "get", "index": 3}"#).unwrap();
+        assert!(matches!(
+            get,
+            Command::Get {
+                target: IndexOrId::Index(3),
+                ..
+            }
+        ));
+    }
 }
@@ -0,0 +1,53 @@
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+pub const DEFAULT_UNLOCK_TIMEOUT: u64 = 5 * 60;
+
+// `[lock]` config, daemon answers everything but unlock, lock, status and
+// help with Payload::Locked until unlocked (see Command::Unlock).
+#[derive(Clone, Debug, Deserialize)]
+pub struct LockConfig {
+    // hex, e.g. `printf %s 'passphrase' | shasum -a 256`
+    pub passphrase_sha256: String,
+    // seconds, unlock expires after it
+    pub timeout: Option<u64>,
+}
+
+impl LockConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout.unwrap_or(DEFAULT_UNLOCK_TIMEOUT))
+    }
+
+    pub fn verify(&self, passphrase: &str) -> bool {
+        hex::encode(Sha256::digest(passphrase.as_bytes()))
+            .eq_ignore_ascii_case(self.passphrase_sha256.trim())
+    }
+}
+
+// lines with passphrase, they are kept out of repl/shell history
+pub fn has_passphrase(line: &str) -> bool {
+    line.split_whitespace().next() == Some("unlock")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let config = LockConfig {
+            // sha256("hunter2")
+            passphrase_sha256: String::from(
+                "F52FBD32B2B3B86FF88EF6C490628285F482AF15DDCB29541F94BCF526A3F6C7",
+            ),
+            timeout: None,
+        };
+        assert!(config.verify("hunter2"));
+        assert!(!config.verify("hunter3"));
+        assert_eq!(
+            config.timeout(),
+            Duration::from_secs(DEFAULT_UNLOCK_TIMEOUT)
+        );
+    }
+}
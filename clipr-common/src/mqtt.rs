@@ -0,0 +1,193 @@
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+pub const DEFAULT_TOPIC: &str = "clipr/items";
+pub const DEFAULT_COMMAND_TOPIC: &str = "clipr/command";
+pub const DEFAULT_KEEP_ALIVE: u16 = 60;
+
+// Captured items are published (as JSON Item) to `topic`, JSON commands
+// published to `command_topic` are run and answered on `<command_topic>/reply`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct MqttConfig {
+    // host:port, plain TCP (1883)
+    pub broker: String,
+    pub client_id: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub topic: Option<String>,
+    pub command_topic: Option<String>,
+    // seconds
+    pub keep_alive: Option<u16>,
+}
+
+impl MqttConfig {
+    pub fn topic(&self) -> &str {
+        self.topic.as_deref().unwrap_or(DEFAULT_TOPIC)
+    }
+
+    pub fn command_topic(&self) -> &str {
+        self.command_topic
+            .as_deref()
+            .unwrap_or(DEFAULT_COMMAND_TOPIC)
+    }
+
+    pub fn reply_topic(&self) -> String {
+        format!("{}/reply", self.command_topic())
+    }
+
+    pub fn keep_alive(&self) -> u16 {
+        self.keep_alive.unwrap_or(DEFAULT_KEEP_ALIVE)
+    }
+}
+
+// Minimal MQTT 3.1.1 client side: QoS 0 publishes, one subscription, pings.
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const SUBSCRIBE: u8 = 0x82;
+const SUBACK: u8 = 0x90;
+const PINGREQ: u8 = 0xc0;
+const PINGRESP: u8 = 0xd0;
+
+#[derive(Debug, PartialEq)]
+pub enum Packet {
+    // return code, 0 is accepted
+    ConnAck(u8),
+    Publish { topic: String, payload: Vec<u8> },
+    SubAck,
+    PingResp,
+    // anything else broker may send (e.g. PUBACK), ignored
+    Other(u8),
+}
+
+fn push_str(buf: &mut Vec<u8>, value: &str) {
+    buf.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    buf.extend_from_slice(value.as_bytes());
+}
+
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut buf = vec![header];
+    let mut len = body.len();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        buf.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+    buf.extend_from_slice(body);
+    buf
+}
+
+pub fn connect(config: &MqttConfig, client_id: &str) -> Vec<u8> {
+    let mut body = vec![];
+    push_str(&mut body, "MQTT");
+    body.push(4); // protocol level 3.1.1
+    let mut flags = 0x02; // clean session
+    if config.username.is_some() {
+        flags |= 0x80;
+    }
+    if config.password.is_some() {
+        flags |= 0x40;
+    }
+    body.push(flags);
+    body.extend_from_slice(&config.keep_alive().to_be_bytes());
+    push_str(&mut body, client_id);
+    for value in [&config.username, &config.password].into_iter().flatten() {
+        push_str(&mut body, value);
+    }
+    packet(CONNECT, &body)
+}
+
+pub fn publish(topic: &str, payload: &[u8]) -> Vec<u8> {
+    let mut body = vec![];
+    push_str(&mut body, topic);
+    body.extend_from_slice(payload);
+    packet(PUBLISH, &body)
+}
+
+pub fn subscribe(packet_id: u16, topic: &str) -> Vec<u8> {
+    let mut body = packet_id.to_be_bytes().to_vec();
+    push_str(&mut body, topic);
+    body.push(0); // QoS 0
+    packet(SUBSCRIBE, &body)
+}
+
+pub fn ping() -> Vec<u8> {
+    packet(PINGREQ, &[])
+}
+
+// Remaining length of packet, None while more bytes are needed, `bytes`
+// are the ones following fixed header type byte.
+pub fn remaining_length(bytes: &[u8]) -> Result<Option<(usize, usize)>> {
+    let mut len = 0;
+    for (i, byte) in bytes.iter().enumerate().take(4) {
+        len += ((byte & 0x7f) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(Some((len, i + 1)));
+        }
+    }
+    if bytes.len() >= 4 {
+        bail!("malformed remaining length");
+    }
+    Ok(None)
+}
+
+pub fn decode(header: u8, body: &[u8]) -> Result<Packet> {
+    Ok(match header & 0xf0 {
+        CONNACK => Packet::ConnAck(*body.get(1).unwrap_or(&0xff)),
+        PUBLISH => {
+            let Some(len) = body
+                .get(..2)
+                .map(|len| u16::from_be_bytes([len[0], len[1]]))
+            else {
+                bail!("short publish");
+            };
+            let topic_end = 2 + len as usize;
+            let Some(topic) = body.get(2..topic_end) else {
+                bail!("short publish");
+            };
+            // QoS 1/2 publishes carry packet id, subscription is QoS 0 but
+            // broker may still downgrade from retained ones
+            let payload_start = if header & 0x06 != 0 {
+                topic_end + 2
+            } else {
+                topic_end
+            };
+            Packet::Publish {
+                topic: String::from_utf8_lossy(topic).into_owned(),
+                payload: body.get(payload_start..).unwrap_or_default().to_vec(),
+            }
+        }
+        SUBACK => Packet::SubAck,
+        PINGRESP => Packet::PingResp,
+        other => Packet::Other(other),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_packets() {
+        let publish = publish("clipr/items", b"{}");
+        assert_eq!(&publish[..4], &[0x30, 15, 0, 11]);
+        let (len, len_bytes) = remaining_length(&publish[1..]).unwrap().unwrap();
+        assert_eq!((len, len_bytes), (15, 1));
+        assert_eq!(
+            decode(publish[0], &publish[2..]).unwrap(),
+            Packet::Publish {
+                topic: String::from("clipr/items"),
+                payload: b"{}".to_vec()
+            }
+        );
+        assert_eq!(remaining_length(&[0xc1, 0x02]).unwrap(), Some((321, 2)));
+        assert_eq!(remaining_length(&[0xc1]).unwrap(), None);
+        assert_eq!(decode(0x20, &[0, 0]).unwrap(), Packet::ConnAck(0));
+    }
+}
@@ -0,0 +1,89 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+const START: &[u8] = b"\x1b]52;";
+const BEL: u8 = 0x07;
+const ST: &[u8] = b"\x1b\\";
+// unterminated sequences longer than that are dropped
+const MAX_LEN: usize = 16 * 1024 * 1024;
+
+// Streaming decoder for OSC52 clipboard writes: `ESC ] 52 ; Pc ; base64 (BEL | ESC \)`.
+// Everything around sequences (regular terminal output) is ignored.
+#[derive(Default)]
+pub struct Decoder {
+    buf: Vec<u8>,
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn decode(body: &[u8]) -> Option<String> {
+    // body is `Pc;Pd`, Pd == "?" is a clipboard query
+    let data = &body[body.iter().position(|c| *c == b';')? + 1..];
+    if data == b"?" {
+        return None;
+    }
+    let bytes = STANDARD.decode(data).ok()?;
+    let value = String::from_utf8_lossy(&bytes).to_string();
+    (!value.is_empty()).then_some(value)
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, data: &[u8]) -> Vec<String> {
+        self.buf.extend_from_slice(data);
+        let mut values = vec![];
+
+        loop {
+            let Some(start) = find(&self.buf, START) else {
+                // keep possible partial START
+                let keep = self.buf.len().min(START.len() - 1);
+                self.buf.drain(..self.buf.len() - keep);
+                break;
+            };
+            let body_start = start + START.len();
+            let body = &self.buf[body_start..];
+            let end = match (body.iter().position(|c| *c == BEL), find(body, ST)) {
+                (Some(bel), Some(st)) if st < bel => Some((st, ST.len())),
+                (Some(bel), _) => Some((bel, 1)),
+                (None, Some(st)) => Some((st, ST.len())),
+                (None, None) => None,
+            };
+            match end {
+                Some((end, terminator_len)) => {
+                    values.extend(decode(&body[..end]));
+                    self.buf.drain(..body_start + end + terminator_len);
+                }
+                None => {
+                    self.buf.drain(..start);
+                    if self.buf.len() > MAX_LEN {
+                        self.buf.clear();
+                    }
+                    break;
+                }
+            }
+        }
+
+        values
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decoder() {
+        let mut decoder = Decoder::new();
+        // "hello" with BEL, "world" with ST, split across reads, query is skipped
+        let values = decoder.feed(b"noise\x1b]52;c;aGVsbG8=\x07\x1b]52;c;?\x07\x1b]52;");
+        assert_eq!(values, vec!["hello".to_string()]);
+        let values = decoder.feed(b"c;d29ybGQ=\x1b\\more noise");
+        assert_eq!(values, vec!["world".to_string()]);
+        assert!(decoder.buf.len() < START.len());
+    }
+}
@@ -0,0 +1,61 @@
+use crate::{Command, Payload};
+use std::time::Duration;
+
+// How chatty cli, its shell and daemon REPL are: `-q` leaves out bare `ok`
+// replies (errors are printed anyway), `-v` adds request JSON and response
+// time, `-vv` daemon address too. Extra lines go to stderr, so stdout stays
+// fine for pipes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Quiet,
+    #[default]
+    Normal,
+    Verbose,
+    Debug,
+}
+
+impl Verbosity {
+    pub fn new(quiet: bool, verbose: u8) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, 0) => Verbosity::Normal,
+            (false, 1) => Verbosity::Verbose,
+            _ => Verbosity::Debug,
+        }
+    }
+
+    // before cmd is sent, address is None for in-process daemon
+    pub fn request(self, cmd: &Command, address: Option<&str>) {
+        if self >= Verbosity::Debug {
+            eprintln!("daemon: {}", address.unwrap_or("in-process"));
+        }
+        if self >= Verbosity::Verbose {
+            eprintln!("> {}", serde_json::to_string(cmd).unwrap_or_default());
+        }
+    }
+
+    pub fn response(self, elapsed: Duration) {
+        if self >= Verbosity::Verbose {
+            eprintln!("< {:.1} ms", elapsed.as_secs_f64() * 1000.0);
+        }
+    }
+
+    // false for replies not worth printing
+    pub fn shows(self, payload: &Payload) -> bool {
+        self > Verbosity::Quiet || !matches!(payload, Payload::Ok)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_verbosity() {
+        assert_eq!(Verbosity::new(true, 2), Verbosity::Quiet);
+        assert_eq!(Verbosity::new(false, 3), Verbosity::Debug);
+        assert!(!Verbosity::Quiet.shows(&Payload::Ok));
+        assert!(Verbosity::Quiet.shows(&Payload::Locked));
+        assert!(Verbosity::Normal.shows(&Payload::Ok));
+    }
+}
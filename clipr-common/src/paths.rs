@@ -0,0 +1,104 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+const CONFIG_FILE: &str = "config.toml";
+const DB_FILE: &str = "db.json";
+const LOG_FILE: &str = "clipr.log";
+const PID_FILE: &str = "clipr.pid";
+
+fn home() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+// XDG dir from env (only absolute paths count, per spec) or its default under home
+fn xdg_dir(var: &str, default: &str) -> Option<PathBuf> {
+    env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| home().map(|home| home.join(default)))
+        .map(|dir| dir.join("clipr"))
+}
+
+// `~` and `~/...` are resolved against $HOME, other paths are kept as is
+pub fn expand_tilde(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), home()) {
+        (Some(""), Some(home)) => home,
+        (Some(rest), Some(home)) if rest.starts_with('/') => home.join(&rest[1..]),
+        _ => PathBuf::from(path),
+    }
+}
+
+// Searched in order: $XDG_CONFIG_HOME/clipr (~/.config/clipr),
+// ~/Library/Application Support/clipr.
+pub fn config_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![];
+    dirs.extend(xdg_dir("XDG_CONFIG_HOME", ".config"));
+    dirs.extend(home().map(|home| home.join("Library/Application Support/clipr")));
+    dirs
+}
+
+// where `clipr-cli init` writes config: Application Support on macOS
+// unless $XDG_CONFIG_HOME is set, XDG one elsewhere
+pub fn config_dir() -> Option<PathBuf> {
+    let dirs = config_dirs();
+    if cfg!(target_os = "macos") && env::var_os("XDG_CONFIG_HOME").is_none() {
+        dirs.last().cloned()
+    } else {
+        dirs.first().cloned()
+    }
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join(CONFIG_FILE))
+}
+
+pub fn find_config() -> Option<PathBuf> {
+    config_dirs()
+        .into_iter()
+        .map(|dir| dir.join(CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+// $XDG_DATA_HOME/clipr (~/.local/share/clipr), db and repl history live there
+pub fn data_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+// falls back to working directory without $HOME
+fn data_file(name: &str) -> PathBuf {
+    data_dir().map_or_else(|| Path::new(".").join(name), |dir| dir.join(name))
+}
+
+pub fn default_db_path() -> PathBuf {
+    data_file(DB_FILE)
+}
+
+// output of daemonized daemon (see Args::daemonize)
+pub fn default_log_path() -> PathBuf {
+    data_file(LOG_FILE)
+}
+
+pub fn default_pid_path() -> PathBuf {
+    data_file(PID_FILE)
+}
+
+// shared by daemon repl and `clipr-cli shell`
+pub fn repl_history_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("repl_history"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_expand_tilde() {
+        let home = home().unwrap();
+        assert_eq!(expand_tilde("~"), home);
+        assert_eq!(expand_tilde("~/clipr/db.json"), home.join("clipr/db.json"));
+        assert_eq!(expand_tilde("~user/db.json"), Path::new("~user/db.json"));
+        assert_eq!(expand_tilde("./db.json"), Path::new("./db.json"));
+    }
+}
@@ -0,0 +1,33 @@
+use crate::{Entries, Item, SourceApp};
+use anyhow::Result;
+
+// Extension point for commands handled inside the daemon.
+// `clipr plugin NAME [ARGS]...` is dispatched to plugin with matching name,
+// returned JSON value is sent back as `Payload::Plugin`.
+pub trait CommandPlugin: Send + Sync {
+    fn name(&self) -> &str;
+
+    // shown by `plugins` command
+    fn help(&self) -> &str {
+        ""
+    }
+
+    fn call(&self, entries: &mut Entries, args: Vec<String>) -> Result<serde_json::Value>;
+
+    // capture hooks: `false` drops captured value before it gets into history
+    fn filter(&self, _value: &str, _app: Option<&SourceApp>) -> bool {
+        true
+    }
+
+    // called for every inserted (or touched) item
+    fn on_insert(&self, _item: &mut Item, _app: Option<&SourceApp>) {}
+}
+
+pub type Plugins = Vec<Box<dyn CommandPlugin>>;
+
+pub fn find_plugin<'a>(plugins: &'a Plugins, name: &str) -> Option<&'a dyn CommandPlugin> {
+    plugins
+        .iter()
+        .find(|plugin| plugin.name() == name)
+        .map(|plugin| plugin.as_ref())
+}
@@ -0,0 +1,138 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+// Positional index of `del` and `list`: `3`, `-1` (negative ones count from
+// history end, -1 is the oldest item) or range `3..7` (end excluded), `3..`
+// and `..20`. Sent as plain number unless it's a range.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Position {
+    Index(i64),
+    Range(Option<i64>, Option<i64>),
+}
+
+// index into history of len items, None when it's out of range
+pub fn resolve(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    usize::try_from(index).ok().filter(|index| *index < len)
+}
+
+// range end, clamped to history
+fn bound(index: i64, len: usize) -> usize {
+    let index = if index < 0 { len as i64 + index } else { index };
+    index.clamp(0, len as i64) as usize
+}
+
+// [from, to) of positional args, to stays None when only single index is
+// given (`del 3` is one item, `list 3` lists from 3 on). Out of range index
+// resolves to len, so nothing is touched.
+pub fn span(
+    from: Option<Position>,
+    to: Option<i64>,
+    len: usize,
+) -> Result<(usize, Option<usize>), String> {
+    let to = to.map(|to| bound(to, len));
+    match from {
+        None => Ok((0, to)),
+        Some(Position::Index(index)) => Ok((resolve(index, len).unwrap_or(len), to)),
+        Some(Position::Range(..)) if to.is_some() => {
+            Err(String::from("range can't be followed by to-index"))
+        }
+        Some(Position::Range(from, to)) => Ok((
+            from.map_or(0, |from| bound(from, len)),
+            Some(to.map_or(len, |to| bound(to, len))),
+        )),
+    }
+}
+
+impl FromStr for Position {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let index = |s: &str| {
+            s.parse::<i64>()
+                .map_err(|_| format!("invalid index: {s:?}"))
+        };
+        let bound = |s: &str| (!s.is_empty()).then(|| index(s)).transpose();
+        match s.split_once("..") {
+            Some((from, to)) => Ok(Position::Range(bound(from)?, bound(to)?)),
+            None => index(s).map(Position::Index),
+        }
+    }
+}
+
+impl From<usize> for Position {
+    fn from(index: usize) -> Self {
+        Position::Index(index as i64)
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Position::Index(index) => write!(f, "{index}"),
+            Position::Range(from, to) => {
+                if let Some(from) = from {
+                    write!(f, "{from}")?;
+                }
+                write!(f, "..")?;
+                if let Some(to) = to {
+                    write!(f, "{to}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Serialize for Position {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Position::Index(index) => serializer.serialize_i64(*index),
+            Position::Range(..) => serializer.collect_str(self),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Position {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Index(i64),
+            Range(String),
+        }
+        match Raw::deserialize(deserializer)? {
+            Raw::Index(index) => Ok(Position::Index(index)),
+            Raw::Range(range) => range.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_span() {
+        let span = |from: &str, to: Option<i64>| span(Some(from.parse().unwrap()), to, 10);
+        assert_eq!(span("3", None), Ok((3, None)));
+        assert_eq!(span("-1", None), Ok((9, None)));
+        assert_eq!(span("-11", None), Ok((10, None)));
+        assert_eq!(span("3", Some(7)), Ok((3, Some(7))));
+        assert_eq!(span("3..7", None), Ok((3, Some(7))));
+        assert_eq!(span("3..", None), Ok((3, Some(10))));
+        assert_eq!(span("..20", None), Ok((0, Some(10))));
+        assert_eq!(span("-3..", None), Ok((7, Some(10))));
+        assert!(span("3..7", Some(8)).is_err());
+        assert!("3..x".parse::<Position>().is_err());
+        assert_eq!(
+            serde_json::to_string(&"..20".parse::<Position>().unwrap()).unwrap(),
+            r#""..20""#
+        );
+        assert_eq!(
+            serde_json::from_str::<Position>("3").unwrap(),
+            Position::Index(3)
+        );
+    }
+}
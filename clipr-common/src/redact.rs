@@ -0,0 +1,88 @@
+use crate::{Entries, Item, ItemKind, ItemSummary};
+use anyhow::Result;
+use regex::Regex;
+use std::borrow::Cow;
+
+pub const MASK: &str = "•••";
+
+// Display-time masking of `[display] redact` patterns, values stored in
+// history (and returned by get/set) aren't touched.
+#[derive(Clone, Debug, Default)]
+pub struct Redactor {
+    patterns: Vec<Regex>,
+}
+
+impl Redactor {
+    pub fn new(patterns: &[String]) -> Result<Self> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| Regex::new(pattern))
+            .collect::<Result<Vec<Regex>, _>>()?;
+        Ok(Self { patterns })
+    }
+
+    pub fn redact<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        let mut value = Cow::Borrowed(value);
+        for pattern in &self.patterns {
+            if let Cow::Owned(redacted) = pattern.replace_all(&value, MASK) {
+                value = Cow::Owned(redacted);
+            }
+        }
+        value
+    }
+
+    // copy with masked value, images are left as is
+    pub fn item<'a>(&self, item: &'a Item) -> Cow<'a, Item> {
+        if matches!(item.kind, ItemKind::Image { .. }) {
+            return Cow::Borrowed(item);
+        }
+        match self.redact(&item.value) {
+            Cow::Borrowed(_) => Cow::Borrowed(item),
+            Cow::Owned(value) => Cow::Owned(Item {
+                value,
+                ..item.clone()
+            }),
+        }
+    }
+
+    // Previews are rebuilt from masked full values, so secrets cut by
+    // shorten don't slip through partially.
+    pub fn summaries(
+        &self,
+        items: &mut [(usize, ItemSummary)],
+        entries: &Entries,
+        preview_length: Option<usize>,
+    ) {
+        if self.patterns.is_empty() {
+            return;
+        }
+        for (index, summary) in items.iter_mut() {
+            let Some(item) = entries.get_item(*index) else {
+                continue;
+            };
+            if let Cow::Owned(item) = self.item(item) {
+                summary.preview = ItemSummary::new(&item, summary.hash, preview_length).preview;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        let redactor = Redactor::new(&[
+            String::from(r"ghp_[A-Za-z0-9]+"),
+            String::from(r"(?i)password=\S+"),
+        ])
+        .unwrap();
+        assert_eq!(
+            redactor.redact("token ghp_abc123 and PASSWORD=hunter2"),
+            "token ••• and •••"
+        );
+        assert!(matches!(redactor.redact("plain"), Cow::Borrowed(_)));
+        assert!(Redactor::new(&[String::from("(")]).is_err());
+    }
+}
@@ -0,0 +1,112 @@
+use anyhow::{anyhow, bail, Result};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+pub const DEFAULT_CHANNEL: &str = "default";
+pub const DEFAULT_INTERVAL: u64 = 5;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RelayConfig {
+    pub url: String,
+    // hex encoded 32 bytes key, shared between all daemons on the channel
+    pub key: String,
+    pub channel: Option<String>,
+    // poll interval (seconds)
+    pub interval: Option<u64>,
+}
+
+impl RelayConfig {
+    pub fn channel_url(&self) -> String {
+        format!(
+            "{}/relay/{}",
+            self.url.trim_end_matches('/'),
+            self.channel.as_deref().unwrap_or(DEFAULT_CHANNEL)
+        )
+    }
+}
+
+// Relay only stores and returns opaque envelopes, it never sees the key.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Envelope {
+    pub seq: u64,
+    pub origin: String,
+    pub data: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Push {
+    pub origin: String,
+    pub data: String,
+}
+
+pub struct Cipher(ChaCha20Poly1305);
+
+impl Cipher {
+    pub fn from_hex(key: &str) -> Result<Self> {
+        let bytes = hex::decode(key.trim())?;
+        if bytes.len() != KEY_LEN {
+            bail!(
+                "relay key should be {KEY_LEN} bytes ({} hex chars)",
+                KEY_LEN * 2
+            );
+        }
+        Ok(Self(ChaCha20Poly1305::new(Key::from_slice(&bytes))))
+    }
+
+    // nonce || ciphertext, hex encoded
+    pub fn seal(&self, value: &str) -> Result<String> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .0
+            .encrypt(&nonce, value.as_bytes())
+            .map_err(|_| anyhow!("can't encrypt value"))?;
+        let mut data = nonce.to_vec();
+        data.extend(ciphertext);
+        Ok(hex::encode(data))
+    }
+
+    pub fn open(&self, data: &str) -> Result<String> {
+        let bytes = hex::decode(data)?;
+        if bytes.len() < NONCE_LEN {
+            bail!("envelope is too short");
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let plaintext = self
+            .0
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("can't decrypt envelope (wrong key?)"))?;
+        Ok(String::from_utf8(plaintext)?)
+    }
+}
+
+// random id used to skip own envelopes while pulling
+pub fn new_origin() -> String {
+    hex::encode(ChaCha20Poly1305::generate_nonce(&mut OsRng))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    #[test]
+    fn test_cipher_roundtrip() {
+        let cipher = Cipher::from_hex(KEY).unwrap();
+        let sealed = cipher.seal("hello").unwrap();
+        assert!(!sealed.contains(&hex::encode("hello")));
+        assert_eq!(cipher.open(&sealed).unwrap(), "hello");
+    }
+
+    #[test]
+    fn test_cipher_wrong_key() {
+        let sealed = Cipher::from_hex(KEY).unwrap().seal("hello").unwrap();
+        let other = Cipher::from_hex(&KEY.replace("00", "ff")).unwrap();
+        assert!(other.open(&sealed).is_err());
+        assert!(Cipher::from_hex("0011").is_err());
+    }
+}
@@ -0,0 +1,40 @@
+use crate::Item;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime};
+
+// Capture behavior for items copied in specific application.
+#[derive(Clone, Debug, Deserialize)]
+pub struct AppRule {
+    // e.g. "com.googlecode.iterm2"
+    pub bundle_id: String,
+    // `false` - never capture (banking apps, password managers)
+    pub capture: Option<bool>,
+    // always add these tags
+    pub tags: Option<Vec<String>>,
+    // drop item from history after N seconds
+    pub expire: Option<u64>,
+    // clean copied links even without `[clean_urls]` (false - never)
+    pub clean_urls: Option<bool>,
+}
+
+impl AppRule {
+    pub fn capture(&self) -> bool {
+        self.capture.unwrap_or(true)
+    }
+
+    pub fn apply(&self, item: &mut Item) {
+        if let Some(tags) = &self.tags {
+            item.tags
+                .get_or_insert_with(HashSet::new)
+                .extend(tags.iter().cloned());
+        }
+        if let Some(secs) = self.expire {
+            item.expires_at = Some(SystemTime::now() + Duration::from_secs(secs));
+        }
+    }
+}
+
+pub fn find_rule<'a>(rules: &'a [AppRule], bundle_id: &str) -> Option<&'a AppRule> {
+    rules.iter().find(|rule| rule.bundle_id == bundle_id)
+}
@@ -0,0 +1,163 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// Counts computed by daemon (see `get --stats`), so frontends can show them
+// without fetching value.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Stats {
+    pub chars: usize,
+    pub words: usize,
+    pub lines: usize,
+    pub bytes: usize,
+    // guessed for code snippets, None for prose and non-text items
+    pub language: Option<String>,
+}
+
+impl Stats {
+    pub fn new(value: &str, text: bool) -> Self {
+        Self {
+            chars: value.chars().count(),
+            words: value.split_whitespace().count(),
+            lines: value.lines().count(),
+            bytes: value.len(),
+            language: text
+                .then(|| detect_language(value))
+                .flatten()
+                .map(String::from),
+        }
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} lines, {} words, {} chars, {} bytes",
+            self.lines, self.words, self.chars, self.bytes
+        )?;
+        if let Some(language) = &self.language {
+            write!(f, ", looks like {language}")?;
+        }
+        Ok(())
+    }
+}
+
+// (language, markers), each marker occurrence counts
+const MARKERS: &[(&str, &[&str])] = &[
+    (
+        "Rust",
+        &[
+            "fn ", "let mut ", "impl ", "pub fn", "::", "-> ", "&self", "match ",
+        ],
+    ),
+    (
+        "Python",
+        &["def ", "import ", "self.", "elif ", "None", "print(", "__"],
+    ),
+    (
+        "JavaScript",
+        &[
+            "const ",
+            "function ",
+            "=> ",
+            "console.log",
+            "===",
+            "require(",
+        ],
+    ),
+    ("Go", &["func ", "package ", ":= ", "fmt.", "err != nil"]),
+    ("C", &["#include", "int main", "printf(", "->", "NULL"]),
+    (
+        "Shell",
+        &["echo ", "fi\n", "done\n", "$(", "then\n", "export "],
+    ),
+    (
+        "SQL",
+        &[
+            "SELECT ",
+            " FROM ",
+            "WHERE ",
+            "INSERT INTO",
+            "JOIN ",
+            "GROUP BY",
+        ],
+    ),
+    ("HTML", &["<div", "</", "<html", "<a ", "class=\""]),
+    (
+        "Lisp",
+        &["(defun ", "(let ", "(setq ", "(lambda ", "(require "],
+    ),
+];
+
+// fewer markers are likely words of plain text
+const MIN_MARKERS: usize = 3;
+// markers are looked for only at the start of long values (list payloads
+// guess language of every item)
+const SAMPLE_LEN: usize = 4096;
+
+// Rough guess from shebang, JSON syntax and counts of language markers.
+pub fn detect_language(value: &str) -> Option<&'static str> {
+    let value = value.trim();
+    if let Some(shebang) = value
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix("#!"))
+    {
+        let language = match shebang {
+            s if s.contains("python") => "Python",
+            s if s.contains("node") => "JavaScript",
+            s if s.contains("ruby") => "Ruby",
+            s if s.contains("perl") => "Perl",
+            _ => "Shell",
+        };
+        return Some(language);
+    }
+    if value.starts_with(['{', '[']) && serde_json::from_str::<serde_json::Value>(value).is_ok() {
+        return Some("JSON");
+    }
+    let mut sample_len = value.len().min(SAMPLE_LEN);
+    while !value.is_char_boundary(sample_len) {
+        sample_len -= 1;
+    }
+    let sample = &value[..sample_len];
+    MARKERS
+        .iter()
+        .map(|(language, markers)| {
+            let count = markers
+                .iter()
+                .map(|marker| sample.matches(marker).count())
+                .sum::<usize>();
+            (count, *language)
+        })
+        .filter(|(count, _)| *count >= MIN_MARKERS)
+        .max_by_key(|(count, _)| *count)
+        .map(|(_, language)| language)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        let stats = Stats::new("héllo world\nbye\n", true);
+        assert_eq!(
+            (stats.chars, stats.words, stats.lines, stats.bytes),
+            (16, 3, 2, 17)
+        );
+        assert_eq!(stats.language, None);
+        assert_eq!(stats.to_string(), "2 lines, 3 words, 16 chars, 17 bytes");
+    }
+
+    #[test]
+    fn test_detect_language() {
+        let python = "import os\n\ndef main():\n    print(os.getcwd())\n";
+        assert_eq!(detect_language(python), Some("Python"));
+        let rust = "fn main() {\n    let mut v = Vec::new();\n    v.push(1);\n}\n";
+        assert_eq!(detect_language(rust), Some("Rust"));
+        assert_eq!(detect_language("#!/bin/sh\nls"), Some("Shell"));
+        assert_eq!(detect_language(r#"{"a": [1, 2]}"#), Some("JSON"));
+        assert_eq!(detect_language("just some words, nothing else"), None);
+    }
+}
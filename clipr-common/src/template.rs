@@ -0,0 +1,137 @@
+use crate::display::{self, highlight, paint};
+use crate::{DisplayConfig, Item, ItemSummary};
+use chrono::prelude::*;
+use std::time::SystemTime;
+
+// Renders list line from template like `{index}: {pin} {preview} [{tags}] {age}`.
+// Placeholders: index, id, hash, pin, favorite, preview, tags, date, age,
+// app, title, note and count (access counter), unknown ones are kept as is,
+// `{{` and `}}` stand for literal braces.
+pub fn render(template: &str, index: usize, item: &ItemSummary, display: &DisplayConfig) -> String {
+    render_with(template, |name| placeholder(name, index, item, display))
+}
+
+// Same placeholders plus full `{value}`, used by `export-note`.
+pub fn render_item(template: &str, index: usize, item: &Item, display: &DisplayConfig) -> String {
+    let summary = ItemSummary::new(item, item.hash, None);
+    render_with(template, |name| match name {
+        "value" => Some(item.value.clone()),
+        _ => placeholder(name, index, &summary, display),
+    })
+}
+
+fn render_with(template: &str, placeholder: impl Fn(&str) -> Option<String>) -> String {
+    let mut line = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                line.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                line.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        closed = true;
+                        break;
+                    }
+                    name.push(c);
+                }
+                match placeholder(&name) {
+                    Some(value) if closed => line.push_str(&value),
+                    _ => {
+                        line.push('{');
+                        line.push_str(&name);
+                        if closed {
+                            line.push('}');
+                        }
+                    }
+                }
+            }
+            c => line.push(c),
+        }
+    }
+    line
+}
+
+fn placeholder(
+    name: &str,
+    index: usize,
+    item: &ItemSummary,
+    display: &DisplayConfig,
+) -> Option<String> {
+    Some(match name {
+        "index" => index.to_string(),
+        "id" => item.id.clone(),
+        "hash" => item.hash.to_string(),
+        "pin" => paint(
+            &item.pin.map(String::from).unwrap_or_default(),
+            display::PIN,
+            display.color,
+        ),
+        "favorite" => if item.favorite { "*" } else { "" }.to_string(),
+        "preview" => highlight(&item.preview, &item.matches, display.color),
+        "tags" => {
+            let mut tags = item.tags.iter().flatten().cloned().collect::<Vec<String>>();
+            tags.sort();
+            paint(&tags.join(","), display::TAGS, display.color)
+        }
+        "date" => paint(
+            &DateTime::<Local>::from(item.accessed_at)
+                .format(display.date_format())
+                .to_string(),
+            display::DATE,
+            display.color,
+        ),
+        "age" => age(item.accessed_at, SystemTime::now()),
+        "app" => item.source_app.clone().unwrap_or_default(),
+        "title" => item.title.clone().unwrap_or_default(),
+        "note" => item.note.clone().unwrap_or_default(),
+        "count" => item.access_counter.to_string(),
+        _ => return None,
+    })
+}
+
+// time since last access in largest whole unit, `45s`, `12m`, `3h`, `2d`
+pub fn age(at: SystemTime, now: SystemTime) -> String {
+    let secs = now.duration_since(at).map_or(0, |age| age.as_secs());
+    match secs {
+        0..=59 => format!("{secs}s"),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Item;
+    use std::time::Duration;
+
+    #[test]
+    fn test_render() {
+        let mut item = Item::new(String::from("hello"));
+        item.pin = Some('A');
+        item.tags = Some(["b", "a"].iter().map(|t| t.to_string()).collect());
+        let summary = ItemSummary::new(&item, 0, None);
+        let display = DisplayConfig::default();
+        assert_eq!(
+            render(
+                "{index}: {pin} {preview} [{tags}] {{{x}",
+                3,
+                &summary,
+                &display
+            ),
+            "3: A hello [a,b] {{x}"
+        );
+        let now = SystemTime::now();
+        assert_eq!(age(now - Duration::from_secs(7200), now), "2h");
+    }
+}
@@ -0,0 +1,48 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// Value conversions applied by `set --transform` (and on capture, see
+// Config::html_to_markdown).
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Transform {
+    // HTML markup to Markdown
+    Html2md,
+}
+
+impl Transform {
+    pub fn apply(self, value: &str) -> String {
+        match self {
+            Transform::Html2md => html_to_markdown(value),
+        }
+    }
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Transform::Html2md => write!(f, "html2md"),
+        }
+    }
+}
+
+// trailing whitespace of converted blocks is dropped
+pub fn html_to_markdown(html: &str) -> String {
+    html2md::parse_html(html).trim().to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_html2md() {
+        let html =
+            r#"<h1>Title</h1><p>Some <b>bold</b> and <a href="https://example.com">link</a></p>"#;
+        assert_eq!(
+            Transform::Html2md.apply(html),
+            "Title\n==========\n\nSome **bold** and [link](https://example.com)"
+        );
+    }
+}
@@ -0,0 +1,40 @@
+use regex::Regex;
+
+// Only pages are fetched, so value should be single http(s) link.
+pub fn is_url(value: &str) -> bool {
+    let value = value.trim();
+    (value.starts_with("http://") || value.starts_with("https://"))
+        && !value.contains(char::is_whitespace)
+}
+
+// `<title>` contents with collapsed whitespace and basic entities decoded
+pub fn parse_title(html: &str) -> Option<String> {
+    let re = Regex::new(r"(?is)<title[^>]*>(.*?)</title>").unwrap();
+    let title = re.captures(html)?.get(1)?.as_str();
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    let title = title
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&amp;", "&");
+    Some(title).filter(|title| !title.is_empty())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_title() {
+        let html = "<html><head><TITLE>\n  async channels &amp; you · GitHub\n</TITLE></head>";
+        assert_eq!(
+            parse_title(html).as_deref(),
+            Some("async channels & you · GitHub")
+        );
+        assert_eq!(parse_title("<title> </title>"), None);
+        assert!(is_url("https://github.com/async-rs/async-std\n"));
+        assert!(!is_url("see https://github.com"));
+    }
+}
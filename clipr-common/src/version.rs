@@ -0,0 +1,47 @@
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+// oldest daemon current clients can talk to, bump on breaking Command/Payload changes
+pub const MIN_DAEMON_VERSION: &str = "0.1.0";
+
+// `GET /version` response
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VersionInfo {
+    pub version: String,
+}
+
+// `v1.2.3` -> [1, 2, 3], pre-release suffixes aren't supported
+pub fn parse(version: &str) -> Result<Vec<u64>> {
+    version
+        .trim_start_matches('v')
+        .split('.')
+        .map(|part| {
+            part.parse()
+                .map_err(|_| anyhow!("invalid version: {version}"))
+        })
+        .collect()
+}
+
+pub fn check_daemon(version: &str) -> Result<()> {
+    if parse(version)? < parse(MIN_DAEMON_VERSION)? {
+        bail!("daemon is v{version}, client needs >={MIN_DAEMON_VERSION}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_daemon() {
+        assert!(parse("v0.10.1").unwrap() > parse("0.9.12").unwrap());
+        assert!(check_daemon(VERSION).is_ok());
+        assert_eq!(
+            check_daemon("0.0.9").unwrap_err().to_string(),
+            format!("daemon is v0.0.9, client needs >={MIN_DAEMON_VERSION}")
+        );
+        assert!(check_daemon("next").is_err());
+    }
+}
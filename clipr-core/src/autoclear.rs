@@ -0,0 +1,29 @@
+use crate::Core;
+use async_std::task;
+use clipr_common::{ClearSecretsConfig, IndexOrId};
+use std::sync::atomic::Ordering;
+
+// Clears general pasteboard after secret item (by id) was captured as
+// `change` (see State::pasteboard_changes), pasteboard changed since is
+// left alone.
+pub(crate) async fn clear_later(core: Core, config: ClearSecretsConfig, id: String, change: u64) {
+    task::sleep(config.after()).await;
+    if core.state.pasteboard_changes.load(Ordering::SeqCst) != change {
+        return;
+    }
+    if let Err(err) = core.clipboard.clear() {
+        return eprintln!("clearing secret {id} failed: {err:#}");
+    }
+    if config.keep() {
+        return;
+    }
+    let mut entries = core.state.entries.write().unwrap();
+    let Some(index) = entries.resolve(&IndexOrId::Id(id)) else {
+        return;
+    };
+    // ephemeral items skip trash
+    if let Some(item) = entries.get(index) {
+        item.ephemeral = true;
+    }
+    entries.delete(index, None);
+}
@@ -0,0 +1,42 @@
+use anyhow::{anyhow, Result};
+use clipr_common::cleanurl::CleanUrlsConfig;
+use clipr_common::State;
+use std::sync::Arc;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// Replaces value of item (found by id, like unfurl does) with the link
+// shortened one leads to, short link is kept as original_url.
+pub async fn resolve(state: Arc<State>, config: CleanUrlsConfig, id: String, url: String) {
+    let target = match async_std::future::timeout(TIMEOUT, fetch_location(&url)).await {
+        Ok(Ok(Some(target))) => target,
+        Ok(Ok(None)) => return,
+        Ok(Err(err)) => return eprintln!("resolving {url} failed: {err}"),
+        Err(_) => return eprintln!("resolving {url} timed out"),
+    };
+    let target = config.clean(&target).unwrap_or(target);
+    let mut entries = state.entries.write().unwrap();
+    let Some(index) = entries.resolve(&clipr_common::IndexOrId::Id(id)) else {
+        return;
+    };
+    if entries.get_value(index).as_deref() == Some(url.as_str())
+        && entries.replace_value(index, target)
+    {
+        if let Some(item) = entries.get(index) {
+            item.original_url.get_or_insert(url);
+        }
+    }
+}
+
+// only the first hop, shorteners redirect straight to target
+async fn fetch_location(url: &str) -> Result<Option<String>> {
+    let res = surf::head(url).await.map_err(|err| anyhow!(err))?;
+    if !res.status().is_redirection() {
+        return Ok(None);
+    }
+    Ok(res
+        .header("Location")
+        .map(|location| location.last().to_string())
+        .filter(|location| clipr_common::unfurl::is_url(location)))
+}
@@ -0,0 +1,23 @@
+use anyhow::{bail, Result};
+use clipr_common::Item;
+
+// Write side of system clipboard, used by `add`, `set`, `insert` and
+// `clear --system`. Captured values come in as Request::Sync (see Handle).
+pub trait Clipboard: Send + Sync {
+    // general pasteboard for None, fails for image it can't read
+    fn set(&self, pasteboard: Option<&str>, item: &Item) -> Result<()>;
+    fn clear(&self) -> Result<()>;
+}
+
+// for embedders without clipboard access, commands touching it fail
+pub struct NoClipboard;
+
+impl Clipboard for NoClipboard {
+    fn set(&self, _pasteboard: Option<&str>, _item: &Item) -> Result<()> {
+        bail!("clipboard isn't available")
+    }
+
+    fn clear(&self) -> Result<()> {
+        bail!("clipboard isn't available")
+    }
+}
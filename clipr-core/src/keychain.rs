@@ -0,0 +1,109 @@
+use crate::memory;
+use anyhow::Result;
+use clipr_common::{calculate_hash, Entries, Item, ItemKind, State, SECRET_TAG};
+use keyring::Entry;
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+const SERVICE: &str = "clipr";
+// value of moved item, real one is fetched by id
+const PLACEHOLDER: &str = "<keychain>";
+
+fn entry(item: &Item) -> Result<Entry> {
+    Ok(Entry::new(SERVICE, &item.id)?)
+}
+
+// tagged `secret` by hand, app rules or plugins (see Config::keychain,
+// ClearSecretsConfig)
+pub fn is_secret(item: &Item) -> bool {
+    item.tags
+        .as_ref()
+        .is_some_and(|tags| tags.contains(SECRET_TAG))
+}
+
+// real value, spilled ones are read back too (see memory)
+pub fn value<'a>(state: &State, item: &'a Item) -> Result<Cow<'a, str>> {
+    if item.spilled {
+        return Ok(Cow::Owned(memory::read(state, item)?));
+    }
+    if !item.keychain {
+        return Ok(Cow::Borrowed(&item.value));
+    }
+    Ok(Cow::Owned(entry(item)?.get_password()?))
+}
+
+// hash of real value (see Entries::rebuild)
+pub fn hash(state: &State, item: &Item) -> Result<u64> {
+    Ok(calculate_hash(&value(state, item)?))
+}
+
+// copy with real value, for Set
+pub fn resolve<'a>(state: &State, item: &'a Item) -> Result<Cow<'a, Item>> {
+    match value(state, item)? {
+        Cow::Borrowed(_) => Ok(Cow::Borrowed(item)),
+        Cow::Owned(value) => Ok(Cow::Owned(Item {
+            value,
+            keychain: false,
+            spilled: false,
+            ..item.clone()
+        })),
+    }
+}
+
+fn store(item: &mut Item) -> Result<()> {
+    entry(item)?.set_password(&item.value)?;
+    item.value = String::from(PLACEHOLDER);
+    item.keychain = true;
+    Ok(())
+}
+
+fn restore(item: &mut Item) -> Result<()> {
+    let entry = entry(item)?;
+    item.value = entry.get_password()?;
+    item.keychain = false;
+    entry.delete_credential()?;
+    Ok(())
+}
+
+// Called before save: moves values of secret items to keychain, brings
+// back untagged ones and drops passwords of items gone since last sync
+// (trashed ones aren't gone yet).
+// Failures are logged, item is retried on next save.
+pub fn sync(state: &State, entries: &mut Entries) {
+    let enabled = state.config.keychain == Some(true);
+    let mut stored = state.keychain_ids.lock().unwrap();
+    let mut current = HashSet::new();
+    let mut moved = vec![];
+    let trashed = entries.trash.iter_mut().map(|trashed| &mut trashed.item);
+    for item in entries
+        .values
+        .iter_mut()
+        .chain(trashed)
+        .filter(|item| !item.ephemeral)
+    {
+        // only text goes to keychain
+        let secret = item.kind == ItemKind::Text && is_secret(item);
+        let result = match (item.keychain, enabled && secret) {
+            (false, true) => store(item).map(|_| true),
+            (true, false) => restore(item).map(|_| true),
+            _ => Ok(false),
+        };
+        match result {
+            Ok(true) => moved.push(item.id.clone()),
+            Ok(false) => {}
+            Err(err) => eprintln!("keychain: {}: {err:#}", item.id),
+        }
+        if item.keychain {
+            current.insert(item.id.clone());
+        }
+    }
+    for id in stored.difference(&current) {
+        if let Err(err) = Entry::new(SERVICE, id).and_then(|entry| entry.delete_credential()) {
+            eprintln!("keychain: {id}: {err:#}");
+        }
+    }
+    *stored = current;
+    for id in moved {
+        entries.mark(&id);
+    }
+}
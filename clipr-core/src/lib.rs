@@ -0,0 +1,1336 @@
+use anyhow::{bail, Result};
+use async_std::channel::{bounded, Receiver, Sender, TrySendError};
+use async_std::task;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+mod autoclear;
+mod cleanurl;
+pub mod clipboard;
+pub mod keychain;
+mod maintenance;
+mod memory;
+mod note;
+pub mod replay;
+pub mod storage;
+mod unfurl;
+mod wal;
+
+use clipboard::{Clipboard, NoClipboard};
+use storage::{FileStorage, Storage};
+
+static USAGE: &str = include_str!("usage.txt");
+
+type Transport = Box<dyn FnOnce(Handle) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send>;
+
+// What transports (HTTP, gRPC, REPL, pasteboard capture, ...) talk to:
+// commands and captured values go through sender, read-only commands
+// may be answered right away with read_call.
+#[derive(Clone)]
+pub struct Handle {
+    pub state: Arc<clipr_common::State>,
+    pub sender: Sender<clipr_common::Request>,
+}
+
+impl Handle {
+    pub async fn call(&self, cmd: clipr_common::Command) -> Option<clipr_common::Response> {
+        clipr_common::Request::send_cmd(&self.sender, cmd).await
+    }
+}
+
+// shared by event loop and command handlers
+#[derive(Clone)]
+struct Core {
+    state: Arc<clipr_common::State>,
+    clipboard: Arc<dyn Clipboard>,
+    storage: Arc<dyn Storage>,
+    // false while replaying (see DaemonBuilder::without_side_effects)
+    side_effects: bool,
+    // history was loaded or saved, until then db isn't saved periodically
+    // (see maintenance), it would be overwritten by partial history
+    synced: Arc<AtomicBool>,
+}
+
+// clipr engine: history, commands and their event loop, without any
+// platform or transport code, e.g.
+//
+//     let daemon = Daemon::builder(config)
+//         .clipboard(MyClipboard)
+//         .transport(|handle| async move { my_server(handle).await })
+//         .build();
+//     daemon.run().await?;
+pub struct Daemon {
+    core: Core,
+    sender: Sender<clipr_common::Request>,
+    receiver: Receiver<clipr_common::Request>,
+    transports: Vec<Transport>,
+    record: Option<PathBuf>,
+}
+
+pub struct DaemonBuilder {
+    config: clipr_common::Config,
+    clipboard: Arc<dyn Clipboard>,
+    storage: Option<Arc<dyn Storage>>,
+    plugins: clipr_common::plugin::Plugins,
+    transports: Vec<Transport>,
+    record: Option<PathBuf>,
+    side_effects: bool,
+}
+
+impl DaemonBuilder {
+    // NoClipboard by default
+    pub fn clipboard(mut self, clipboard: impl Clipboard + 'static) -> Self {
+        self.clipboard = Arc::new(clipboard);
+        self
+    }
+
+    // FileStorage at Config::db_path by default
+    pub fn storage(mut self, storage: impl Storage + 'static) -> Self {
+        self.storage = Some(Arc::new(storage));
+        self
+    }
+
+    pub fn plugins(mut self, plugins: clipr_common::plugin::Plugins) -> Self {
+        self.plugins = plugins;
+        self
+    }
+
+    // append incoming requests to file (see replay)
+    pub fn record(mut self, path: PathBuf) -> Self {
+        self.record = Some(path);
+        self
+    }
+
+    // Nothing outside of history is touched: keychain isn't synced, values
+    // aren't spilled (see memory), notes aren't exported, see replay.
+    pub fn without_side_effects(mut self) -> Self {
+        self.side_effects = false;
+        self
+    }
+
+    // spawned by Daemon::run, daemon keeps running when it returns
+    pub fn transport<F, T>(mut self, transport: F) -> Self
+    where
+        F: FnOnce(Handle) -> T + Send + 'static,
+        T: Future<Output = ()> + Send + 'static,
+    {
+        self.transports
+            .push(Box::new(move |handle| Box::pin(transport(handle))));
+        self
+    }
+
+    pub fn build(self) -> Daemon {
+        let storage = self.storage.unwrap_or_else(|| {
+            let storage = FileStorage::new(self.config.db_path());
+            Arc::new(if self.config.wal() {
+                storage.with_wal()
+            } else {
+                storage
+            })
+        });
+        let mut state = clipr_common::State::new(self.config);
+        state.plugins = self.plugins;
+        if self.side_effects {
+            state.spill_dir = storage.spill_dir();
+        }
+        let (sender, receiver) = bounded::<clipr_common::Request>(1);
+        Daemon {
+            core: Core {
+                state: Arc::new(state),
+                clipboard: self.clipboard,
+                storage,
+                side_effects: self.side_effects,
+                synced: Arc::new(AtomicBool::new(false)),
+            },
+            sender,
+            receiver,
+            transports: self.transports,
+            record: self.record,
+        }
+    }
+}
+
+impl Daemon {
+    pub fn builder(config: clipr_common::Config) -> DaemonBuilder {
+        DaemonBuilder {
+            config,
+            clipboard: Arc::new(NoClipboard),
+            storage: None,
+            plugins: vec![],
+            transports: vec![],
+            record: None,
+            side_effects: true,
+        }
+    }
+
+    // for tasks started outside of transports
+    pub fn handle(&self) -> Handle {
+        Handle {
+            state: self.core.state.clone(),
+            sender: self.sender.clone(),
+        }
+    }
+
+    // Spawns transports and runs event loop until `quit`, history is
+    // saved on exit.
+    pub async fn run(self) -> Result<()> {
+        let recorder = self
+            .record
+            .as_deref()
+            .map(replay::Recorder::open)
+            .transpose()?;
+        let handle = self.handle();
+        for transport in self.transports {
+            task::spawn(transport(handle.clone()));
+        }
+        task::spawn(expire_loop(self.core.state.clone()));
+        if let Some(config) = self.core.state.config.maintenance.clone() {
+            task::spawn(maintenance::maintenance_loop(self.core.clone(), config));
+        }
+        event_loop(self.core.clone(), self.receiver, recorder).await;
+        save(&self.core)
+    }
+}
+
+async fn event_loop(
+    core: Core,
+    receiver: Receiver<clipr_common::Request>,
+    mut recorder: Option<replay::Recorder>,
+) {
+    let s = core.state.clone();
+    let mut subscribers: Vec<Sender<clipr_common::Response>> = vec![];
+    loop {
+        if let Ok(msg) = receiver.recv().await {
+            // captures are recorded once they are handled, see below
+            let mut capture = None;
+            if let Some(recorder) = recorder.as_mut() {
+                match &msg {
+                    clipr_common::Request::Sync(..) => {
+                        capture = replay::Recorded::from_request(&msg)
+                    }
+                    _ => recorder.record(&msg),
+                }
+            }
+            let mutating = match &msg {
+                clipr_common::Request::Command(cmd, _) => !cmd.is_read_only(),
+                clipr_common::Request::Subscribe(_) | clipr_common::Request::Quit => false,
+                clipr_common::Request::Sync(..) | clipr_common::Request::Mirror(_) => true,
+            };
+            match msg {
+                clipr_common::Request::Quit => return,
+                clipr_common::Request::Sync(value, kind, source_app, change_count) => {
+                    // skipped copies count too, they replace secret anyway
+                    let change = s
+                        .pasteboard_changes
+                        .fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+                        + 1;
+                    let rule = s.config.app_rule(source_app.as_ref());
+                    if rule.is_some_and(|rule| !rule.capture())
+                        || !s
+                            .plugins
+                            .iter()
+                            .all(|p| p.filter(&value, source_app.as_ref()))
+                    {
+                        continue;
+                    }
+                    let cleanup = s.config.clean_urls(rule);
+                    let (value, original_url) = match cleanup.and_then(|c| c.clean(&value)) {
+                        Some(clean) => (clean, Some(value)),
+                        None => (value, None),
+                    };
+                    let incognito = s.is_incognito(SystemTime::now());
+                    let short = cleanup
+                        .filter(|c| c.resolve_shorteners == Some(true) && !incognito)
+                        .filter(|_| {
+                            clipr_common::cleanurl::host(value.trim()).is_some_and(|host| {
+                                clipr_common::cleanurl::SHORTENERS.contains(&host)
+                            })
+                        })
+                        .map(|c| (c.clone(), value.trim().to_string()));
+                    let mut append = s.append.lock().unwrap();
+                    let append = append
+                        .as_mut()
+                        .filter(|_| kind == clipr_common::ItemKind::Text);
+                    let link = (s.config.unfurl == Some(true)
+                        && !incognito
+                        && append.is_none()
+                        && kind == clipr_common::ItemKind::Text
+                        && clipr_common::unfurl::is_url(&value))
+                    .then(|| value.clone());
+                    let mut entries = s.entries.write().unwrap();
+                    // already saved items stay persistent
+                    let ephemeral = incognito
+                        && !entries
+                            .hashes
+                            .contains(&clipr_common::calculate_hash(&value));
+                    let item = match append {
+                        Some(append) => entries.insert_joined(append, value),
+                        None => entries.insert_dedup(value, s.config.dedup()),
+                    };
+                    item.kind = kind;
+                    item.ephemeral |= ephemeral;
+                    item.change_count = change_count;
+                    if original_url.is_some() {
+                        item.original_url = original_url;
+                    }
+                    if let Some((config, url)) = short {
+                        task::spawn(cleanurl::resolve(s.clone(), config, item.id.clone(), url));
+                    }
+                    if let Some(url) = link.filter(|_| item.title.is_none()) {
+                        task::spawn(unfurl::unfurl(s.clone(), item.id.clone(), url));
+                    }
+                    if let Some(rule) = rule {
+                        rule.apply(item);
+                    }
+                    for plugin in s.plugins.iter() {
+                        plugin.on_insert(item, source_app.as_ref());
+                    }
+                    if let Some(app) = source_app {
+                        item.source_app = Some(app.name);
+                    }
+                    // incognito and secret values stay off disk, so do skipped
+                    // ones (password managers)
+                    if let Some((recorder, capture)) = recorder.as_mut().zip(capture) {
+                        if !incognito && !keychain::is_secret(item) {
+                            recorder.write(&capture);
+                        }
+                    }
+                    if let Some(config) = s
+                        .config
+                        .clear_secrets
+                        .clone()
+                        .filter(|_| keychain::is_secret(item))
+                    {
+                        task::spawn(autoclear::clear_later(
+                            core.clone(),
+                            config,
+                            item.id.clone(),
+                            change,
+                        ));
+                    }
+                    if s.config
+                        .ring
+                        .as_ref()
+                        .is_some_and(|ring| ring.pins == Some(true))
+                    {
+                        entries.pin_ring(s.config.ring_size());
+                    }
+                    if let Some(max_entries) = s.config.max_entries {
+                        entries.evict(max_entries);
+                    }
+                    // subscribers don't see new items while locked
+                    if let Some(item) = entries.get(0).filter(|_| !s.is_locked(SystemTime::now())) {
+                        notify(&mut subscribers, item);
+                    }
+                }
+                clipr_common::Request::Subscribe(sender) => subscribers.push(sender),
+                clipr_common::Request::Mirror(update) => {
+                    let mut entries = s.entries.write().unwrap();
+                    match update {
+                        clipr_common::MirrorUpdate::Snapshot(items) => {
+                            *entries = clipr_common::Entries::new();
+                            for item in items.into_iter().rev() {
+                                entries.mirror(item);
+                            }
+                        }
+                        clipr_common::MirrorUpdate::Item(item) => {
+                            entries.mirror(*item);
+                            if let Some(item) =
+                                entries.get(0).filter(|_| !s.is_locked(SystemTime::now()))
+                            {
+                                notify(&mut subscribers, item);
+                            }
+                        }
+                    }
+                }
+                clipr_common::Request::Command(cmd, sender) => {
+                    let response = match handle_call(&core, cmd).await {
+                        Ok(clipr_common::Payload::Stop) => return,
+                        Ok(payload) => clipr_common::Response::Payload(payload),
+                        Err(err) => {
+                            eprintln!("command failed: {err:#}");
+                            clipr_common::Response::Error(format!("{err:#}"))
+                        }
+                    };
+                    // requester may be gone already
+                    let _ = sender.send(response).await;
+                }
+            };
+            if mutating {
+                memory::enforce(&core);
+                log(&core);
+            }
+        }
+    }
+}
+
+// slow subscribers miss events, closed ones are dropped
+fn notify(subscribers: &mut Vec<Sender<clipr_common::Response>>, item: &clipr_common::Item) {
+    subscribers.retain(|sub| {
+        !matches!(
+            sub.try_send(clipr_common::Response::NewItem(Box::new(item.clone()))),
+            Err(TrySendError::Closed(_))
+        )
+    });
+}
+
+async fn expire_loop(state: Arc<clipr_common::State>) {
+    loop {
+        task::sleep(Duration::from_secs(1)).await;
+        let now = SystemTime::now();
+        let mut entries = state.entries.write().unwrap();
+        entries.remove_expired(now);
+        let mut incognito_until = state.incognito_until.lock().unwrap();
+        if incognito_until.is_some_and(|until| until <= now) {
+            incognito_until.take();
+            entries.remove_ephemeral();
+        }
+    }
+}
+
+fn not_found(target: &clipr_common::IndexOrId) -> clipr_common::Payload {
+    clipr_common::Payload::NotFound {
+        target: target.clone(),
+    }
+}
+
+// rejects command if item at index isn't the one client expects
+fn conflict(
+    entries: &clipr_common::Entries,
+    index: usize,
+    expected_hash: Option<u64>,
+) -> Option<clipr_common::Payload> {
+    let hash = entries.hash_at(index);
+    expected_hash
+        .filter(|expected| hash != Some(*expected))
+        .map(|_| clipr_common::Payload::Conflict { index, hash })
+}
+
+// capture side skips daemon's own writes of values already in history
+// (see State::take_own_write), new ones are recorded as any other copy
+fn write_clipboard(core: &Core, pasteboard: Option<&str>, item: &clipr_common::Item) -> Result<()> {
+    core.clipboard.set(pasteboard, item)?;
+    core.state.record_write(pasteboard, &item.value);
+    Ok(())
+}
+
+// `set` of history item, false if there is none at index
+fn set_item(
+    core: &Core,
+    entries: &mut clipr_common::Entries,
+    index: usize,
+    pasteboard: Option<&str>,
+) -> Result<bool> {
+    let Some(item) = entries.get_item(index) else {
+        return Ok(false);
+    };
+    let item = keychain::resolve(&core.state, item)?;
+    write_clipboard(core, pasteboard, &item)?;
+    if let Some(item) = entries.get(index) {
+        item.set_count += 1;
+    }
+    Ok(true)
+}
+
+// masks `[display] redact` patterns in previews
+fn redact(
+    state: &clipr_common::State,
+    entries: &clipr_common::Entries,
+    items: &mut [(usize, clipr_common::ItemSummary)],
+    preview_length: Option<usize>,
+) {
+    if let Some(display) = &state.config.display {
+        display.redactor().summaries(items, entries, preview_length);
+    }
+}
+
+// Commands which only read entries, also called directly by HTTP handlers
+// (see Command::is_read_only).
+pub fn read_call(
+    state: &clipr_common::State,
+    cmd: &clipr_common::Command,
+) -> clipr_common::Payload {
+    if let Some(locked) = state.check_lock(cmd) {
+        return locked;
+    }
+    let entries = state.entries.read().unwrap();
+    match cmd {
+        clipr_common::Command::List {
+            from_index,
+            to_index,
+            preview_length,
+            favorites,
+            ranked,
+            template,
+            filter,
+        } => {
+            let preview_length = preview_length.or(state.config.preview_length());
+            let ranked =
+                *ranked || state.config.list_order == Some(clipr_common::ListOrder::Frecency);
+            let mut items = if !filter.is_empty() {
+                if let Err(err) = filter.validate() {
+                    return clipr_common::Payload::InvalidArgs {
+                        reason: err.to_string(),
+                    };
+                }
+                let mut selected = entries.select(filter);
+                if ranked {
+                    let now = SystemTime::now();
+                    selected.sort_by(|(_, a), (_, b)| b.frecency(now).total_cmp(&a.frecency(now)));
+                }
+                let (from_index, to_index) =
+                    match clipr_common::range::span(*from_index, *to_index, selected.len()) {
+                        Ok(span) => span,
+                        Err(reason) => return clipr_common::Payload::InvalidArgs { reason },
+                    };
+                let to_index = to_index.unwrap_or(selected.len());
+                let selected: Vec<_> = selected
+                    .into_iter()
+                    .skip(from_index)
+                    .take(to_index.saturating_sub(from_index))
+                    .collect();
+                clipr_common::summarize(&selected, preview_length)
+            } else {
+                let (from_index, to_index) =
+                    match clipr_common::range::span(*from_index, *to_index, entries.len()) {
+                        Ok(span) => span,
+                        Err(reason) => return clipr_common::Payload::InvalidArgs { reason },
+                    };
+                if ranked {
+                    entries.select_ranked(Some(from_index), to_index, preview_length)
+                } else {
+                    entries.select_by_range(Some(from_index), to_index, preview_length)
+                }
+            };
+            if *favorites {
+                items.retain(|(_, item)| item.favorite);
+            }
+            redact(state, &entries, &mut items, preview_length);
+            filter.mark_matches(&mut items);
+            clipr_common::Payload::List {
+                value: items,
+                preview_length,
+                template: template.clone(),
+            }
+        }
+        clipr_common::Command::Count => clipr_common::Payload::Value {
+            value: Some(entries.len().to_string()),
+        },
+        clipr_common::Command::History { day } => {
+            let mut days = entries.history(*day, state.config.preview_length());
+            for day in days.iter_mut() {
+                redact(
+                    state,
+                    &entries,
+                    &mut day.items,
+                    state.config.preview_length(),
+                );
+            }
+            clipr_common::Payload::History { value: days }
+        }
+        clipr_common::Command::Get { target, stats, .. } => match entries
+            .resolve(target)
+            .and_then(|index| entries.get_item(index))
+            .map(|item| keychain::value(state, item).map(|value| (value, &item.kind)))
+        {
+            Some(Ok((value, kind))) if *stats => clipr_common::Payload::Stats {
+                value: clipr_common::stats::Stats::new(
+                    &value,
+                    *kind == clipr_common::ItemKind::Text,
+                ),
+            },
+            Some(Ok((value, _))) => clipr_common::Payload::Value {
+                value: Some(value.into_owned()),
+            },
+            Some(Err(err)) => clipr_common::Payload::Message {
+                value: format!("keychain: {err:#}"),
+            },
+            None => not_found(target),
+        },
+        clipr_common::Command::GetMany { targets } => clipr_common::Payload::Values {
+            value: targets
+                .iter()
+                .map(|target| {
+                    let item = entries
+                        .resolve(target)
+                        .and_then(|index| entries.get_item(index))?;
+                    match keychain::value(state, item) {
+                        Ok(value) => Some(value.into_owned()),
+                        Err(err) => {
+                            eprintln!("keychain: {err:#}");
+                            None
+                        }
+                    }
+                })
+                .collect(),
+        },
+        clipr_common::Command::GetBy { pin, tag } => {
+            let filter = clipr_common::Filter {
+                pin: pin.map(String::from),
+                tag: tag.iter().cloned().collect(),
+                ..clipr_common::Filter::default()
+            };
+            if filter.is_empty() {
+                return clipr_common::Payload::InvalidArgs {
+                    reason: String::from("--pin or --tag is required"),
+                };
+            }
+            match entries.select(&filter).first() {
+                Some((_, item)) => match keychain::value(state, item) {
+                    Ok(value) => clipr_common::Payload::Value {
+                        value: Some(value.into_owned()),
+                    },
+                    Err(err) => clipr_common::Payload::Message {
+                        value: format!("keychain: {err:#}"),
+                    },
+                },
+                None => {
+                    let by = pin
+                        .map(|pin| format!("pin {pin}"))
+                        .into_iter()
+                        .chain(tag.as_ref().map(|tag| format!("tag {tag}")))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    not_found(&clipr_common::IndexOrId::Id(by))
+                }
+            }
+        }
+        clipr_common::Command::Select { filter, all, .. } => {
+            let selected = if filter.is_empty() {
+                if !all {
+                    return clipr_common::Payload::InvalidArgs {
+                        reason: String::from("no filter given, use --all to select everything"),
+                    };
+                }
+                entries.select_all()
+            } else {
+                if let Err(err) = filter.validate() {
+                    return clipr_common::Payload::InvalidArgs {
+                        reason: err.to_string(),
+                    };
+                }
+                entries.select(filter)
+            };
+            let mut items = clipr_common::summarize(&selected, state.config.preview_length());
+            redact(state, &entries, &mut items, state.config.preview_length());
+            filter.mark_matches(&mut items);
+            clipr_common::Payload::List {
+                value: items,
+                preview_length: state.config.preview_length(),
+                template: None,
+            }
+        }
+        _ => clipr_common::Payload::Message {
+            value: String::from("not a read-only command"),
+        },
+    }
+}
+
+async fn handle_call(core: &Core, cmd: clipr_common::Command) -> Result<clipr_common::Payload> {
+    let state = &core.state;
+    if let Some(locked) = state.check_lock(&cmd) {
+        return Ok(locked);
+    }
+    if let Some(mirror) = state
+        .config
+        .mirror
+        .as_ref()
+        .filter(|_| !cmd.is_allowed_mirror())
+    {
+        return Ok(clipr_common::Payload::InvalidArgs {
+            reason: format!("read-only mirror of {}", mirror.primary),
+        });
+    }
+    Ok(match cmd {
+        clipr_common::Command::List { .. }
+        | clipr_common::Command::Get { .. }
+        | clipr_common::Command::GetMany { .. }
+        | clipr_common::Command::GetBy { .. }
+        | clipr_common::Command::Count
+        | clipr_common::Command::History { .. } => read_call(state, &cmd),
+        clipr_common::Command::Status { peers } => {
+            let count = state.entries.read().unwrap().len();
+            clipr_common::Payload::Status {
+                listen_on: state.config.listen_on(),
+                count,
+                peers: peers.then(|| state.peers.lock().unwrap().clone()),
+            }
+        }
+        clipr_common::Command::Save => {
+            save(core)?;
+            clipr_common::Payload::Ok
+        }
+        clipr_common::Command::Load => {
+            load(core)?;
+            clipr_common::Payload::Ok
+        }
+        clipr_common::Command::Add { value } => {
+            write_clipboard(core, None, &clipr_common::Item::new(value.join(" ")))?;
+            clipr_common::Payload::Ok
+        }
+        clipr_common::Command::Insert {
+            filename,
+            mime,
+            data,
+        } => {
+            let data = match data {
+                Some(data) => data,
+                None => async_std::fs::read(&filename).await?,
+            };
+            let mime = mime.unwrap_or_else(|| clipr_common::guess_mime(&filename).to_string());
+            if mime.starts_with("text/") {
+                // recorded by capture side as any other copy
+                write_clipboard(
+                    core,
+                    None,
+                    &clipr_common::Item::new(String::from_utf8(data)?),
+                )?;
+            } else if mime.starts_with("image/") {
+                let image = clipr_common::Item {
+                    kind: clipr_common::ItemKind::Image { mime: mime.clone() },
+                    ..clipr_common::Item::new(clipr_common::image_value(&data))
+                };
+                if write_clipboard(core, None, &image).is_err() {
+                    bail!("{filename} isn't {mime} image");
+                }
+                // images are never captured, so they are recorded right here
+                let mut entries = state.entries.write().unwrap();
+                let item = entries.insert_dedup(image.value, state.config.dedup());
+                item.kind = image.kind;
+            } else {
+                bail!("unsupported mime type {mime}");
+            }
+            clipr_common::Payload::Ok
+        }
+        clipr_common::Command::Set {
+            target,
+            pasteboard,
+            expected_hash,
+            transform,
+        } => {
+            let mut entries = state.entries.write().unwrap();
+            let Some(index) = entries.resolve(&target) else {
+                return Ok(not_found(&target));
+            };
+            if let Some(conflict) = conflict(&entries, index, expected_hash) {
+                return Ok(conflict);
+            }
+            if let Some(transform) = transform {
+                let Some(item) = entries.get_item(index) else {
+                    return Ok(not_found(&target));
+                };
+                let item = keychain::resolve(&core.state, item)?;
+                if item.kind != clipr_common::ItemKind::Text {
+                    bail!("{transform} applies to text items only");
+                }
+                let value = transform.apply(&item.value);
+                write_clipboard(core, pasteboard.as_deref(), &clipr_common::Item::new(value))?;
+                return Ok(clipr_common::Payload::Ok);
+            }
+            if set_item(core, &mut entries, index, pasteboard.as_deref())? {
+                clipr_common::Payload::Ok
+            } else {
+                not_found(&target)
+            }
+        }
+        clipr_common::Command::Del {
+            from_index,
+            to_index,
+            expected_hash,
+            dry_run,
+        } => {
+            let mut entries = state.entries.write().unwrap();
+            // single item has to exist, ranges are clamped
+            if let clipr_common::range::Position::Index(index) = from_index {
+                if to_index.is_none()
+                    && clipr_common::range::resolve(index, entries.len()).is_none()
+                {
+                    return Ok(not_found(&from_index.to_string().parse().unwrap()));
+                }
+            }
+            let (from_index, to_index) =
+                match clipr_common::range::span(Some(from_index), to_index, entries.len()) {
+                    Ok(span) => span,
+                    Err(reason) => return Ok(clipr_common::Payload::InvalidArgs { reason }),
+                };
+            if let Some(conflict) = conflict(&entries, from_index, expected_hash) {
+                return Ok(conflict);
+            }
+            if dry_run {
+                let preview_length = state.config.preview_length();
+                let mut items = entries.select_by_range(
+                    Some(from_index),
+                    Some(to_index.unwrap_or(from_index + 1)),
+                    preview_length,
+                );
+                // as delete does, ranges keep favorites
+                if to_index.is_some() {
+                    items.retain(|(_, item)| !item.favorite);
+                }
+                redact(state, &entries, &mut items, preview_length);
+                return Ok(clipr_common::Payload::List {
+                    value: items,
+                    preview_length,
+                    template: None,
+                });
+            }
+            entries.delete(from_index, to_index);
+            clipr_common::Payload::Ok
+        }
+        clipr_common::Command::Tag { target, tag } => {
+            let mut entries = state.entries.write().unwrap();
+            let Some(index) = entries.resolve(&target) else {
+                return Ok(not_found(&target));
+            };
+            entries.tag(index, tag);
+            clipr_common::Payload::Ok
+        }
+        clipr_common::Command::Untag { target, tag } => {
+            let mut entries = state.entries.write().unwrap();
+            let Some(index) = entries.resolve(&target) else {
+                return Ok(not_found(&target));
+            };
+            entries.untag(index, tag);
+            clipr_common::Payload::Ok
+        }
+        clipr_common::Command::Pin { target, pin } => {
+            let mut entries = state.entries.write().unwrap();
+            let Some(index) = entries.resolve(&target) else {
+                return Ok(not_found(&target));
+            };
+            entries.pin(index, pin.to_uppercase().next().unwrap());
+            clipr_common::Payload::Ok
+        }
+        clipr_common::Command::SetPin { pin, pasteboard } => {
+            let mut entries = state.entries.write().unwrap();
+            let index = entries.select_by_pin(pin).first().map(|(index, _)| *index);
+            // there is no item to point at, 404 is still what hotkey scripts check
+            let Some(index) = index else {
+                return Ok(not_found(&clipr_common::IndexOrId::Id(format!(
+                    "pin {pin}"
+                ))));
+            };
+            set_item(core, &mut entries, index, pasteboard.as_deref())?;
+            clipr_common::Payload::Ok
+        }
+        clipr_common::Command::Unpin { target } => {
+            let mut entries = state.entries.write().unwrap();
+            let Some(index) = entries.resolve(&target) else {
+                return Ok(not_found(&target));
+            };
+            entries.unpin(index);
+            clipr_common::Payload::Ok
+        }
+        clipr_common::Command::Select { set, .. } => match read_call(state, &cmd) {
+            clipr_common::Payload::List {
+                value,
+                preview_length,
+                ..
+            } if set && value.len() > 1 => clipr_common::Payload::Ambiguous {
+                value,
+                preview_length,
+            },
+            clipr_common::Payload::List { value, .. } if set && value.len() == 1 => {
+                let (index, _) = &value[0];
+                let mut entries = state.entries.write().unwrap();
+                set_item(core, &mut entries, *index, None)?;
+                clipr_common::Payload::Ok
+            }
+            payload => payload,
+        },
+        clipr_common::Command::ExportNote {
+            path,
+            filter,
+            template,
+        } => {
+            if filter.is_empty() {
+                return Ok(clipr_common::Payload::InvalidArgs {
+                    reason: String::from("no filter given"),
+                });
+            }
+            if let Err(err) = filter.validate() {
+                return Ok(clipr_common::Payload::InvalidArgs {
+                    reason: err.to_string(),
+                });
+            }
+            if !path.ends_with(".md") {
+                return Ok(clipr_common::Payload::InvalidArgs {
+                    reason: format!("{path} isn't a Markdown (.md) file"),
+                });
+            }
+            let items = state.entries.read().unwrap().select(&filter);
+            // replayed export would append the same note again
+            if !core.side_effects {
+                return Ok(clipr_common::Payload::Message {
+                    value: format!("{} items not appended to {path} (replay)", items.len()),
+                });
+            }
+            let count = note::export(
+                &clipr_common::paths::expand_tilde(&path),
+                &items,
+                template.as_deref(),
+                &state.config.display(),
+            )?;
+            clipr_common::Payload::Message {
+                value: format!("{count} items appended to {path}"),
+            }
+        }
+        clipr_common::Command::Tags => {
+            let entries = state.entries.read().unwrap();
+            clipr_common::Payload::Tags {
+                value: entries.get_tag_counts(),
+            }
+        }
+
+        clipr_common::Command::Fav { ref target } | clipr_common::Command::Unfav { ref target } => {
+            let favorite = matches!(cmd, clipr_common::Command::Fav { .. });
+            let mut entries = state.entries.write().unwrap();
+            let Some(index) = entries.resolve(target) else {
+                return Ok(not_found(target));
+            };
+            entries.favorite(index, favorite);
+            clipr_common::Payload::Ok
+        }
+        clipr_common::Command::Clear {
+            keep_pinned,
+            system,
+        } => {
+            let mut entries = state.entries.write().unwrap();
+            entries.clear(keep_pinned);
+            if system {
+                core.clipboard.clear()?;
+            }
+            clipr_common::Payload::Ok
+        }
+        clipr_common::Command::Trash => {
+            let entries = state.entries.read().unwrap();
+            let redactor = state.config.display.as_ref().map(|d| d.redactor());
+            let trashed: Vec<(usize, clipr_common::Item)> = entries
+                .trash
+                .iter()
+                .enumerate()
+                .map(|(index, trashed)| match redactor {
+                    Some(redactor) => (index, redactor.item(&trashed.item).into_owned()),
+                    None => (index, trashed.item.clone()),
+                })
+                .collect();
+            clipr_common::Payload::List {
+                value: clipr_common::summarize(&trashed, state.config.preview_length()),
+                preview_length: state.config.preview_length(),
+                template: None,
+            }
+        }
+        clipr_common::Command::Restore { target } => {
+            let mut entries = state.entries.write().unwrap();
+            let Some(index) = entries.trash_index(&target) else {
+                return Ok(not_found(&target));
+            };
+            clipr_common::Payload::Message {
+                value: format!("restored at {}", entries.restore(index)),
+            }
+        }
+        clipr_common::Command::Rebuild => {
+            let mut entries = state.entries.write().unwrap();
+            clipr_common::Payload::Message {
+                value: entries
+                    .rebuild(|item| keychain::hash(state, item), state.config.dedup())
+                    .to_string(),
+            }
+        }
+        clipr_common::Command::Dedup { dry_run } => {
+            let mut entries = state.entries.write().unwrap();
+            clipr_common::Payload::Groups {
+                value: entries.dedup(dry_run),
+            }
+        }
+        clipr_common::Command::Prune { selector, dry_run } => {
+            if selector.is_empty() {
+                return Ok(clipr_common::Payload::InvalidArgs {
+                    reason: String::from("no filter given"),
+                });
+            }
+            if let Err(err) = selector.validate() {
+                return Ok(clipr_common::Payload::InvalidArgs {
+                    reason: err.to_string(),
+                });
+            }
+            let mut entries = state.entries.write().unwrap();
+            let items = entries.prune(&selector, dry_run);
+            if dry_run {
+                let mut items = clipr_common::summarize(&items, state.config.preview_length());
+                redact(state, &entries, &mut items, state.config.preview_length());
+                selector.mark_matches(&mut items);
+                clipr_common::Payload::List {
+                    value: items,
+                    preview_length: state.config.preview_length(),
+                    template: None,
+                }
+            } else {
+                clipr_common::Payload::Message {
+                    value: format!("{} items deleted", items.len()),
+                }
+            }
+        }
+        clipr_common::Command::Note { target, text } => {
+            let mut entries = state.entries.write().unwrap();
+            let Some(index) = entries.resolve(&target) else {
+                return Ok(not_found(&target));
+            };
+            let note = Some(text.join(" ")).filter(|note| !note.is_empty());
+            entries.note(index, note);
+            clipr_common::Payload::Ok
+        }
+        clipr_common::Command::Ring => {
+            let entries = state.entries.read().unwrap();
+            let mut items = clipr_common::summarize(
+                &entries.ring(state.config.ring_size()),
+                state.config.preview_length(),
+            );
+            redact(state, &entries, &mut items, state.config.preview_length());
+            clipr_common::Payload::List {
+                value: items,
+                preview_length: state.config.preview_length(),
+                template: None,
+            }
+        }
+        clipr_common::Command::Plugin { name, args } => {
+            match clipr_common::plugin::find_plugin(&state.plugins, &name) {
+                Some(plugin) => {
+                    let mut entries = state.entries.write().unwrap();
+                    match plugin.call(&mut entries, args) {
+                        Ok(value) => clipr_common::Payload::Plugin { name, value },
+                        Err(err) => clipr_common::Payload::Message {
+                            value: format!("plugin {name:?} failed: {err}"),
+                        },
+                    }
+                }
+                None => clipr_common::Payload::Message {
+                    value: format!("plugin {name:?} not found"),
+                },
+            }
+        }
+        clipr_common::Command::Plugins => clipr_common::Payload::Message {
+            value: state
+                .plugins
+                .iter()
+                .map(|plugin| format!("{}: {}", plugin.name(), plugin.help()))
+                .collect::<Vec<String>>()
+                .join("\n"),
+        },
+        clipr_common::Command::Incognito { stop: true, .. } => {
+            state.incognito_until.lock().unwrap().take();
+            let removed = state.entries.write().unwrap().remove_ephemeral();
+            clipr_common::Payload::Message {
+                value: format!("incognito ended, {removed} items removed"),
+            }
+        }
+        clipr_common::Command::Incognito { duration, .. } => {
+            let duration = duration.unwrap_or(clipr_common::DEFAULT_INCOGNITO_DURATION);
+            let until = SystemTime::now() + Duration::from_secs(duration);
+            *state.incognito_until.lock().unwrap() = Some(until);
+            clipr_common::Payload::Message {
+                value: format!("incognito for {duration}s, copied items won't be saved"),
+            }
+        }
+        clipr_common::Command::AppendMode { stop: true, .. } => {
+            let count = state
+                .append
+                .lock()
+                .unwrap()
+                .take()
+                .map_or(0, |append| append.count);
+            clipr_common::Payload::Message {
+                value: format!("append mode ended, {count} captures joined"),
+            }
+        }
+        clipr_common::Command::AppendMode { separator, .. } => {
+            let separator =
+                separator.unwrap_or_else(|| clipr_common::DEFAULT_APPEND_SEPARATOR.to_string());
+            let value = format!("appending copied text, separated by {separator:?}");
+            *state.append.lock().unwrap() = Some(clipr_common::Append::new(separator));
+            clipr_common::Payload::Message { value }
+        }
+        clipr_common::Command::Queue { stop: true, .. } => {
+            state.queue.lock().unwrap().clear();
+            clipr_common::Payload::Ok
+        }
+        clipr_common::Command::Queue { targets, next, .. } if next || !targets.is_empty() => {
+            let mut entries = state.entries.write().unwrap();
+            let mut queue = state.queue.lock().unwrap();
+            if !targets.is_empty() {
+                let mut ids = VecDeque::new();
+                for target in targets.iter() {
+                    let Some(item) = entries
+                        .resolve(target)
+                        .and_then(|index| entries.get_item(index))
+                    else {
+                        return Ok(not_found(target));
+                    };
+                    ids.push_back(item.id.clone());
+                }
+                *queue = ids;
+            }
+            // items deleted since are skipped
+            while let Some(id) = queue.pop_front() {
+                if let Some(index) = entries.resolve(&clipr_common::IndexOrId::Id(id)) {
+                    set_item(core, &mut entries, index, None)?;
+                    return Ok(clipr_common::Payload::Message {
+                        value: format!("{} more in queue", queue.len()),
+                    });
+                }
+            }
+            clipr_common::Payload::Message {
+                value: String::from("queue is empty"),
+            }
+        }
+        clipr_common::Command::Queue { .. } => {
+            let entries = state.entries.read().unwrap();
+            let queued: Vec<(usize, clipr_common::Item)> = state
+                .queue
+                .lock()
+                .unwrap()
+                .iter()
+                .filter_map(|id| {
+                    let index = entries.resolve(&clipr_common::IndexOrId::Id(id.clone()))?;
+                    Some((index, entries.get_item(index)?.clone()))
+                })
+                .collect();
+            let mut items = clipr_common::summarize(&queued, state.config.preview_length());
+            redact(state, &entries, &mut items, state.config.preview_length());
+            clipr_common::Payload::List {
+                value: items,
+                preview_length: state.config.preview_length(),
+                template: None,
+            }
+        }
+        clipr_common::Command::Unlock { passphrase } => match &state.config.lock {
+            Some(lock) if lock.verify(&passphrase) => {
+                let until = SystemTime::now() + lock.timeout();
+                *state.unlocked_until.lock().unwrap() = Some(until);
+                clipr_common::Payload::Message {
+                    value: format!("unlocked for {}s", lock.timeout().as_secs()),
+                }
+            }
+            Some(_) => clipr_common::Payload::Message {
+                value: String::from("wrong passphrase"),
+            },
+            None => clipr_common::Payload::Message {
+                value: String::from("lock isn't configured"),
+            },
+        },
+        clipr_common::Command::Lock => {
+            state.unlocked_until.lock().unwrap().take();
+            clipr_common::Payload::Ok
+        }
+        clipr_common::Command::Help => clipr_common::Payload::Message {
+            value: USAGE.to_string(),
+        },
+        clipr_common::Command::Quit => clipr_common::Payload::Stop,
+    })
+}
+
+fn save(core: &Core) -> Result<()> {
+    let entries = {
+        let mut entries = core.state.entries.write().unwrap();
+        if core.side_effects {
+            keychain::sync(&core.state, &mut entries);
+        }
+        // they are in db now
+        entries.take_changes();
+        entries.persistent()
+    };
+    core.storage.save(&entries)?;
+    core.synced.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+// Changes of request are in storage log before next one is handled,
+// logging doesn't fail requests.
+fn log(core: &Core) {
+    let mut entries = core.state.entries.write().unwrap();
+    if core.side_effects {
+        keychain::sync(&core.state, &mut entries);
+    }
+    let changes = entries.take_changes();
+    if let Err(err) = core.storage.log(&entries, &changes) {
+        eprintln!("wal: {err:#}");
+    }
+}
+
+fn load(core: &Core) -> Result<()> {
+    let mut data = core.storage.load()?;
+    if !data.is_consistent() {
+        eprintln!(
+            "index rebuilt on load: {}",
+            data.rebuild(
+                |item| keychain::hash(&core.state, item),
+                core.state.config.dedup()
+            )
+        );
+    }
+    data.purge_trash(SystemTime::now() - core.state.config.trash_retention());
+    *core.state.keychain_ids.lock().unwrap() = data
+        .values
+        .iter()
+        .filter(|item| item.keychain)
+        .map(|item| item.id.clone())
+        .collect();
+    *core.state.entries.write().unwrap() = data;
+    core.synced.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use storage::MemoryStorage;
+
+    #[async_std::test]
+    async fn test_daemon() {
+        let storage = MemoryStorage::default();
+        let daemon = Daemon::builder(clipr_common::Config::default())
+            .storage(storage.clone())
+            .transport(|handle| async move {
+                handle
+                    .sender
+                    .send(clipr_common::Request::Sync(
+                        String::from("hello"),
+                        clipr_common::ItemKind::Text,
+                        None,
+                        None,
+                    ))
+                    .await
+                    .unwrap();
+                let count = handle.call(clipr_common::Command::Count).await;
+                assert!(matches!(
+                    count,
+                    Some(clipr_common::Response::Payload(clipr_common::Payload::Value { value }))
+                        if value.as_deref() == Some("1")
+                ));
+                let set = handle
+                    .call(clipr_common::Command::Set {
+                        target: clipr_common::IndexOrId::Index(0),
+                        pasteboard: None,
+                        expected_hash: None,
+                        transform: None,
+                    })
+                    .await;
+                assert!(matches!(set, Some(clipr_common::Response::Error(_))));
+                for all in [false, true] {
+                    let select = handle
+                        .call(clipr_common::Command::Select {
+                            set: false,
+                            all,
+                            filter: clipr_common::Filter::default(),
+                        })
+                        .await;
+                    match select {
+                        Some(clipr_common::Response::Payload(clipr_common::Payload::List {
+                            value,
+                            ..
+                        })) => assert!(all && value.len() == 1),
+                        Some(clipr_common::Response::Payload(
+                            clipr_common::Payload::InvalidArgs { .. },
+                        )) => assert!(!all),
+                        other => panic!("unexpected response: {other:?}"),
+                    }
+                }
+                let del = handle
+                    .call(clipr_common::Command::Del {
+                        from_index: clipr_common::range::Position::Index(99),
+                        to_index: None,
+                        expected_hash: None,
+                        dry_run: false,
+                    })
+                    .await;
+                assert!(matches!(
+                    del,
+                    Some(clipr_common::Response::Payload(
+                        clipr_common::Payload::NotFound { .. }
+                    ))
+                ));
+                let prune = handle
+                    .call(clipr_common::Command::Prune {
+                        selector: clipr_common::Filter::default(),
+                        dry_run: true,
+                    })
+                    .await;
+                assert!(matches!(
+                    prune,
+                    Some(clipr_common::Response::Payload(
+                        clipr_common::Payload::InvalidArgs { .. }
+                    ))
+                ));
+                handle
+                    .sender
+                    .send(clipr_common::Request::Sync(
+                        String::from("help"),
+                        clipr_common::ItemKind::Text,
+                        None,
+                        None,
+                    ))
+                    .await
+                    .unwrap();
+                let select_set = |value: &str| clipr_common::Command::Select {
+                    set: true,
+                    all: false,
+                    filter: clipr_common::Filter {
+                        value: Some(String::from(value)),
+                        ..clipr_common::Filter::default()
+                    },
+                };
+                // single match goes to (missing) clipboard
+                let set = handle.call(select_set("hello")).await;
+                assert!(matches!(set, Some(clipr_common::Response::Error(_))));
+                let set = handle.call(select_set("hel")).await;
+                assert!(matches!(
+                    set,
+                    Some(clipr_common::Response::Payload(clipr_common::Payload::Ambiguous {
+                        value,
+                        ..
+                    })) if value.len() == 2
+                ));
+                let values = handle
+                    .call(clipr_common::Command::GetMany {
+                        targets: vec![
+                            clipr_common::IndexOrId::Index(1),
+                            clipr_common::IndexOrId::Index(5),
+                            clipr_common::IndexOrId::Index(0),
+                        ],
+                    })
+                    .await;
+                assert!(matches!(
+                    values,
+                    Some(clipr_common::Response::Payload(clipr_common::Payload::Values { value }))
+                        if value == vec![Some(String::from("hello")), None, Some(String::from("help"))]
+                ));
+                handle
+                    .call(clipr_common::Command::Tag {
+                        target: clipr_common::IndexOrId::Index(1),
+                        tag: String::from("greeting"),
+                    })
+                    .await;
+                let by_tag = handle
+                    .call(clipr_common::Command::GetBy {
+                        pin: None,
+                        tag: Some(String::from("greeting")),
+                    })
+                    .await;
+                assert!(matches!(
+                    by_tag,
+                    Some(clipr_common::Response::Payload(clipr_common::Payload::Value { value }))
+                        if value.as_deref() == Some("hello")
+                ));
+                handle.call(clipr_common::Command::Quit).await;
+            })
+            .build();
+        daemon.run().await.unwrap();
+        let saved = storage.take().unwrap();
+        assert_eq!(saved.get_value(1).as_deref(), Some("hello"));
+    }
+}
@@ -0,0 +1,76 @@
+use crate::{keychain, save, Core};
+use async_std::task;
+use clipr_common::{MaintenanceConfig, RebuildReport};
+use std::fmt;
+use std::sync::atomic::Ordering;
+use std::time::SystemTime;
+
+// what one run did, logged by maintenance_loop
+#[derive(Debug, Default)]
+pub struct Report {
+    pub expired: usize,
+    pub merged: usize,
+    // old enough trash items (see Config::trash_days)
+    pub purged: usize,
+    pub rebuilt: Option<RebuildReport>,
+    // error of save, if it failed
+    pub save_error: Option<String>,
+    // history wasn't loaded or saved yet (see Core::synced)
+    pub unsaved: bool,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} expired, {} duplicates merged, {} purged from trash, ",
+            self.expired, self.merged, self.purged
+        )?;
+        match &self.rebuilt {
+            Some(rebuilt) => write!(f, "index rebuilt ({rebuilt}), ")?,
+            None => write!(f, "index ok, ")?,
+        }
+        match &self.save_error {
+            Some(err) => write!(f, "save failed: {err}"),
+            None if self.unsaved => write!(f, "not saved (history isn't loaded yet)"),
+            None => write!(f, "saved"),
+        }
+    }
+}
+
+pub(crate) fn run(core: &Core, config: &MaintenanceConfig) -> Report {
+    let mut report = Report::default();
+    {
+        let mut entries = core.state.entries.write().unwrap();
+        // before anything else, removal pairs values with hashes
+        if !entries.is_consistent() {
+            report.rebuilt = Some(entries.rebuild(
+                |item| keychain::hash(&core.state, item),
+                core.state.config.dedup(),
+            ));
+        }
+        let now = SystemTime::now();
+        report.expired = entries.remove_expired(now);
+        report.purged = entries.purge_trash(now - core.state.config.trash_retention());
+        if config.dedup == Some(true) {
+            report.merged = entries
+                .dedup(false)
+                .iter()
+                .map(|group| group.len() - 1)
+                .sum();
+        }
+    }
+    if core.synced.load(Ordering::SeqCst) {
+        report.save_error = save(core).err().map(|err| format!("{err:#}"));
+    } else {
+        report.unsaved = true;
+    }
+    report
+}
+
+pub(crate) async fn maintenance_loop(core: Core, config: MaintenanceConfig) {
+    loop {
+        task::sleep(config.interval()).await;
+        eprintln!("maintenance: {}", run(&core, &config));
+    }
+}
@@ -0,0 +1,186 @@
+use crate::keychain::is_secret;
+use crate::Core;
+use anyhow::{bail, Result};
+use clipr_common::{calculate_hash, Entries, Item, ItemKind, MemoryConfig, MemoryPolicy, State};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// value of spilled item, real one is read from spill dir by id
+const PLACEHOLDER: &str = "<spilled>";
+
+fn spill_path(dir: &Path, item: &Item) -> PathBuf {
+    dir.join(&item.id)
+}
+
+pub(crate) fn read(state: &State, item: &Item) -> Result<String> {
+    let Some(dir) = &state.spill_dir else {
+        bail!("{} is spilled, but storage has no spill dir", item.id);
+    };
+    Ok(fs::read_to_string(spill_path(dir, item))?)
+}
+
+fn spill(dir: &Path, item: &mut Item) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(spill_path(dir, item), &item.value)?;
+    item.value = String::from(PLACEHOLDER);
+    item.spilled = true;
+    Ok(())
+}
+
+// Cut at char boundary, hash follows new value. False (item is left as is)
+// when cut value would duplicate another one, `known` hashes stay unique.
+fn truncate(item: &mut Item, len: usize, known: &mut HashSet<u64>) -> bool {
+    let end = (0..=len)
+        .rev()
+        .find(|end| item.value.is_char_boundary(*end))
+        .unwrap_or(0);
+    let hash = calculate_hash(&item.value[..end]);
+    if known.contains(&hash) {
+        return false;
+    }
+    known.remove(&item.hash);
+    known.insert(hash);
+    item.value.truncate(end);
+    item.hash = hash;
+    true
+}
+
+// Brings values back under MemoryConfig::max_bytes (see shrink).
+pub(crate) fn enforce(core: &Core) {
+    let Some(config) = &core.state.config.memory else {
+        return;
+    };
+    let mut entries = core.state.entries.write().unwrap();
+    shrink(&mut entries, config, core.state.spill_dir.as_deref());
+}
+
+// Trash goes first, then history from oldest item. Only text is truncated,
+// other kinds (images, files) are spilled under either policy. Spill
+// failures are logged, item stays as is. Nothing is spilled when storage has
+// no spill dir (see Storage::spill_dir).
+fn shrink(entries: &mut Entries, config: &MemoryConfig, spill_dir: Option<&Path>) {
+    let Some(max_bytes) = config.max_bytes else {
+        return;
+    };
+    let mut size = entries.size();
+    if size <= max_bytes {
+        return;
+    }
+    let policy = config.policy.unwrap_or_default();
+    let min_size = config.min_size();
+    let mut changed = vec![];
+    let Entries {
+        values,
+        hashes,
+        trash,
+        ..
+    } = entries;
+    let mut known: HashSet<u64> = hashes
+        .iter()
+        .copied()
+        .chain(trash.iter().map(|trashed| trashed.item.hash))
+        .collect();
+    let trashed = trash
+        .iter_mut()
+        .rev()
+        .map(|trashed| (&mut trashed.item, None));
+    let history = values
+        .iter_mut()
+        .zip(hashes.iter_mut())
+        .rev()
+        .filter(|(item, _)| !item.favorite && item.pin.is_none())
+        .map(|(item, hash)| (item, Some(hash)));
+    for (item, hash) in trashed.chain(history) {
+        if size <= max_bytes {
+            break;
+        }
+        if item.keychain || item.spilled || is_secret(item) || item.value.len() <= min_size {
+            continue;
+        }
+        let before = item.value.len();
+        if policy == MemoryPolicy::Truncate && item.kind == ItemKind::Text {
+            if !truncate(item, min_size, &mut known) {
+                continue;
+            }
+            if let Some(hash) = hash {
+                *hash = item.hash;
+            }
+        } else {
+            // incognito values never touch disk
+            let Some(dir) = spill_dir.filter(|_| !item.ephemeral) else {
+                continue;
+            };
+            if let Err(err) = spill(dir, item) {
+                eprintln!("spilling {} failed: {err:#}", item.id);
+                continue;
+            }
+        }
+        size = (size + item.value.len()).saturating_sub(before);
+        changed.push(item.id.clone());
+    }
+    for id in changed {
+        entries.mark(&id);
+    }
+}
+
+// Called on save to db file: drops spilled values of items gone from
+// history and trash, dir belongs to that db only.
+pub(crate) fn sync(dir: &Path, entries: &Entries) {
+    let Ok(files) = fs::read_dir(dir) else {
+        return;
+    };
+    let spilled: HashSet<&str> = entries
+        .values
+        .iter()
+        .chain(entries.trash.iter().map(|trashed| &trashed.item))
+        .filter(|item| item.spilled)
+        .map(|item| item.id.as_str())
+        .collect();
+    for file in files.flatten() {
+        if !spilled.contains(file.file_name().to_string_lossy().as_ref()) {
+            if let Err(err) = fs::remove_file(file.path()) {
+                eprintln!("spilled value {:?} isn't removed: {err}", file.file_name());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_truncate() {
+        let mut item = Item::new(String::from("héllo"));
+        let mut known = HashSet::from([item.hash]);
+        assert!(truncate(&mut item, 2, &mut known));
+        assert_eq!(item.value, "h");
+        assert_eq!(item.hash, calculate_hash("h"));
+        let mut other = Item::new(String::from("hi"));
+        assert!(!truncate(&mut other, 1, &mut known));
+        assert_eq!(other.value, "hi");
+    }
+
+    #[test]
+    fn test_shrink() {
+        let mut entries = Entries::new();
+        entries.insert(String::from("aaaa-first"));
+        let image = entries.insert(clipr_common::image_value(b"large image data"));
+        image.kind = ItemKind::Image {
+            mime: String::from("image/png"),
+        };
+        let image = image.value.clone();
+        entries.insert(String::from("aaaa-second"));
+        let config = MemoryConfig {
+            max_bytes: Some(8),
+            policy: Some(MemoryPolicy::Truncate),
+            min_size: Some(4),
+        };
+        shrink(&mut entries, &config, None);
+        let values: Vec<&str> = entries.values.iter().map(|i| i.value.as_str()).collect();
+        // second one would duplicate first one cut, image isn't cut
+        assert_eq!(values, ["aaaa-second", image.as_str(), "aaaa"]);
+        assert!(entries.is_consistent());
+    }
+}
@@ -0,0 +1,65 @@
+use anyhow::{Context, Result};
+use clipr_common::template::render_item;
+use clipr_common::{DisplayConfig, Item, DEFAULT_NOTE_TEMPLATE};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+// Items (newest first, as selected) rendered oldest first, so appended note
+// reads in copy order. Keychain items are left out, note is plain text.
+fn render(items: &[(usize, Item)], template: Option<&str>, display: &DisplayConfig) -> Vec<String> {
+    let template = template.unwrap_or(DEFAULT_NOTE_TEMPLATE);
+    items
+        .iter()
+        .rev()
+        .filter(|(_, item)| !item.keychain)
+        .map(|(index, item)| render_item(template, *index, item, display))
+        .collect()
+}
+
+// returns number of appended items
+pub fn export(
+    path: &Path,
+    items: &[(usize, Item)],
+    template: Option<&str>,
+    display: &DisplayConfig,
+) -> Result<usize> {
+    let mut display = display.clone();
+    display.color = false;
+    let blocks = render(items, template, &display);
+    if blocks.is_empty() {
+        return Ok(0);
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("can't open {}", path.display()))?;
+    // blank line keeps appended blocks apart from existing note text
+    writeln!(file)?;
+    for block in &blocks {
+        writeln!(file, "{}", block.trim_end())?;
+    }
+    Ok(blocks.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut first = Item::new(String::from("https://example.com"));
+        first.tags = Some([String::from("research")].into_iter().collect());
+        let second = Item::new(String::from("quote"));
+        let items = vec![(0, second), (1, first)];
+        assert_eq!(
+            render(
+                &items,
+                Some("- {value} [{tags}]"),
+                &DisplayConfig::default()
+            ),
+            vec!["- https://example.com [research]", "- quote []"]
+        );
+    }
+}
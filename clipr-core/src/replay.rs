@@ -0,0 +1,158 @@
+use crate::clipboard::NoClipboard;
+use crate::storage::MemoryStorage;
+use crate::DaemonBuilder;
+use anyhow::{Context, Result};
+use clipr_common::{Command, Entries, ItemKind, Request, Response, SourceApp};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+// one line of `--record` file
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Recorded {
+    Sync {
+        value: String,
+        kind: ItemKind,
+        source_app: Option<SourceApp>,
+        #[serde(default)]
+        change_count: Option<i64>,
+    },
+    Command {
+        command: Command,
+    },
+}
+
+impl Recorded {
+    // subscriptions and quit aren't recorded, neither is lock state (unlock
+    // carries passphrase), replayed daemon runs unlocked
+    pub(crate) fn from_request(request: &Request) -> Option<Self> {
+        match request {
+            Request::Command(Command::Unlock { .. } | Command::Lock, _) => None,
+            Request::Sync(value, kind, source_app, change_count) => Some(Recorded::Sync {
+                value: value.clone(),
+                kind: kind.clone(),
+                source_app: source_app.clone(),
+                change_count: *change_count,
+            }),
+            Request::Command(command, _) => Some(Recorded::Command {
+                command: command.clone(),
+            }),
+            // mirrored history is primary daemon's state
+            Request::Subscribe(_) | Request::Mirror(_) | Request::Quit => None,
+        }
+    }
+}
+
+// Appends requests seen by event loop to file as JSON lines, every line is
+// flushed so crashed daemon leaves complete log.
+pub struct Recorder {
+    file: File,
+}
+
+impl Recorder {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("can't open {}", path.display()))?;
+        Ok(Self { file })
+    }
+
+    pub fn record(&mut self, request: &Request) {
+        if let Some(recorded) = Recorded::from_request(request) {
+            self.write(&recorded);
+        }
+    }
+
+    pub fn write(&mut self, recorded: &Recorded) {
+        let written = serde_json::to_string(recorded)
+            .map_err(anyhow::Error::from)
+            .and_then(|line| Ok(writeln!(self.file, "{line}")?));
+        if let Err(err) = written {
+            eprintln!("request isn't recorded: {err}");
+        }
+    }
+}
+
+pub fn read(path: &Path) -> Result<Vec<Recorded>> {
+    let file = File::open(path).with_context(|| format!("can't open {}", path.display()))?;
+    BufReader::new(file)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.as_ref().is_ok_and(|line| line.trim().is_empty()))
+        .map(|(number, line)| {
+            serde_json::from_str(&line?)
+                .with_context(|| format!("{}:{}", path.display(), number + 1))
+        })
+        .collect()
+}
+
+// Feeds recorded requests through event loop of fresh daemon (no clipboard,
+// nothing is loaded or saved, no other side effects either), prints every
+// command with its response and returns resulting history. Stops at
+// recorded `quit`.
+pub async fn replay(mut builder: DaemonBuilder, path: &Path) -> Result<Entries> {
+    let records = read(path)?;
+    let storage = MemoryStorage::default();
+    builder.config.lock = None;
+    builder
+        .clipboard(NoClipboard)
+        .storage(storage.clone())
+        .without_side_effects()
+        .transport(|handle| async move {
+            for record in records {
+                match record {
+                    Recorded::Sync {
+                        value,
+                        kind,
+                        source_app,
+                        change_count,
+                    } => {
+                        let _ = handle
+                            .sender
+                            .send(Request::Sync(value, kind, source_app, change_count))
+                            .await;
+                    }
+                    Recorded::Command { command } => {
+                        println!("> {command:?}");
+                        match handle.call(command).await {
+                            Some(Response::Payload(payload)) => {
+                                println!("{}", String::from(&payload))
+                            }
+                            Some(Response::Error(err)) => println!("error: {err}"),
+                            _ => return,
+                        }
+                    }
+                }
+            }
+            let _ = handle.call(Command::Quit).await;
+        })
+        .build()
+        .run()
+        .await?;
+    Ok(storage.take().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_request() {
+        let sync = Request::Sync(String::from("value"), ItemKind::Text, None, Some(1));
+        let line = serde_json::to_string(&Recorded::from_request(&sync).unwrap()).unwrap();
+        assert!(matches!(
+            serde_json::from_str(&line).unwrap(),
+            Recorded::Sync { value, .. } if value == "value"
+        ));
+        let (sender, _) = async_std::channel::bounded(1);
+        let unlock = Command::Unlock {
+            passphrase: String::from("hunter2"),
+        };
+        assert!(Recorded::from_request(&Request::Command(unlock, sender)).is_none());
+        assert!(Recorded::from_request(&Request::Quit).is_none());
+    }
+}
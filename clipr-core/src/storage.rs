@@ -0,0 +1,125 @@
+use crate::memory;
+use crate::wal::{Wal, CHECKPOINT_RECORDS};
+use anyhow::Result;
+use clipr_common::{Changes, Entries};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+// Where history is kept between runs (`save`/`load` commands, exit).
+pub trait Storage: Send + Sync {
+    fn load(&self) -> Result<Entries>;
+    // gets Entries::persistent copy, secret values are in keychain already
+    fn save(&self, entries: &Entries) -> Result<()>;
+    // called after every mutating request with live history (ephemeral
+    // items included) and what changed in it, so changes since last save
+    // survive a crash
+    fn log(&self, _entries: &Entries, _changes: &Changes) -> Result<()> {
+        Ok(())
+    }
+    // where values over MemoryConfig::max_bytes go, None if storage keeps
+    // nothing on disk (they are never spilled then)
+    fn spill_dir(&self) -> Option<PathBuf> {
+        None
+    }
+}
+
+// pretty printed JSON file, default one (see Config::db_path)
+pub struct FileStorage {
+    path: PathBuf,
+    wal: Option<Mutex<Wal>>,
+}
+
+impl FileStorage {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, wal: None }
+    }
+
+    // changes are logged to `<db>.wal` between saves (see wal)
+    pub fn with_wal(self) -> Self {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".wal");
+        Self {
+            wal: Some(Mutex::new(Wal::new(path.into()))),
+            ..self
+        }
+    }
+}
+
+impl Storage for FileStorage {
+    fn load(&self) -> Result<Entries> {
+        let data = std::fs::read_to_string(&self.path)?;
+        let mut entries = serde_json::from_str(&data)?;
+        if let Some(wal) = &self.wal {
+            let records = wal.lock().unwrap().replay(&mut entries)?;
+            if records > 0 {
+                eprintln!("wal: {records} unsaved changes recovered");
+            }
+        }
+        Ok(entries)
+    }
+
+    fn save(&self, entries: &Entries) -> Result<()> {
+        // default location (see paths::data_dir) may not exist yet
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // crash while writing leaves previous db in place
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(entries)?)?;
+        std::fs::rename(&tmp, &self.path)?;
+        if let Some(dir) = self.spill_dir() {
+            memory::sync(&dir, entries);
+        }
+        if let Some(wal) = &self.wal {
+            wal.lock().unwrap().checkpoint()?;
+        }
+        Ok(())
+    }
+
+    // `<db>.spill/`, file per item id
+    fn spill_dir(&self) -> Option<PathBuf> {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".spill");
+        Some(path.into())
+    }
+
+    // log is folded into db once it's CHECKPOINT_RECORDS long
+    fn log(&self, entries: &Entries, changes: &Changes) -> Result<()> {
+        let Some(wal) = &self.wal else {
+            return Ok(());
+        };
+        if wal.lock().unwrap().append(entries, changes)? >= CHECKPOINT_RECORDS {
+            self.save(&entries.persistent())?;
+        }
+        Ok(())
+    }
+}
+
+// Keeps last saved history in memory, clones share it (see replay).
+#[derive(Clone, Default)]
+pub struct MemoryStorage {
+    entries: Arc<Mutex<Option<Entries>>>,
+}
+
+impl MemoryStorage {
+    pub fn take(&self) -> Option<Entries> {
+        self.entries.lock().unwrap().take()
+    }
+}
+
+impl Storage for MemoryStorage {
+    // empty history until first save
+    fn load(&self) -> Result<Entries> {
+        let entries = self.entries.lock().unwrap();
+        Ok(entries
+            .as_ref()
+            .map(Entries::persistent)
+            .unwrap_or_default())
+    }
+
+    fn save(&self, entries: &Entries) -> Result<()> {
+        *self.entries.lock().unwrap() = Some(entries.persistent());
+        Ok(())
+    }
+}
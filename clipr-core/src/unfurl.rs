@@ -0,0 +1,44 @@
+use anyhow::{anyhow, Result};
+use async_std::prelude::*;
+use clipr_common::unfurl::parse_title;
+use clipr_common::State;
+use std::sync::Arc;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+// `<title>` is expected in `<head>`, rest of the page isn't read
+const MAX_PAGE_SIZE: u64 = 64 * 1024;
+
+// Fetches page title of copied link and stores it on item (found by id,
+// indices may shift while page is loading).
+pub async fn unfurl(state: Arc<State>, id: String, url: String) {
+    let title = match async_std::future::timeout(TIMEOUT, fetch_title(url.trim())).await {
+        Ok(Ok(Some(title))) => title,
+        Ok(Ok(None)) => return,
+        Ok(Err(err)) => return eprintln!("unfurl of {url} failed: {err}"),
+        Err(_) => return eprintln!("unfurl of {url} timed out"),
+    };
+    let mut entries = state.entries.write().unwrap();
+    if let Some(index) = entries.resolve(&clipr_common::IndexOrId::Id(id)) {
+        if let Some(item) = entries.get(index) {
+            item.title = Some(title);
+        }
+    }
+}
+
+async fn fetch_title(url: &str) -> Result<Option<String>> {
+    let res = surf::get(url)
+        .middleware(surf::middleware::Redirect::default())
+        .await
+        .map_err(|err| anyhow!(err))?;
+    if !res.status().is_success()
+        || res
+            .content_type()
+            .is_none_or(|mime| mime.essence() != "text/html")
+    {
+        return Ok(None);
+    }
+    let mut page = vec![];
+    res.take(MAX_PAGE_SIZE).read_to_end(&mut page).await?;
+    Ok(parse_title(&String::from_utf8_lossy(&page)))
+}
@@ -0,0 +1,243 @@
+use crate::keychain::is_secret;
+use anyhow::Result;
+use clipr_common::{Changes, Entries, Item, Trashed};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+// log is folded into db after that many records
+pub const CHECKPOINT_RECORDS: usize = 100;
+
+// One line of log: what changed in history with a mutating request. Items
+// are kept as db keeps them (keychain values are placeholders), values of
+// secret ones reach disk only through keychain, so they aren't logged.
+// Such item is lost in a crash unless it's in db already.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Record {
+    // ids of history items, newest first, only when they changed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    order: Option<Vec<String>>,
+    // new or changed items
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    items: Vec<Item>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trash_order: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    trash: Vec<Trashed>,
+}
+
+impl Record {
+    fn is_empty(&self) -> bool {
+        self.order.is_none()
+            && self.items.is_empty()
+            && self.trash_order.is_none()
+            && self.trash.is_empty()
+    }
+
+    // Only changed items are looked at, order is listed when it changed.
+    // Ephemeral items are never saved, so they aren't logged either.
+    fn new(entries: &Entries, changes: &Changes) -> Self {
+        let logged =
+            |item: &Item| changes.items.contains(&item.id) && (item.keychain || !is_secret(item));
+        let values = entries.values.iter().filter(|item| !item.ephemeral);
+        Self {
+            order: changes
+                .reordered
+                .then(|| values.clone().map(|item| item.id.clone()).collect()),
+            items: values.filter(|item| logged(item)).cloned().collect(),
+            trash_order: changes.reordered.then(|| {
+                entries
+                    .trash
+                    .iter()
+                    .map(|trashed| trashed.item.id.clone())
+                    .collect()
+            }),
+            trash: entries
+                .trash
+                .iter()
+                .filter(|trashed| logged(&trashed.item))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+// Puts record on top of history, items not mentioned stay as they are.
+pub fn apply(entries: &mut Entries, record: Record) {
+    let order = record
+        .order
+        .unwrap_or_else(|| entries.values.iter().map(|item| item.id.clone()).collect());
+    let mut items: HashMap<String, Item> = std::mem::take(&mut entries.values)
+        .into_iter()
+        .chain(record.items)
+        .map(|item| (item.id.clone(), item))
+        .collect();
+    entries.values = order.iter().filter_map(|id| items.remove(id)).collect();
+    entries.hashes = entries.values.iter().map(|item| item.hash).collect();
+
+    let trash_order = record.trash_order.unwrap_or_else(|| {
+        entries
+            .trash
+            .iter()
+            .map(|trashed| trashed.item.id.clone())
+            .collect()
+    });
+    let mut trash: HashMap<String, Trashed> = std::mem::take(&mut entries.trash)
+        .into_iter()
+        .chain(record.trash)
+        .map(|trashed| (trashed.item.id.clone(), trashed))
+        .collect();
+    entries.trash = trash_order
+        .iter()
+        .filter_map(|id| trash.remove(id))
+        .collect();
+}
+
+// Write-ahead log of history changes since last save (`<db>.wal`), every
+// record is synced to disk before request is answered. Nothing is logged
+// until history is loaded or saved, so records always build on db.
+pub struct Wal {
+    path: PathBuf,
+    file: Option<File>,
+    started: bool,
+    records: usize,
+    // bytes of complete records, torn tail is cut before next append
+    len: u64,
+}
+
+impl Wal {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            file: None,
+            started: false,
+            records: 0,
+            len: 0,
+        }
+    }
+
+    // Applies logged records to history loaded from db, torn last line
+    // (crash in the middle of write) is dropped.
+    pub fn replay(&mut self, entries: &mut Entries) -> Result<usize> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err.into()),
+        };
+        let mut reader = BufReader::new(file);
+        let (mut records, mut len) = (0, 0);
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 0 {
+            if !line.ends_with('\n') {
+                eprintln!("wal: incomplete record {} dropped", records + 1);
+                break;
+            }
+            match serde_json::from_str::<Record>(&line) {
+                Ok(record) => apply(entries, record),
+                Err(err) => {
+                    eprintln!("wal: record {} dropped: {err}", records + 1);
+                    break;
+                }
+            }
+            records += 1;
+            len += line.len() as u64;
+            line.clear();
+        }
+        entries.take_changes();
+        self.started = true;
+        self.records = records;
+        self.len = len;
+        self.file = None;
+        Ok(records)
+    }
+
+    // Appends changes since last record, returns number of records in log
+    // (see CHECKPOINT_RECORDS).
+    pub fn append(&mut self, entries: &Entries, changes: &Changes) -> Result<usize> {
+        if !self.started {
+            return Ok(0);
+        }
+        let record = Record::new(entries, changes);
+        if record.is_empty() {
+            return Ok(self.records);
+        }
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?;
+                file.set_len(self.len)?;
+                self.file.insert(file)
+            }
+        };
+        let line = format!("{}\n", serde_json::to_string(&record)?);
+        file.write_all(line.as_bytes())?;
+        file.sync_data()?;
+        self.records += 1;
+        self.len += line.len() as u64;
+        Ok(self.records)
+    }
+
+    // entries are in db now
+    pub fn checkpoint(&mut self) -> Result<()> {
+        self.file = None;
+        match fs::remove_file(&self.path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+        self.started = true;
+        self.records = 0;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clipr_common::SECRET_TAG;
+
+    #[test]
+    fn test_record_apply() {
+        let mut entries = Entries::new();
+        entries.insert(String::from("first"));
+        entries.insert(String::from("second"));
+        let saved = serde_json::to_string(&entries).unwrap();
+        entries.take_changes();
+
+        entries.insert(String::from("third"));
+        entries.get(0).unwrap().ephemeral = true;
+        entries.tag(1, String::from("work"));
+        entries.delete(2, None);
+        entries.insert(String::from("hunter2"));
+        entries.tag(0, String::from(SECRET_TAG));
+        let changes = entries.take_changes();
+        let record = Record::new(&entries, &changes);
+        assert_eq!(record.items.len(), 1);
+        assert_eq!(record.trash.len(), 1);
+        let line = serde_json::to_string(&record).unwrap();
+        assert!(!line.contains("hunter2"));
+        let changes = entries.take_changes();
+        assert!(Record::new(&entries, &changes).is_empty());
+
+        let mut recovered: Entries = serde_json::from_str(&saved).unwrap();
+        apply(&mut recovered, serde_json::from_str(&line).unwrap());
+        let values: Vec<&str> = recovered.values.iter().map(|i| i.value.as_str()).collect();
+        assert_eq!(values, ["second"]);
+        assert!(recovered
+            .values
+            .front()
+            .unwrap()
+            .tags
+            .as_ref()
+            .unwrap()
+            .contains("work"));
+        assert_eq!(recovered.trash[0].item.value, "first");
+        assert_eq!(recovered.hashes.len(), 1);
+    }
+}
@@ -0,0 +1,9 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    {
+        if std::env::var_os("PROTOC").is_none() {
+            std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().unwrap());
+        }
+        tonic_prost_build::compile_protos("proto/clipr.proto").unwrap();
+    }
+}
@@ -0,0 +1,42 @@
+use clipr_common::HttpConfig;
+use tide::{Middleware, Next, Request, StatusCode};
+
+// served without token: handshake, docs and relay (peers use their own key)
+const PUBLIC_PATHS: [&str; 4] = ["/version", "/openapi.json", "/docs", "/relay"];
+
+// Resolves `Authorization: Bearer <token>` to Access (401 for unknown one),
+// commands are checked against it by http::dispatch.
+pub struct Auth {
+    config: HttpConfig,
+}
+
+impl Auth {
+    pub fn new(config: HttpConfig) -> Self {
+        Self { config }
+    }
+}
+
+#[tide::utils::async_trait]
+impl<State: Clone + Send + Sync + 'static> Middleware<State> for Auth {
+    async fn handle(&self, mut req: Request<State>, next: Next<'_, State>) -> tide::Result {
+        let path = req.url().path();
+        if PUBLIC_PATHS
+            .iter()
+            .any(|public| path == *public || path.starts_with(&format!("{public}/")))
+        {
+            return Ok(next.run(req).await);
+        }
+        let token = req
+            .header("Authorization")
+            .and_then(|values| values.last().as_str().strip_prefix("Bearer "))
+            .map(str::trim);
+        let Some(access) = self.config.access(token) else {
+            return Err(tide::Error::from_str(
+                StatusCode::Unauthorized,
+                "missing or unknown token",
+            ));
+        };
+        req.set_ext(access);
+        Ok(next.run(req).await)
+    }
+}
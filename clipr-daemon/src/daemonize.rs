@@ -0,0 +1,43 @@
+use anyhow::{bail, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+// parent exits right away, child carries on
+fn fork() -> Result<()> {
+    match unsafe { libc::fork() } {
+        -1 => bail!("fork failed: {}", io::Error::last_os_error()),
+        0 => Ok(()),
+        _ => std::process::exit(0),
+    }
+}
+
+// Detaches from terminal (fork, setsid, fork again, so daemon never gets
+// controlling terminal back), stdin is /dev/null, stdout/stderr are appended
+// to log. Has to run before any thread is started, only grandchild returns.
+pub fn daemonize(log: &Path) -> Result<()> {
+    // opened upfront, so errors still reach the shell
+    if let Some(dir) = log.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let log_file = OpenOptions::new().create(true).append(true).open(log)?;
+    let null = File::open("/dev/null")?;
+    println!("detached, output goes to {}", log.display());
+
+    fork()?;
+    if unsafe { libc::setsid() } == -1 {
+        bail!("setsid failed: {}", io::Error::last_os_error());
+    }
+    fork()?;
+    for (from, to) in [
+        (null.as_raw_fd(), libc::STDIN_FILENO),
+        (log_file.as_raw_fd(), libc::STDOUT_FILENO),
+        (log_file.as_raw_fd(), libc::STDERR_FILENO),
+    ] {
+        if unsafe { libc::dup2(from, to) } == -1 {
+            bail!("dup2 failed: {}", io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
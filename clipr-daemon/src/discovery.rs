@@ -0,0 +1,70 @@
+use anyhow::Result;
+use clipr_common::{Peer, State};
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const SERVICE_TYPE: &str = "_clipr._tcp.local.";
+
+fn instance_name(fullname: &str) -> String {
+    fullname
+        .trim_end_matches(SERVICE_TYPE)
+        .trim_end_matches('.')
+        .to_string()
+}
+
+fn peer_from(info: &ServiceInfo) -> Option<Peer> {
+    let mut addrs: Vec<_> = info.get_addresses_v4().into_iter().collect();
+    addrs.sort();
+    addrs.first().map(|addr| Peer {
+        name: instance_name(info.get_fullname()),
+        address: format!("{}:{}", addr, info.get_port()),
+    })
+}
+
+// Announces this daemon over mDNS and keeps `state.peers` in sync with
+// other daemons announced on the same LAN.
+pub async fn discovery(state: Arc<State>) {
+    if let Err(err) = run(state).await {
+        eprintln!("discovery disabled: {err}");
+    }
+}
+
+async fn run(state: Arc<State>) -> Result<()> {
+    let mdns = ServiceDaemon::new()?;
+    let hostname = gethostname::gethostname().to_string_lossy().to_string();
+    let hostname = hostname.trim_end_matches(".local");
+    let port = state.config.port.unwrap();
+
+    let info = ServiceInfo::new(
+        SERVICE_TYPE,
+        &format!("{hostname}-{port}"),
+        &format!("{hostname}.local."),
+        "",
+        port,
+        None::<HashMap<String, String>>,
+    )?
+    .enable_addr_auto();
+    let own = info.get_fullname().to_string();
+    mdns.register(info)?;
+
+    let events = mdns.browse(SERVICE_TYPE)?;
+    while let Ok(event) = events.recv_async().await {
+        match event {
+            ServiceEvent::ServiceResolved(info) if info.get_fullname() != own => {
+                if let Some(peer) = peer_from(&info) {
+                    let mut peers = state.peers.lock().unwrap();
+                    peers.retain(|p| p.name != peer.name);
+                    peers.push(peer);
+                }
+            }
+            ServiceEvent::ServiceRemoved(_, fullname) => {
+                let name = instance_name(&fullname);
+                state.peers.lock().unwrap().retain(|p| p.name != name);
+            }
+            _ => continue,
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,434 @@
+use anyhow::Result;
+use async_std::channel::{bounded, Sender};
+use async_std::stream::{Stream, StreamExt};
+use clap::ValueEnum;
+use clipr_common::range::Position;
+use clipr_common::transform::Transform;
+use clipr_common::{Command, Filter, IndexOrId, Payload, Request};
+use std::pin::Pin;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tonic::{Response, Status};
+
+pub mod proto {
+    tonic::include_proto!("clipr");
+}
+
+use proto::clipr_server::{Clipr, CliprServer};
+
+fn invalid<E: std::fmt::Display>(err: E) -> Status {
+    Status::invalid_argument(err.to_string())
+}
+
+fn target(index: u64, id: Option<String>) -> IndexOrId {
+    match id {
+        Some(id) => IndexOrId::Id(id),
+        None => IndexOrId::Index(index as usize),
+    }
+}
+
+impl From<proto::Select> for Filter {
+    fn from(select: proto::Select) -> Self {
+        Self {
+            pin: select.pin,
+            tag: select.tag,
+            value: select.value,
+            app: select.app,
+            note: select.note,
+            title: select.title,
+            regex: select.regex,
+            older_than: select.older_than,
+            newer_than: select.newer_than,
+            kind: None,
+        }
+    }
+}
+
+impl TryFrom<proto::Command> for Command {
+    type Error = Status;
+
+    fn try_from(cmd: proto::Command) -> Result<Self, Self::Error> {
+        use proto::command::Command as C;
+
+        let cmd = cmd
+            .command
+            .ok_or_else(|| Status::invalid_argument("empty command"))?;
+
+        Ok(match cmd {
+            C::Add(add) => Command::Add { value: add.value },
+            C::Del(del) => Command::Del {
+                from_index: Position::Index(del.from_index as i64),
+                to_index: del.to_index.map(|i| i as i64),
+                expected_hash: del.expected_hash,
+                dry_run: del.dry_run,
+            },
+            C::List(list) => Command::List {
+                from_index: list.from_index.map(|i| Position::Index(i as i64)),
+                to_index: list.to_index.map(|i| i as i64),
+                preview_length: list.preview_length.map(|i| i as usize),
+                favorites: list.favorites,
+                ranked: list.ranked,
+                template: None,
+                filter: Filter::default(),
+            },
+            C::Get(get) => Command::Get {
+                target: target(get.index, get.id),
+                raw: false,
+                stats: false,
+            },
+            C::Set(set) => Command::Set {
+                target: target(set.index, set.id),
+                pasteboard: set.pasteboard,
+                expected_hash: set.expected_hash,
+                transform: set
+                    .transform
+                    .map(|name| Transform::from_str(&name, true))
+                    .transpose()
+                    .map_err(Status::invalid_argument)?,
+            },
+            C::Insert(insert) => Command::Insert {
+                filename: insert.filename,
+                mime: insert.mime,
+                data: insert.data,
+            },
+            C::Tag(tag) => Command::Tag {
+                target: target(tag.index, tag.id),
+                tag: tag.tag,
+            },
+            C::Untag(tag) => Command::Untag {
+                target: target(tag.index, tag.id),
+                tag: tag.tag,
+            },
+            C::Pin(pin) => Command::Pin {
+                target: target(pin.index, pin.id),
+                pin: pin
+                    .pin
+                    .chars()
+                    .next()
+                    .ok_or_else(|| Status::invalid_argument("empty pin"))?,
+            },
+            C::Unpin(unpin) => Command::Unpin {
+                target: target(unpin.index, unpin.id),
+            },
+            C::Tags(_) => Command::Tags,
+            C::Count(_) => Command::Count,
+            C::Status(status) => Command::Status {
+                peers: status.peers,
+            },
+            C::Save(_) => Command::Save,
+            C::Load(_) => Command::Load,
+            C::Select(select) => Command::Select {
+                set: select.set,
+                all: select.all,
+                filter: select.into(),
+            },
+            C::Clear(clear) => Command::Clear {
+                keep_pinned: clear.keep_pinned,
+                system: clear.system,
+            },
+            C::Dedup(dedup) => Command::Dedup {
+                dry_run: dedup.dry_run,
+            },
+            C::History(history) => Command::History {
+                day: history
+                    .day
+                    .map(|day| day.parse())
+                    .transpose()
+                    .map_err(invalid)?,
+            },
+            C::Prune(prune) => Command::Prune {
+                selector: prune.selector.map(Filter::from).unwrap_or_default(),
+                dry_run: prune.dry_run,
+            },
+            C::Plugin(plugin) => Command::Plugin {
+                name: plugin.name,
+                args: plugin.args,
+            },
+            C::Plugins(_) => Command::Plugins,
+            C::Ring(_) => Command::Ring,
+            C::Fav(fav) => Command::Fav {
+                target: target(fav.index, fav.id),
+            },
+            C::Unfav(unfav) => Command::Unfav {
+                target: target(unfav.index, unfav.id),
+            },
+            C::Note(note) => Command::Note {
+                target: target(note.index, note.id),
+                text: vec![note.text],
+            },
+            C::Help(_) => Command::Help,
+            C::Quit(_) => Command::Quit,
+            C::Raw(raw) => serde_json::from_str(&raw).map_err(invalid)?,
+        })
+    }
+}
+
+impl From<&clipr_common::Item> for proto::Item {
+    fn from(item: &clipr_common::Item) -> Self {
+        let mut tags: Vec<String> = item.tags.iter().flatten().cloned().collect();
+        tags.sort();
+        Self {
+            value: item.value.clone(),
+            access_counter: item.access_counter,
+            accessed_at: timestamp(item.accessed_at),
+            tags,
+            pin: item.pin.map(String::from),
+            source_app: item.source_app.clone(),
+            expires_at: item.expires_at.map(timestamp),
+            note: item.note.clone(),
+            favorite: item.favorite,
+            id: item.id.clone(),
+            files: files(&item.kind),
+            image: image(&item.kind),
+            title: item.title.clone(),
+            ephemeral: item.ephemeral,
+            keychain: item.keychain,
+            set_count: item.set_count,
+            hash: item.hash,
+            original_url: item.original_url.clone(),
+        }
+    }
+}
+
+fn files(kind: &clipr_common::ItemKind) -> Vec<String> {
+    match kind {
+        clipr_common::ItemKind::Files(paths) => paths
+            .iter()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn image(kind: &clipr_common::ItemKind) -> Option<String> {
+    match kind {
+        clipr_common::ItemKind::Image { mime } => Some(mime.clone()),
+        _ => None,
+    }
+}
+
+fn timestamp(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+impl From<&clipr_common::ItemSummary> for proto::ItemSummary {
+    fn from(item: &clipr_common::ItemSummary) -> Self {
+        let mut tags: Vec<String> = item.tags.iter().flatten().cloned().collect();
+        tags.sort();
+        Self {
+            id: item.id.clone(),
+            preview: item.preview.clone(),
+            hash: item.hash,
+            access_counter: item.access_counter,
+            accessed_at: timestamp(item.accessed_at),
+            tags,
+            pin: item.pin.map(String::from),
+            source_app: item.source_app.clone(),
+            expires_at: item.expires_at.map(timestamp),
+            note: item.note.clone(),
+            favorite: item.favorite,
+            files: files(&item.kind),
+            image: image(&item.kind),
+            title: item.title.clone(),
+            ephemeral: item.ephemeral,
+            set_count: item.set_count,
+            language: item.language.clone(),
+            change_count: item.change_count,
+            matches: item
+                .matches
+                .iter()
+                .map(|&(start, end)| proto::Match {
+                    start: start as u64,
+                    end: end as u64,
+                })
+                .collect(),
+        }
+    }
+}
+
+fn summary_list(items: &[(usize, clipr_common::ItemSummary)]) -> Vec<proto::IndexedSummary> {
+    items
+        .iter()
+        .map(|(index, item)| proto::IndexedSummary {
+            index: *index as u64,
+            item: Some(item.into()),
+        })
+        .collect()
+}
+
+impl From<Payload> for proto::Payload {
+    fn from(payload: Payload) -> Self {
+        use proto::payload::Payload as P;
+
+        fn item_list(items: &[(usize, clipr_common::Item)]) -> Vec<proto::IndexedItem> {
+            items
+                .iter()
+                .map(|(index, item)| proto::IndexedItem {
+                    index: *index as u64,
+                    item: Some(item.into()),
+                })
+                .collect()
+        }
+
+        let payload = match payload {
+            Payload::Ok => P::Ok(proto::Empty {}),
+            Payload::Stop => P::Stop(proto::Empty {}),
+            Payload::List {
+                value,
+                preview_length,
+                ..
+            } => P::List(proto::SummaryList {
+                value: summary_list(&value),
+                preview_length: preview_length.map(|l| l as u64),
+            }),
+            Payload::Ambiguous {
+                value,
+                preview_length,
+            } => P::Ambiguous(proto::SummaryList {
+                value: summary_list(&value),
+                preview_length: preview_length.map(|l| l as u64),
+            }),
+            Payload::History { value } => P::History(proto::HistoryReply {
+                days: value
+                    .iter()
+                    .map(|day| proto::Day {
+                        day: day.day.to_string(),
+                        count: day.count as u64,
+                        items: summary_list(&day.items),
+                    })
+                    .collect(),
+            }),
+            Payload::Value { value } => P::Value(proto::Value { value }),
+            Payload::Stats { value } => P::Stats(proto::Stats {
+                chars: value.chars as u64,
+                words: value.words as u64,
+                lines: value.lines as u64,
+                bytes: value.bytes as u64,
+                language: value.language,
+            }),
+            Payload::Values { value } => P::Values(proto::ValuesReply {
+                values: value
+                    .into_iter()
+                    .map(|value| proto::Value { value })
+                    .collect(),
+            }),
+            Payload::Message { value } => P::Message(value),
+            Payload::Status {
+                listen_on,
+                count,
+                peers,
+            } => P::Status(proto::StatusReply {
+                listen_on,
+                count: count as u64,
+                with_peers: peers.is_some(),
+                peers: peers
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|peer| proto::Peer {
+                        name: peer.name,
+                        address: peer.address,
+                    })
+                    .collect(),
+            }),
+            // answered with NOT_FOUND status
+            Payload::NotFound { target } => P::Message(format!("item at {target} not found")),
+            // answered with FAILED_PRECONDITION status
+            Payload::Locked => P::Message(String::from(&payload)),
+            // answered with INVALID_ARGUMENT status
+            Payload::InvalidArgs { .. } => P::Message(String::from(&payload)),
+            Payload::Conflict { index, hash } => P::Conflict(proto::ConflictReply {
+                index: index as u64,
+                hash,
+            }),
+            Payload::Groups { value } => P::Groups(proto::GroupsReply {
+                groups: value
+                    .iter()
+                    .map(|group| proto::ItemList {
+                        value: item_list(group),
+                        preview_length: None,
+                    })
+                    .collect(),
+            }),
+            Payload::Tags { value } => P::Tags(proto::TagsReply {
+                tags: value
+                    .into_iter()
+                    .map(|(tag, count)| proto::TagCount {
+                        tag,
+                        count: count as u64,
+                    })
+                    .collect(),
+            }),
+            Payload::Plugin { name, value } => P::Plugin(proto::PluginReply {
+                name,
+                value: value.to_string(),
+            }),
+        };
+
+        Self {
+            payload: Some(payload),
+        }
+    }
+}
+
+struct Service {
+    sender: Sender<Request>,
+}
+
+#[tonic::async_trait]
+impl Clipr for Service {
+    type SubscribeStream = Pin<Box<dyn Stream<Item = Result<proto::Item, Status>> + Send>>;
+
+    async fn call(
+        &self,
+        request: tonic::Request<proto::Command>,
+    ) -> Result<Response<proto::Payload>, Status> {
+        let cmd = Command::try_from(request.into_inner())?;
+        match Request::send_cmd(&self.sender, cmd).await {
+            Some(clipr_common::Response::Payload(payload @ Payload::NotFound { .. })) => {
+                Err(Status::not_found(String::from(&payload)))
+            }
+            Some(clipr_common::Response::Payload(payload @ Payload::Locked)) => {
+                Err(Status::failed_precondition(String::from(&payload)))
+            }
+            Some(clipr_common::Response::Payload(payload @ Payload::InvalidArgs { .. })) => {
+                Err(Status::invalid_argument(String::from(&payload)))
+            }
+            Some(clipr_common::Response::Payload(payload)) => Ok(Response::new(payload.into())),
+            Some(clipr_common::Response::Error(err)) => Err(Status::internal(err)),
+            _ => Err(Status::unavailable("daemon is shutting down")),
+        }
+    }
+
+    async fn subscribe(
+        &self,
+        _request: tonic::Request<proto::SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        let (tx, rx) = bounded(16);
+        self.sender
+            .send(Request::Subscribe(tx))
+            .await
+            .map_err(|_| Status::unavailable("daemon is shutting down"))?;
+        let items = rx.filter_map(|response| match response {
+            clipr_common::Response::NewItem(item) => Some(Ok(proto::Item::from(item.as_ref()))),
+            _ => None,
+        });
+        Ok(Response::new(Box::pin(items)))
+    }
+}
+
+async fn serve(listen_on: String, sender: Sender<Request>) -> Result<()> {
+    tonic::transport::Server::builder()
+        .add_service(CliprServer::new(Service { sender }))
+        .serve(listen_on.parse()?)
+        .await?;
+    Ok(())
+}
+
+// tonic needs tokio, so gRPC server lives on its own runtime thread.
+pub fn spawn(listen_on: String, sender: Sender<Request>) {
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        if let Err(err) = runtime.block_on(serve(listen_on, sender)) {
+            eprintln!("grpc server failed: {err}");
+        }
+    });
+}
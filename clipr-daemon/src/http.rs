@@ -0,0 +1,392 @@
+use crate::auth::Auth;
+use crate::limits::{BodyLimit, RateLimit};
+use anyhow::Result;
+use async_std::channel::{bounded, Sender};
+use async_std::io::Cursor;
+use clipr_common::range::Position;
+use clipr_common::version::VersionInfo;
+use clipr_common::{image_data, Access, Command, ErrorBody, Filter, IndexOrId, ItemKind, Payload};
+use clipr_core::keychain;
+use serde::de::DeserializeOwned;
+use std::borrow::Cow;
+use std::str::FromStr;
+use std::sync::Arc;
+use tide::http::Mime;
+use tide::prelude::*;
+use tide::{Body, Response, StatusCode};
+
+static OPENAPI: &str = include_str!("openapi.json");
+static SWAGGER: &str = include_str!("swagger.html");
+
+// Read-only commands are served right here under entries read lock,
+// everything else goes through event loop.
+#[derive(Clone)]
+struct HttpState {
+    sender: Sender<clipr_common::Request>,
+    state: Arc<clipr_common::State>,
+}
+
+type Request = tide::Request<HttpState>;
+
+async fn dispatch(req: &Request, cmd: Command) -> tide::Result<Body> {
+    Body::from_json(&call(req, cmd).await?)
+}
+
+async fn call(req: &Request, cmd: Command) -> tide::Result<Payload> {
+    // set by auth::Auth
+    let access = req.ext::<Access>().copied().unwrap_or_default();
+    if access == Access::Read && !cmd.is_read_access() {
+        return Err(tide::Error::from_str(
+            StatusCode::Forbidden,
+            "token is read-only",
+        ));
+    }
+    let response = if cmd.is_read_only() {
+        Some(clipr_common::Response::Payload(clipr_core::read_call(
+            &req.state().state,
+            &cmd,
+        )))
+    } else {
+        clipr_common::Request::send_cmd(&req.state().sender, cmd).await
+    };
+    match response {
+        Some(clipr_common::Response::Payload(val @ Payload::NotFound { .. })) => Err(
+            tide::Error::from_str(StatusCode::NotFound, String::from(&val)),
+        ),
+        Some(clipr_common::Response::Payload(val @ Payload::Locked)) => Err(tide::Error::from_str(
+            StatusCode::Locked,
+            String::from(&val),
+        )),
+        Some(clipr_common::Response::Payload(val @ Payload::InvalidArgs { .. })) => Err(
+            tide::Error::from_str(StatusCode::BadRequest, String::from(&val)),
+        ),
+        Some(clipr_common::Response::Payload(val)) => Ok(val),
+        Some(clipr_common::Response::Error(err)) => {
+            Err(tide::Error::from_str(StatusCode::InternalServerError, err))
+        }
+        _ => Err(tide::Error::from_str(
+            StatusCode::ServiceUnavailable,
+            "daemon is shutting down",
+        )),
+    }
+}
+
+// every error is answered with JSON ErrorBody
+async fn error_body(mut res: Response) -> tide::Result<Response> {
+    if let Some(err) = res.error() {
+        let error = match res.status() {
+            StatusCode::BadRequest | StatusCode::UnprocessableEntity => "invalid-request",
+            StatusCode::Unauthorized => "unauthorized",
+            StatusCode::Forbidden => "forbidden",
+            StatusCode::NotFound => "not-found",
+            StatusCode::PayloadTooLarge => "too-large",
+            StatusCode::Locked => "locked",
+            StatusCode::TooManyRequests => "rate-limited",
+            StatusCode::ServiceUnavailable => "unavailable",
+            _ => "internal",
+        };
+        let body = ErrorBody {
+            error: error.to_string(),
+            detail: err.to_string(),
+        };
+        res.set_body(Body::from_json(&body)?);
+    }
+    Ok(res)
+}
+
+fn index_param(req: &Request) -> tide::Result<usize> {
+    req.param("index")?
+        .parse()
+        .map_err(|err| tide::Error::new(StatusCode::BadRequest, err))
+}
+
+#[derive(Deserialize)]
+struct ListQuery {
+    // `from_index=..20` ranges too, see range::Position
+    from_index: Option<Position>,
+    to_index: Option<i64>,
+    preview_length: Option<usize>,
+    #[serde(default)]
+    favorites: bool,
+    #[serde(default)]
+    ranked: bool,
+}
+
+#[derive(Deserialize)]
+struct CallbackQuery {
+    // position or id, see IndexOrId::from_str
+    index: String,
+    pasteboard: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DeleteQuery {
+    expected_hash: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct InsertQuery {
+    filename: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NewItem {
+    value: String,
+}
+
+// strict (unknown fields are rejected), 400 on any mismatch
+async fn json_body<T: DeserializeOwned>(req: &mut Request) -> tide::Result<T> {
+    let body = req.body_string().await?;
+    serde_json::from_str(&body).map_err(|err| tide::Error::new(StatusCode::BadRequest, err))
+}
+
+async fn command(mut req: Request) -> tide::Result<Body> {
+    let cmd: Command = json_body(&mut req).await?;
+    dispatch(&req, cmd).await
+}
+
+async fn list_items(req: Request) -> tide::Result<Body> {
+    let query: ListQuery = req.query()?;
+    let cmd = Command::List {
+        from_index: query.from_index,
+        to_index: query.to_index,
+        preview_length: query.preview_length,
+        favorites: query.favorites,
+        ranked: query.ranked,
+        template: None,
+        filter: Filter::default(),
+    };
+    dispatch(&req, cmd).await
+}
+
+async fn add_item(mut req: Request) -> tide::Result<Body> {
+    let item: NewItem = json_body(&mut req).await?;
+    let cmd = Command::Add {
+        value: vec![item.value],
+    };
+    dispatch(&req, cmd).await
+}
+
+// raw body is put on pasteboard, Content-Type is its mime type
+async fn insert(mut req: Request) -> tide::Result<Body> {
+    let query: InsertQuery = req.query()?;
+    let mime = req.content_type().map(|mime| mime.essence().to_string());
+    let data = req.body_bytes().await?;
+    let cmd = Command::Insert {
+        filename: query.filename.unwrap_or_else(|| "-".to_string()),
+        mime,
+        data: Some(data),
+    };
+    dispatch(&req, cmd).await
+}
+
+// accepts item id as well as index
+async fn get_item(req: Request) -> tide::Result<Body> {
+    let target = req.param("index")?.parse()?;
+    let cmd = Command::Get {
+        target,
+        raw: false,
+        stats: false,
+    };
+    dispatch(&req, cmd).await
+}
+
+// counts and guessed language, without value
+async fn get_item_stats(req: Request) -> tide::Result<Body> {
+    let target = req.param("index")?.parse()?;
+    let cmd = Command::Get {
+        target,
+        raw: false,
+        stats: true,
+    };
+    dispatch(&req, cmd).await
+}
+
+// value as chunked text/plain body (images as they were inserted),
+// without building JSON around it
+async fn get_item_raw(req: Request) -> tide::Result<Response> {
+    let target: IndexOrId = req.param("index")?.parse()?;
+    if let Some(locked) = req.state().state.check_lock(&Command::Get {
+        target: target.clone(),
+        raw: true,
+        stats: false,
+    }) {
+        return Err(tide::Error::from_str(
+            StatusCode::Locked,
+            String::from(&locked),
+        ));
+    }
+    let item = {
+        let entries = req.state().state.entries.read().unwrap();
+        entries
+            .resolve(&target)
+            .and_then(|index| entries.get_item(index))
+            .map(|item| {
+                (
+                    keychain::value(&req.state().state, item).map(Cow::into_owned),
+                    item.kind.clone(),
+                )
+            })
+    };
+    let Some((value, kind)) = item else {
+        return Err(tide::Error::from_str(
+            StatusCode::NotFound,
+            format!("item at {target} not found"),
+        ));
+    };
+    let value = value?;
+    let (data, mime) = match kind {
+        ItemKind::Image { mime } => (image_data(&value)?, Mime::from_str(&mime)?),
+        _ => (value.into_bytes(), tide::http::mime::PLAIN),
+    };
+    Ok(Response::builder(StatusCode::Ok)
+        .body(Body::from_reader(Cursor::new(data), None))
+        .content_type(mime)
+        .build())
+}
+
+async fn delete_item(req: Request) -> tide::Result<Body> {
+    let query: DeleteQuery = req.query()?;
+    let cmd = Command::Del {
+        from_index: index_param(&req)?.into(),
+        to_index: None,
+        expected_hash: query.expected_hash,
+        dry_run: false,
+    };
+    dispatch(&req, cmd).await
+}
+
+// Plain text answers of /x-callback routes, for Shortcuts "Get Contents of
+// URL" actions and `curl` calls from osascript.
+async fn text_reply(req: &Request, cmd: Command) -> tide::Result<Response> {
+    let payload = call(req, cmd).await?;
+    Ok(Response::builder(StatusCode::Ok)
+        .body(payload.format(&req.state().state.config.display()))
+        .content_type(tide::http::mime::PLAIN)
+        .build())
+}
+
+async fn callback_list(req: Request) -> tide::Result<Response> {
+    let query: ListQuery = req.query()?;
+    let cmd = Command::List {
+        from_index: query.from_index,
+        to_index: query.to_index,
+        preview_length: query.preview_length,
+        favorites: query.favorites,
+        ranked: query.ranked,
+        template: None,
+        filter: Filter::default(),
+    };
+    text_reply(&req, cmd).await
+}
+
+async fn callback_get(req: Request) -> tide::Result<Response> {
+    let query: CallbackQuery = req.query()?;
+    let cmd = Command::Get {
+        target: query.index.parse()?,
+        raw: false,
+        stats: false,
+    };
+    text_reply(&req, cmd).await
+}
+
+// GET on purpose, Shortcuts and osascript can't easily send bodies
+async fn callback_set(req: Request) -> tide::Result<Response> {
+    let query: CallbackQuery = req.query()?;
+    let cmd = Command::Set {
+        target: query.index.parse()?,
+        pasteboard: query.pasteboard,
+        expected_hash: None,
+        transform: None,
+    };
+    text_reply(&req, cmd).await
+}
+
+// e.g. `curl -X POST .../pins/a/set` bound to hotkey
+async fn set_pin(req: Request) -> tide::Result<Body> {
+    let pin = req.param("pin")?;
+    let mut chars = pin.chars();
+    let (Some(pin), None) = (chars.next(), chars.next()) else {
+        return Err(tide::Error::from_str(
+            StatusCode::BadRequest,
+            format!("pin is a single char, got {pin:?}"),
+        ));
+    };
+    let cmd = Command::SetPin {
+        pin,
+        pasteboard: None,
+    };
+    dispatch(&req, cmd).await
+}
+
+async fn tags(req: Request) -> tide::Result<Body> {
+    dispatch(&req, Command::Tags).await
+}
+
+// server-sent `new-item` event per captured item
+async fn events(req: Request, sse: tide::sse::Sender) -> tide::Result<()> {
+    let (tx, rx) = bounded(16);
+    req.state()
+        .sender
+        .send(clipr_common::Request::Subscribe(tx))
+        .await
+        .map_err(|_| tide::Error::from_str(StatusCode::ServiceUnavailable, "shutting down"))?;
+    while let Ok(clipr_common::Response::NewItem(item)) = rx.recv().await {
+        sse.send("new-item", serde_json::to_string(&item)?, None)
+            .await?;
+    }
+    Ok(())
+}
+
+async fn version(_req: Request) -> tide::Result<Body> {
+    Body::from_json(&VersionInfo {
+        version: clipr_common::version::VERSION.to_string(),
+    })
+}
+
+async fn openapi(_req: Request) -> tide::Result<Response> {
+    Ok(Response::builder(StatusCode::Ok)
+        .body(OPENAPI)
+        .content_type(tide::http::mime::JSON)
+        .build())
+}
+
+async fn docs(_req: Request) -> tide::Result<Response> {
+    Ok(Response::builder(StatusCode::Ok)
+        .body(SWAGGER)
+        .content_type(tide::http::mime::HTML)
+        .build())
+}
+
+pub async fn http_server(
+    state: Arc<clipr_common::State>,
+    sender: Sender<clipr_common::Request>,
+) -> Result<()> {
+    let listen_on = state.config.listen_on();
+    let limits = state.config.http.clone().unwrap_or_default();
+    let mut app = tide::with_state(HttpState { sender, state });
+    app.with(tide::utils::After(error_body));
+    app.with(RateLimit::new(limits.rate_limit()));
+    app.with(Auth::new(limits.clone()));
+    app.with(BodyLimit::new(limits.max_body_size()));
+    app.at("/command").post(command);
+    app.at("/items").get(list_items).post(add_item);
+    app.at("/items/:index").get(get_item).delete(delete_item);
+    app.at("/items/:index/raw").get(get_item_raw);
+    app.at("/items/:index/stats").get(get_item_stats);
+    app.at("/insert").post(insert);
+    app.at("/tags").get(tags);
+    app.at("/pins/:pin/set").post(set_pin);
+    app.at("/x-callback/list").get(callback_list);
+    app.at("/x-callback/get").get(callback_get);
+    app.at("/x-callback/set").get(callback_set);
+    app.at("/events").get(tide::sse::endpoint(events));
+    app.at("/version").get(version);
+    app.at("/openapi.json").get(openapi);
+    app.at("/docs").get(docs);
+    #[cfg(feature = "relay-server")]
+    app.at("/relay").nest(crate::relay::server::app());
+    app.listen(listen_on).await?;
+    Ok(())
+}
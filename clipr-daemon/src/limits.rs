@@ -0,0 +1,164 @@
+use async_std::io::ReadExt;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tide::{Body, Middleware, Next, Request, StatusCode};
+
+const WINDOW: Duration = Duration::from_secs(1);
+// forget idle clients once there are that many
+const MAX_CLIENTS: usize = 1024;
+
+// Rejects bodies larger than limit (413), chunked ones included.
+pub struct BodyLimit {
+    limit: usize,
+}
+
+impl BodyLimit {
+    pub fn new(limit: usize) -> Self {
+        Self { limit }
+    }
+}
+
+#[tide::utils::async_trait]
+impl<State: Clone + Send + Sync + 'static> Middleware<State> for BodyLimit {
+    async fn handle(&self, mut req: Request<State>, next: Next<'_, State>) -> tide::Result {
+        if req.len().is_some_and(|len| len > self.limit) {
+            return Err(too_large(self.limit));
+        }
+        if req.len().is_none_or(|len| len > 0) {
+            let body = req.take_body();
+            let mime = body.mime().clone();
+            let mut buffer = Vec::new();
+            body.take(self.limit as u64 + 1)
+                .read_to_end(&mut buffer)
+                .await?;
+            if buffer.len() > self.limit {
+                return Err(too_large(self.limit));
+            }
+            let mut body = Body::from(buffer);
+            body.set_mime(mime);
+            req.set_body(body);
+        }
+        Ok(next.run(req).await)
+    }
+}
+
+fn too_large(limit: usize) -> tide::Error {
+    tide::Error::from_str(
+        StatusCode::PayloadTooLarge,
+        format!("request body is larger than {limit} bytes"),
+    )
+}
+
+// Fixed window limit of requests per second per client address (429).
+pub struct RateLimit {
+    limit: u32,
+    windows: Mutex<HashMap<String, (Instant, u32)>>,
+}
+
+impl RateLimit {
+    pub fn new(limit: u32) -> Self {
+        Self {
+            limit,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // false once client is over the limit, 0 allows everything
+    pub fn allows(&self, client: String) -> bool {
+        self.limit == 0 || self.hit(client)
+    }
+
+    fn hit(&self, client: String) -> bool {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        if windows.len() > MAX_CLIENTS {
+            windows.retain(|_, (started_at, _)| now.duration_since(*started_at) < WINDOW);
+        }
+        let (started_at, count) = windows.entry(client).or_insert((now, 0));
+        if now.duration_since(*started_at) >= WINDOW {
+            *started_at = now;
+            *count = 0;
+        }
+        *count += 1;
+        *count <= self.limit
+    }
+}
+
+#[tide::utils::async_trait]
+impl<State: Clone + Send + Sync + 'static> Middleware<State> for RateLimit {
+    async fn handle(&self, req: Request<State>, next: Next<'_, State>) -> tide::Result {
+        // port differs between connections of the same client
+        let client = req
+            .peer_addr()
+            .map(|addr| addr.rsplit_once(':').map_or(addr, |(host, _)| host))
+            .unwrap_or_default()
+            .to_string();
+        if !self.allows(client) {
+            return Err(tide::Error::from_str(
+                StatusCode::TooManyRequests,
+                format!("more than {} requests per second", self.limit),
+            ));
+        }
+        Ok(next.run(req).await)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use async_std::task;
+
+    #[test]
+    fn test_rate_limit_window() {
+        let limit = RateLimit::new(2);
+        assert!(limit.hit(String::from("a")));
+        assert!(limit.hit(String::from("a")));
+        assert!(!limit.hit(String::from("a")));
+        // other clients have windows of their own
+        assert!(limit.hit(String::from("b")));
+
+        // window is over, counting starts again
+        let started_at = Instant::now().checked_sub(WINDOW).unwrap();
+        limit.windows.lock().unwrap().get_mut("a").unwrap().0 = started_at;
+        assert!(limit.hit(String::from("a")));
+        assert!(limit.hit(String::from("a")));
+        assert!(!limit.hit(String::from("a")));
+    }
+
+    #[test]
+    fn test_rate_limit_prune() {
+        let limit = RateLimit::new(1);
+        let started_at = Instant::now().checked_sub(WINDOW).unwrap();
+        limit
+            .windows
+            .lock()
+            .unwrap()
+            .extend((0..=MAX_CLIENTS).map(|i| (i.to_string(), (started_at, 1))));
+        limit.hit(String::from("active"));
+        limit.hit(String::from("active"));
+        // idle clients are gone, active one keeps its count
+        let windows = limit.windows.lock().unwrap();
+        assert_eq!(windows.len(), 1);
+        assert_eq!(windows["active"].1, 2);
+    }
+
+    #[test]
+    fn test_body_limit_chunked() {
+        let mut app = tide::new();
+        app.with(BodyLimit::new(4));
+        app.at("/")
+            .post(|mut req: Request<()>| async move { req.body_string().await });
+        let post = |body: &'static str| {
+            let mut req = tide::http::Request::post("http://localhost/");
+            // no length, body is chunked
+            req.set_body(Body::from_reader(async_std::io::Cursor::new(body), None));
+            task::block_on(app.respond::<_, tide::http::Response>(req)).unwrap()
+        };
+
+        let mut res = post("four");
+        assert_eq!(res.status(), StatusCode::Ok);
+        assert_eq!(task::block_on(res.body_string()).unwrap(), "four");
+        assert_eq!(post("fives").status(), StatusCode::PayloadTooLarge);
+    }
+}
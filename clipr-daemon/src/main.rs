@@ -1,102 +1,116 @@
 use anyhow::Result;
-use async_std::channel::{bounded, Receiver, Sender};
-use async_std::fs::File;
-use async_std::prelude::*;
+use async_std::channel::{bounded, Sender};
 use async_std::task;
 use clap::Parser;
-use cocoa::appkit::{NSPasteboard, NSPasteboardTypeString};
-use cocoa::base::nil;
-use cocoa::foundation::{NSInteger, NSString};
+use clipr_core::clipboard::Clipboard;
 use rustyline::Editor;
-use std::fs::File as SyncFile;
-use std::io::prelude::*;
 use std::sync::Arc;
-use std::time::Duration;
-use tide::prelude::*;
-use tide::Body;
+use std::time::{Duration, SystemTime};
 
-static USAGE: &str = include_str!("usage.txt");
+mod auth;
+mod daemonize;
+mod discovery;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod http;
+mod limits;
+mod mirror;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod osc52;
+mod pasteboard;
+mod plugins;
+mod relay;
+mod repl;
+#[cfg(feature = "scripting")]
+mod scripting;
+mod shutdown;
+mod text;
 
-unsafe fn get_change_count() -> NSInteger {
-    NSPasteboard::generalPasteboard(nil).changeCount()
-}
-
-unsafe fn get_current_entry() -> Option<String> {
-    match NSPasteboard::generalPasteboard(nil).stringForType(NSPasteboardTypeString) {
-        nil => None,
-        value => {
-            let bytes = value.UTF8String() as *const u8;
-            let length = value.len();
-            let string = std::str::from_utf8(std::slice::from_raw_parts(bytes, length)).unwrap();
-            Some(String::from(string))
-        }
-    }
-}
-
-unsafe fn set_current_entry(s: String) {
-    let pb = NSPasteboard::generalPasteboard(nil);
-    pb.clearContents();
+use pasteboard::{
+    get_change_count, get_current_entry, get_current_files, get_current_html, get_frontmost_app,
+    get_pasteboard,
+};
 
-    let value = NSString::alloc(nil).init_str(&s);
-    pb.setString_forType(value, NSPasteboardTypeString);
-}
-
-async fn clipboard_sync(sender: Sender<clipr_common::Request>) {
-    let mut last_hash: u64 = 0;
+async fn clipboard_sync(
+    state: Arc<clipr_common::State>,
+    pasteboard: String,
+    sender: Sender<clipr_common::Request>,
+    relay_outbox: Option<Sender<String>>,
+) {
+    let pb = unsafe { get_pasteboard(&pasteboard) };
     let mut last_change_count: i64 = 0;
+    // restarted daemon doesn't recapture what it already saw
+    let mut last_hash: u64 = {
+        let change_count = unsafe { get_change_count(pb) };
+        let entries = state.entries.read().unwrap();
+        entries
+            .get_item(0)
+            .filter(|item| item.change_count == Some(change_count))
+            .map_or(0, |item| item.hash)
+    };
+    let html_to_markdown = state.config.html_to_markdown == Some(true);
     loop {
         task::sleep(Duration::from_millis(500)).await;
-        let change_count = unsafe { get_change_count() };
+        let change_count = unsafe { get_change_count(pb) };
         if last_change_count == change_count {
             continue;
         } else {
             last_change_count = change_count;
         }
-        match unsafe { get_current_entry() } {
+        let current = match unsafe { get_current_files(pb) } {
+            Some(paths) => Some((
+                clipr_common::files_value(&paths),
+                clipr_common::ItemKind::Files(paths),
+            )),
+            None => html_to_markdown
+                .then(|| unsafe { get_current_html(pb) })
+                .flatten()
+                .map(|html| clipr_common::transform::html_to_markdown(&html))
+                .filter(|val| !val.is_empty())
+                .or_else(|| unsafe { get_current_entry(pb) })
+                .map(|val| (val, clipr_common::ItemKind::Text)),
+        };
+        match current {
             None => continue,
-            Some(val) => {
+            Some((val, kind)) => {
                 let hash = clipr_common::calculate_hash(&val);
                 if last_hash == hash {
                     continue;
                 }
 
                 last_hash = hash;
-                sender.send(clipr_common::Request::Sync(val)).await.unwrap();
-            }
-        }
-    }
-}
-
-async fn repl_loop(sender: Sender<clipr_common::Request>) {
-    let mut rl = Editor::<()>::new().unwrap();
-    loop {
-        let readline = rl.readline(":> ");
-        match readline {
-            Ok(line) => {
-                if line.is_empty() {
+                // `set` of history item, recapture would only touch it again
+                let name = (pasteboard != pasteboard::GENERAL).then_some(pasteboard.as_str());
+                if state.take_own_write(name, hash)
+                    && state.entries.read().unwrap().hashes.contains(&hash)
+                {
                     continue;
                 }
-
-                rl.add_history_entry(line.as_str());
-
-                let mut cmd_line = shellwords::split(line.as_str()).unwrap();
-                let bin_name = std::env::args().next().unwrap();
-                cmd_line.insert(0, bin_name);
-
-                let cmd = match clipr_common::Args::try_parse_from(cmd_line) {
-                    Ok(args) => args.command.unwrap(),
-                    Err(_) => clipr_common::Command::Help,
-                };
-
-                match clipr_common::Request::send_cmd(&sender, cmd).await {
-                    Some(clipr_common::Response::Stop) => return,
-                    Some(clipr_common::Response::Payload(val)) => {
-                        println!("{}", String::from(&val))
+                let source_app = unsafe { get_frontmost_app() };
+                let capture = state
+                    .config
+                    .app_rule(source_app.as_ref())
+                    .is_none_or(|rule| rule.capture());
+                // paths are meaningless on other machines, incognito items stay local
+                let relay = capture
+                    && kind == clipr_common::ItemKind::Text
+                    && !state.is_incognito(SystemTime::now());
+                if let Some(outbox) = relay_outbox.as_ref().filter(|_| relay) {
+                    if outbox.try_send(val.clone()).is_err() {
+                        eprintln!("relay outbox is full, value won't be pushed");
                     }
-                    _ => continue,
                 }
+                sender
+                    .send(clipr_common::Request::Sync(
+                        val,
+                        kind,
+                        source_app,
+                        Some(change_count),
+                    ))
+                    .await
+                    .unwrap();
             }
-            Err(_) => sender.send(clipr_common::Request::Quit).await.unwrap(),
         }
     }
 }
@@ -114,245 +128,142 @@ async fn empty_fg_loop(sender: Sender<clipr_common::Request>) {
     }
 }
 
-async fn http_server(listen_on: String, sender: Sender<clipr_common::Request>) -> Result<()> {
-    let mut app = tide::with_state(sender);
-    app.at("/command").post(
-        |mut req: tide::Request<Sender<clipr_common::Request>>| async move {
-            // TODO: handle invalid command properly
-            let cmd: clipr_common::Command = req.body_json().await?;
-
-            let sender = req.state();
-
-            match clipr_common::Request::send_cmd(sender, cmd).await {
-                Some(clipr_common::Response::Payload(val)) => Body::from_json(&val),
-                _ => Body::from_json(&json!({})),
-            }
-        },
-    );
-    app.listen(listen_on).await?;
+// Newest item is written like `set` does, so it isn't captured again.
+fn restore_clipboard(state: &clipr_common::State) -> Result<()> {
+    if !unsafe { pasteboard::is_empty(get_pasteboard(pasteboard::GENERAL)) } {
+        return Ok(());
+    }
+    let entries = state.entries.read().unwrap();
+    let Some(item) = entries.get_item(0) else {
+        return Ok(());
+    };
+    let item = clipr_core::keychain::resolve(state, item)?;
+    pasteboard::Pasteboards.set(None, &item)?;
+    state.record_write(None, &item.value);
     Ok(())
 }
 
-async fn event_loop(state: Arc<clipr_common::State>, receiver: Receiver<clipr_common::Request>) {
-    let s = state.clone();
-    loop {
-        if let Ok(msg) = receiver.recv().await {
-            match msg {
-                clipr_common::Request::Quit => return,
-                clipr_common::Request::Sync(value) => {
-                    let mut entries = s.entries.lock().unwrap();
-                    entries.insert(value)
-                }
-                clipr_common::Request::Command(cmd, sender) => {
-                    let payload = handle_call(s.clone(), cmd).await.unwrap();
-                    match payload {
-                        clipr_common::Payload::Stop => return,
-                        _ => {
-                            sender
-                                .send(clipr_common::Response::Payload(payload))
-                                .await
-                                .unwrap();
-                            continue;
-                        }
-                    }
-                }
-            };
+// Pasteboard polling, values copied to general one are pushed to relay
+// peers too.
+async fn capture(handle: clipr_core::Handle) {
+    if handle.state.config.restore_clipboard == Some(true) {
+        if let Err(err) = restore_clipboard(&handle.state) {
+            eprintln!("restoring clipboard failed: {err:#}");
         }
     }
+    let relay_outbox = handle.state.config.relay.clone().map(|config| {
+        let (outbox, inbox) = bounded::<String>(16);
+        task::spawn(relay::relay_sync(config, handle.sender.clone(), inbox));
+        outbox
+    });
+    for name in handle.state.config.pasteboards.iter().flatten() {
+        task::spawn(clipboard_sync(
+            handle.state.clone(),
+            name.clone(),
+            handle.sender.clone(),
+            None,
+        ));
+    }
+    clipboard_sync(
+        handle.state,
+        pasteboard::GENERAL.to_string(),
+        handle.sender,
+        relay_outbox,
+    )
+    .await;
 }
 
-async fn save_db(state: Arc<clipr_common::State>) -> Result<()> {
-    let db_path = state.config.db.as_ref().unwrap();
-    let mut file = File::create(db_path).await?;
-    let data = serde_json::to_string_pretty(&state.entries)?;
-    file.write_all(data.as_bytes()).await?;
-    Ok(())
-}
-
-fn save_db_sync(state: Arc<clipr_common::State>) -> Result<()> {
-    let db_path = state.config.db.as_ref().unwrap();
-    let mut file = SyncFile::create(db_path)?;
-    let data = serde_json::to_string_pretty(&state.entries)?;
-    file.write_all(data.as_bytes())?;
-    Ok(())
-}
+fn main() -> Result<()> {
+    env_logger::init();
+    let args = clipr_common::Args::parse();
+    let config = clipr_common::Config::load_from_args(&args)?;
+    let pid_file = config.pid_path();
+    if args.replay.is_none() {
+        shutdown::check_running(&pid_file)?;
+    }
+    if args.daemonize {
+        daemonize::daemonize(&config.log_path())?;
+    }
+    let host = config.host.clone().unwrap();
+    let discovery = config.discovery.unwrap_or(false);
+    #[cfg(feature = "grpc")]
+    let grpc_port = config.grpc_port;
+    let osc52_port = config.osc52_port;
+    let text_port = config.text_port;
+    let mirror = config.mirror.clone();
+    #[cfg(feature = "mqtt")]
+    let mqtt = config.mqtt.clone();
+    let interactive = config.interactive.unwrap_or(false) && !args.daemonize;
+    let mut display = config.display();
+    display.color = args.color.enabled();
+    let plugins = plugins::registry(&config);
 
-async fn load_db(state: Arc<clipr_common::State>) -> Result<()> {
-    let db_path = state.config.db.as_ref().unwrap();
-    let mut file = File::open(db_path).await?;
-    let mut buffer = String::new();
-    file.read_to_string(&mut buffer).await?;
-    let data: clipr_common::Entries = serde_json::from_str(buffer.as_str())?;
-    let mut entries = state.entries.lock().unwrap();
-    *entries = data;
-    drop(entries);
-    Ok(())
-}
+    if let Some(path) = &args.replay {
+        let builder = clipr_core::Daemon::builder(config).plugins(plugins);
+        let entries = task::block_on(clipr_core::replay::replay(builder, path))?;
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
 
-// TODO: use state.handle_call + Mutex around State
-async fn handle_call(
-    state: Arc<clipr_common::State>,
-    cmd: clipr_common::Command,
-) -> Result<clipr_common::Payload> {
-    Ok(match cmd {
-        clipr_common::Command::List {
-            from_index,
-            to_index,
-            preview_length,
-        } => {
-            let entries = state.entries.lock().unwrap();
-            let items = entries.select_by_range(from_index, to_index);
-            clipr_common::Payload::List {
-                value: items,
-                preview_length,
-            }
-        }
-        clipr_common::Command::Count => {
-            let entries = state.entries.lock().unwrap();
-            clipr_common::Payload::Value {
-                value: Some(entries.len().to_string()),
-            }
-        }
-        clipr_common::Command::Save => {
-            save_db(state.clone()).await.unwrap();
-            clipr_common::Payload::Ok
-        }
-        clipr_common::Command::Load => {
-            load_db(state.clone()).await.unwrap();
-            clipr_common::Payload::Ok
-        }
-        clipr_common::Command::Get { index } => {
-            let mut entries = state.entries.lock().unwrap();
-            match entries.get_value(index) {
-                Some(val) => clipr_common::Payload::Value { value: Some(val) },
-                None => clipr_common::Payload::Message {
-                    value: format!("item at {index:?} not found"),
-                },
-            }
-        }
-        clipr_common::Command::Add { value } => {
-            unsafe { set_current_entry(value.join(" ")) };
-            clipr_common::Payload::Ok
-        }
-        clipr_common::Command::Insert { filename } => {
-            let mut file = File::open(filename).await?;
-            let mut buffer = String::new();
-            file.read_to_string(&mut buffer).await?;
-            unsafe { set_current_entry(buffer) };
-            clipr_common::Payload::Ok
-        }
-        clipr_common::Command::Set { index } => {
-            let mut entries = state.entries.lock().unwrap();
-            if let Some(value) = entries.get_value(index) {
-                unsafe { set_current_entry(value) };
-                clipr_common::Payload::Ok
-            } else {
-                clipr_common::Payload::Message {
-                    value: format!("item at {index:?} not found"),
-                }
-            }
-        }
-        clipr_common::Command::Del {
-            from_index,
-            to_index,
-        } => {
-            let mut entries = state.entries.lock().unwrap();
-            entries.delete(from_index, to_index);
-            clipr_common::Payload::Ok
-        }
-        clipr_common::Command::Tag { index, tag } => {
-            let mut entries = state.entries.lock().unwrap();
-            if entries.tag(index, tag) {
-                clipr_common::Payload::Ok
-            } else {
-                clipr_common::Payload::Message {
-                    value: format!("item at {index:?} not found"),
-                }
+    shutdown::write_pid(&pid_file)?;
+    let mut builder = clipr_core::Daemon::builder(config)
+        .clipboard(pasteboard::Pasteboards)
+        .plugins(plugins)
+        .transport(|handle| shutdown::terminate_loop(handle.sender))
+        .transport(|handle| async move {
+            if let Err(err) = http::http_server(handle.state, handle.sender).await {
+                eprintln!("http server failed: {err}");
             }
-        }
-        clipr_common::Command::Untag { index, tag } => {
-            let mut entries = state.entries.lock().unwrap();
-            if entries.untag(index, tag) {
-                clipr_common::Payload::Ok
-            } else {
-                clipr_common::Payload::Message {
-                    value: format!("item at {index:?} not found"),
-                }
-            }
-        }
-        clipr_common::Command::Pin { index, pin } => {
-            let mut entries = state.entries.lock().unwrap();
-            entries.pin(index, pin.to_uppercase().next().unwrap());
-            clipr_common::Payload::Ok
-        }
-        clipr_common::Command::Unpin { index } => {
-            let mut entries = state.entries.lock().unwrap();
-            entries.unpin(index);
-            clipr_common::Payload::Ok
-        }
-        clipr_common::Command::Select {
-            set,
-            pin,
-            tag,
-            value,
-        } => {
-            let entries = state.entries.lock().unwrap();
-
-            if pin.is_none() && tag.is_empty() && value.is_none() {
-                return Ok(clipr_common::Payload::Message {
-                    value: String::from("invalid args"),
-                });
-            };
-
-            let items: Vec<(usize, clipr_common::Item)> = entries.select(
-                pin.map(|s| s.to_uppercase().chars().next().unwrap()),
-                tag,
-                value,
-            );
-
-            if set && !items.is_empty() {
-                let (_, item) = &items[0];
-                unsafe { set_current_entry(item.value.clone()) };
-                clipr_common::Payload::Ok
-            } else {
-                clipr_common::Payload::List {
-                    value: items,
-                    preview_length: None,
-                }
+        });
+    // mirror leaves pasteboard to primary daemon
+    builder = match mirror {
+        Some(config) => builder.transport(|handle| mirror::mirror_loop(config, handle.sender)),
+        None => builder.transport(capture),
+    };
+    if let Some(path) = args.record.clone() {
+        builder = builder.record(path);
+    }
+    if discovery {
+        builder = builder.transport(|handle| discovery::discovery(handle.state));
+    }
+    #[cfg(feature = "grpc")]
+    if let Some(port) = grpc_port {
+        let listen_on = format!("{host}:{port}");
+        builder =
+            builder.transport(move |handle| async move { grpc::spawn(listen_on, handle.sender) });
+    }
+    #[cfg(feature = "mqtt")]
+    if let Some(config) = mqtt {
+        builder = builder.transport(|handle| mqtt::mqtt_loop(config, handle.sender));
+    }
+    if let Some(port) = osc52_port {
+        let listen_on = format!("{host}:{port}");
+        builder = builder.transport(move |handle| async move {
+            if let Err(err) = osc52::osc52_server(listen_on, handle.sender).await {
+                eprintln!("osc52 server failed: {err}");
             }
-        }
-        clipr_common::Command::Tags => {
-            let entries = state.entries.lock().unwrap();
-            let tags = entries.get_tags();
-            let mut ts = tags.into_iter().collect::<Vec<String>>();
-            ts.sort();
-            clipr_common::Payload::Value {
-                value: Some(ts.join(":")),
+        });
+    }
+    if let Some(port) = text_port {
+        let listen_on = format!("{host}:{port}");
+        // replies are parsed by scripts, no colors
+        let mut display = display.clone();
+        display.color = false;
+        builder = builder.transport(move |handle| async move {
+            let http = handle.state.config.http.clone().unwrap_or_default();
+            if let Err(err) = text::text_server(listen_on, handle.sender, display, http).await {
+                eprintln!("text server failed: {err}");
             }
-        }
-
-        clipr_common::Command::Help => clipr_common::Payload::Message {
-            value: USAGE.to_string(),
-        },
-        clipr_common::Command::Quit => clipr_common::Payload::Stop,
-    })
-}
-
-fn main() -> Result<()> {
-    env_logger::init();
-    let args = clipr_common::Args::parse();
-    let config = clipr_common::Config::load_from_args(&args)?;
-    let state = Arc::new(clipr_common::State::new(config));
-    let (sender, receiver) = bounded::<clipr_common::Request>(1);
-    task::spawn(clipboard_sync(sender.clone()));
-    task::spawn(http_server(state.config.listen_on(), sender.clone()));
-    if !state.config.interactive.unwrap_or(false) {
-        task::spawn(empty_fg_loop(sender));
-    } else {
-        task::spawn(repl_loop(sender));
+        });
     }
-    task::block_on(event_loop(state.clone(), receiver));
-    // sync state at exit
-    save_db_sync(state)?;
-    Ok(())
+    // detached daemon's stdin is /dev/null, it runs until `quit`
+    if interactive {
+        builder =
+            builder.transport(move |handle| repl::repl_loop(handle.state, handle.sender, display));
+    } else if !args.daemonize {
+        builder = builder.transport(|handle| empty_fg_loop(handle.sender));
+    }
+    let result = task::block_on(builder.build().run());
+    let _ = std::fs::remove_file(&pid_file);
+    result
 }
@@ -0,0 +1,110 @@
+use anyhow::Result;
+use async_std::channel::Sender;
+use async_std::future;
+use async_std::prelude::*;
+use async_std::task;
+use clipr_client::Client;
+use clipr_common::{IndexOrId, Item, ItemSummary, MirrorConfig, MirrorUpdate, Request};
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+// values of keychain items are real secrets, they stay on primary
+fn is_secret(tags: &Option<HashSet<String>>) -> bool {
+    tags.as_ref()
+        .is_some_and(|tags| tags.contains(clipr_common::SECRET_TAG))
+}
+
+fn item(summary: ItemSummary, value: String) -> Item {
+    Item {
+        id: summary.id,
+        value,
+        access_counter: summary.access_counter,
+        accessed_at: summary.accessed_at,
+        tags: summary.tags,
+        pin: summary.pin,
+        source_app: summary.source_app,
+        expires_at: summary.expires_at,
+        note: summary.note,
+        favorite: summary.favorite,
+        kind: summary.kind,
+        title: summary.title,
+        ephemeral: summary.ephemeral,
+        set_count: summary.set_count,
+        change_count: summary.change_count,
+        hash: summary.hash,
+        ..Item::new(String::new())
+    }
+}
+
+// whole history: summaries, then values in one get-many call
+async fn snapshot(client: &Client) -> Result<Vec<Item>> {
+    let summaries: Vec<ItemSummary> = client
+        .list(None, None, None)
+        .await?
+        .into_iter()
+        .map(|(_, summary)| summary)
+        .filter(|summary| !is_secret(&summary.tags))
+        .collect();
+    let targets = summaries
+        .iter()
+        .map(|summary| IndexOrId::Id(summary.id.clone()))
+        .collect();
+    let values = client.get_many(targets).await?;
+    Ok(summaries
+        .into_iter()
+        .zip(values)
+        // deleted in between
+        .filter_map(|(summary, value)| Some(item(summary, value?)))
+        .collect())
+}
+
+async fn follow(client: &Client, config: &MirrorConfig, sender: &Sender<Request>) -> Result<()> {
+    // subscribed before snapshot, so items captured meanwhile aren't missed
+    let mut events = client.subscribe().await?;
+    loop {
+        let items = snapshot(client).await?;
+        sender
+            .send(Request::Mirror(MirrorUpdate::Snapshot(items)))
+            .await?;
+        let refresh_at = Instant::now() + config.interval();
+        loop {
+            let wait = refresh_at.saturating_duration_since(Instant::now());
+            let next = future::timeout(wait, events.next()).await;
+            let item = match next {
+                Ok(Some(item)) => item?,
+                Ok(None) => return Ok(()),
+                Err(_) => break,
+            };
+            if !item.keychain && !is_secret(&item.tags) {
+                sender
+                    .send(Request::Mirror(MirrorUpdate::Item(Box::new(item))))
+                    .await?;
+            }
+        }
+    }
+}
+
+// Keeps local history in step with primary daemon, reconnects when it goes
+// away (e.g. laptop leaves home network).
+pub async fn mirror_loop(config: MirrorConfig, sender: Sender<Request>) {
+    let client = Client::new(&config.primary);
+    let client = match &config.token {
+        Some(token) => match client.with_token(token) {
+            Ok(client) => client,
+            Err(err) => return eprintln!("mirror disabled: {err:#}"),
+        },
+        None => client,
+    };
+    loop {
+        match follow(&client, &config, &sender).await {
+            Ok(()) => eprintln!("mirror of {} disconnected", config.primary),
+            Err(err) => eprintln!("mirror of {} failed: {err:#}", config.primary),
+        }
+        if sender.is_closed() {
+            return;
+        }
+        task::sleep(RETRY_INTERVAL).await;
+    }
+}
@@ -0,0 +1,177 @@
+use anyhow::{bail, Result};
+use async_std::channel::{bounded, Sender};
+use async_std::future;
+use async_std::net::{Shutdown, TcpStream};
+use async_std::prelude::*;
+use async_std::task;
+use clipr_common::mqtt::{self, MqttConfig, Packet};
+use clipr_common::{Command, ErrorBody, Item, Request, Response};
+use clipr_core::keychain;
+use std::time::Duration;
+
+const RETRY_INTERVAL: Duration = Duration::from_secs(10);
+const SUBSCRIPTION_ID: u16 = 1;
+
+enum Event {
+    Item(Box<Item>),
+    Packet(Packet),
+    // broker connection is gone
+    Closed(anyhow::Error),
+}
+
+// Subscribers get new items before keychain sync, so secret values are
+// still in place, and incognito ones are never meant to leave the machine.
+fn is_published(item: &Item) -> bool {
+    !item.keychain && !item.ephemeral && !keychain::is_secret(item)
+}
+
+async fn read_packet(stream: &mut TcpStream) -> Result<Packet> {
+    let mut header = [0u8; 1];
+    stream.read_exact(&mut header).await?;
+    let mut len_bytes = vec![];
+    let len = loop {
+        let mut byte = [0u8; 1];
+        stream.read_exact(&mut byte).await?;
+        len_bytes.push(byte[0]);
+        if let Some((len, _)) = mqtt::remaining_length(&len_bytes)? {
+            break len;
+        }
+    };
+    let mut body = vec![0u8; len];
+    stream.read_exact(&mut body).await?;
+    mqtt::decode(header[0], &body)
+}
+
+// JSON Command in, JSON Payload (or ErrorBody) out
+async fn run_command(sender: &Sender<Request>, payload: &[u8]) -> Vec<u8> {
+    let reply = match serde_json::from_slice::<Command>(payload) {
+        Ok(cmd) => match Request::send_cmd(sender, cmd).await {
+            Some(Response::Payload(payload)) => serde_json::to_vec(&payload),
+            Some(Response::Error(detail)) => serde_json::to_vec(&ErrorBody {
+                error: String::from("internal"),
+                detail,
+            }),
+            _ => serde_json::to_vec(&ErrorBody {
+                error: String::from("unavailable"),
+                detail: String::from("daemon is shutting down"),
+            }),
+        },
+        Err(err) => serde_json::to_vec(&ErrorBody {
+            error: String::from("invalid-request"),
+            detail: err.to_string(),
+        }),
+    };
+    reply.unwrap_or_default()
+}
+
+async fn session(config: &MqttConfig, sender: &Sender<Request>) -> Result<()> {
+    let mut stream = TcpStream::connect(&config.broker).await?;
+    let client_id = config
+        .client_id
+        .clone()
+        .unwrap_or_else(|| format!("clipr-{}", gethostname::gethostname().to_string_lossy()));
+    stream.write_all(&mqtt::connect(config, &client_id)).await?;
+    match read_packet(&mut stream).await? {
+        Packet::ConnAck(0) => {}
+        Packet::ConnAck(code) => bail!("connection refused by broker (code {code})"),
+        other => bail!("unexpected packet {other:?} instead of CONNACK"),
+    }
+    stream
+        .write_all(&mqtt::subscribe(SUBSCRIPTION_ID, config.command_topic()))
+        .await?;
+
+    let (events_tx, events) = bounded::<Event>(16);
+    let (items_tx, items) = bounded::<Response>(16);
+    sender.send(Request::Subscribe(items_tx)).await?;
+    let items_events = events_tx.clone();
+    task::spawn(async move {
+        while let Ok(Response::NewItem(item)) = items.recv().await {
+            if items_events.send(Event::Item(item)).await.is_err() {
+                return;
+            }
+        }
+    });
+    let mut reader = stream.clone();
+    task::spawn(async move {
+        loop {
+            let (event, closed) = match read_packet(&mut reader).await {
+                Ok(packet) => (Event::Packet(packet), false),
+                Err(err) => (Event::Closed(err), true),
+            };
+            if events_tx.send(event).await.is_err() || closed {
+                return;
+            }
+        }
+    });
+
+    // pings keep connection alive when nothing is copied
+    let ping_interval = Duration::from_secs(u64::from(config.keep_alive()).max(2) / 2);
+    let result = loop {
+        let event = match future::timeout(ping_interval, events.recv()).await {
+            Ok(Ok(event)) => event,
+            Ok(Err(_)) => break Ok(()),
+            Err(_) => {
+                if let Err(err) = stream.write_all(&mqtt::ping()).await {
+                    break Err(err.into());
+                }
+                continue;
+            }
+        };
+        let written = match event {
+            Event::Item(item) if !is_published(&item) => continue,
+            Event::Item(item) => {
+                let payload = serde_json::to_vec(&item).unwrap_or_default();
+                stream
+                    .write_all(&mqtt::publish(config.topic(), &payload))
+                    .await
+            }
+            Event::Packet(Packet::Publish { topic, payload })
+                if topic == config.command_topic() =>
+            {
+                let reply = run_command(sender, &payload).await;
+                stream
+                    .write_all(&mqtt::publish(&config.reply_topic(), &reply))
+                    .await
+            }
+            Event::Packet(_) => continue,
+            Event::Closed(err) => break Err(err),
+        };
+        if let Err(err) = written {
+            break Err(err.into());
+        }
+    };
+    // stops reader task
+    let _ = stream.shutdown(Shutdown::Both);
+    result
+}
+
+// Bridges daemon to MQTT broker (e.g. Home Assistant's), reconnects when
+// broker goes away.
+pub async fn mqtt_loop(config: MqttConfig, sender: Sender<Request>) {
+    loop {
+        match session(&config, &sender).await {
+            Ok(()) => eprintln!("mqtt broker {} disconnected", config.broker),
+            Err(err) => eprintln!("mqtt broker {} failed: {err:#}", config.broker),
+        }
+        if sender.is_closed() {
+            return;
+        }
+        task::sleep(RETRY_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_published() {
+        let mut item = Item::new(String::from("hunter2"));
+        assert!(is_published(&item));
+        item.ephemeral = true;
+        assert!(!is_published(&item));
+        item.ephemeral = false;
+        item.tags = Some([String::from(clipr_common::SECRET_TAG)].into());
+        assert!(!is_published(&item));
+    }
+}
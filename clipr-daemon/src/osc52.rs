@@ -0,0 +1,43 @@
+use anyhow::Result;
+use async_std::channel::Sender;
+use async_std::net::{TcpListener, TcpStream};
+use async_std::prelude::*;
+use async_std::task;
+use clipr_common::osc52::Decoder;
+
+async fn handle(mut stream: TcpStream, sender: Sender<clipr_common::Request>) -> Result<()> {
+    let mut decoder = Decoder::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        for value in decoder.feed(&buf[..n]) {
+            sender
+                .send(clipr_common::Request::Sync(
+                    value,
+                    clipr_common::ItemKind::Text,
+                    None,
+                    None,
+                ))
+                .await?;
+        }
+    }
+}
+
+// Accepts raw terminal output (e.g. forwarded from remote host with `ssh -R`)
+// and captures OSC52 clipboard writes found in it.
+pub async fn osc52_server(listen_on: String, sender: Sender<clipr_common::Request>) -> Result<()> {
+    let listener = TcpListener::bind(listen_on).await?;
+    let mut incoming = listener.incoming();
+    while let Some(stream) = incoming.next().await {
+        let (stream, sender) = (stream?, sender.clone());
+        task::spawn(async move {
+            if let Err(err) = handle(stream, sender).await {
+                eprintln!("osc52 connection failed: {err}");
+            }
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,162 @@
+use anyhow::{bail, Result};
+use clipr_common::{Item, ItemKind, SourceApp};
+use clipr_core::clipboard::Clipboard;
+use cocoa::appkit::{
+    NSDragPboard, NSFilenamesPboardType, NSFindPboard, NSFontPboard, NSPasteboard,
+    NSPasteboardTypeHTML, NSPasteboardTypeString, NSRulerPboard,
+};
+use cocoa::base::{id, nil};
+use cocoa::foundation::{NSArray, NSData, NSInteger, NSString, NSUInteger};
+use objc::{class, msg_send, sel, sel_impl};
+use std::ffi::c_void;
+use std::path::PathBuf;
+
+pub const GENERAL: &str = "general";
+
+// NSPasteboard handle. Named pasteboards live as long as the process,
+// so it's fine to pass them between tasks.
+#[derive(Clone, Copy)]
+pub struct Pasteboard(id);
+
+unsafe impl Send for Pasteboard {}
+
+// "general", "find", "font", "ruler", "drag" or any custom pasteboard name
+pub unsafe fn get_pasteboard(name: &str) -> Pasteboard {
+    let pb = match name {
+        GENERAL => NSPasteboard::generalPasteboard(nil),
+        "find" => NSPasteboard::pasteboardWithName(nil, NSFindPboard),
+        "font" => NSPasteboard::pasteboardWithName(nil, NSFontPboard),
+        "ruler" => NSPasteboard::pasteboardWithName(nil, NSRulerPboard),
+        "drag" => NSPasteboard::pasteboardWithName(nil, NSDragPboard),
+        custom => NSPasteboard::pasteboardWithName(nil, NSString::alloc(nil).init_str(custom)),
+    };
+    Pasteboard(pb)
+}
+
+pub unsafe fn get_change_count(pb: Pasteboard) -> NSInteger {
+    pb.0.changeCount()
+}
+
+unsafe fn nsstring_to_string(value: id) -> Option<String> {
+    if value == nil {
+        return None;
+    }
+    let bytes = value.UTF8String() as *const u8;
+    let length = value.len();
+    let string = std::str::from_utf8(std::slice::from_raw_parts(bytes, length)).unwrap();
+    Some(String::from(string))
+}
+
+pub unsafe fn get_current_entry(pb: Pasteboard) -> Option<String> {
+    nsstring_to_string(pb.0.stringForType(NSPasteboardTypeString))
+}
+
+// set by browsers and rich text editors along with plain string
+pub unsafe fn get_current_html(pb: Pasteboard) -> Option<String> {
+    nsstring_to_string(pb.0.stringForType(NSPasteboardTypeHTML))
+}
+
+// Finder puts file names as plain string as well, so files should be checked first
+pub unsafe fn get_current_files(pb: Pasteboard) -> Option<Vec<PathBuf>> {
+    let filenames = pb.0.propertyListForType(NSFilenamesPboardType);
+    if filenames == nil {
+        return None;
+    }
+    let paths = (0..filenames.count())
+        .filter_map(|i| nsstring_to_string(filenames.objectAtIndex(i)))
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+    Some(paths).filter(|paths| !paths.is_empty())
+}
+
+// NSPasteboard doesn't know who wrote to it, so frontmost application
+// at the moment of capture is the best guess.
+pub unsafe fn get_frontmost_app() -> Option<SourceApp> {
+    let workspace: id = msg_send![class!(NSWorkspace), sharedWorkspace];
+    let app: id = msg_send![workspace, frontmostApplication];
+    if app == nil {
+        return None;
+    }
+    let name: id = msg_send![app, localizedName];
+    let bundle_id: id = msg_send![app, bundleIdentifier];
+    Some(SourceApp {
+        name: nsstring_to_string(name)?,
+        bundle_id: nsstring_to_string(bundle_id),
+    })
+}
+
+// nothing of any type on it
+pub unsafe fn is_empty(pb: Pasteboard) -> bool {
+    let types = pb.0.types();
+    types == nil || types.count() == 0
+}
+
+pub unsafe fn clear(pb: Pasteboard) {
+    pb.0.clearContents();
+}
+
+pub unsafe fn set_current_entry(pb: Pasteboard, s: String) {
+    pb.0.clearContents();
+
+    let value = NSString::alloc(nil).init_str(&s);
+    pb.0.setString_forType(value, NSPasteboardTypeString);
+}
+
+// file URLs, so Finder pastes files themselves
+pub unsafe fn set_current_files(pb: Pasteboard, paths: &[PathBuf]) {
+    pb.0.clearContents();
+
+    let urls = paths
+        .iter()
+        .map(|path| {
+            let path = NSString::alloc(nil).init_str(&path.to_string_lossy());
+            let url: id = msg_send![class!(NSURL), fileURLWithPath: path];
+            url
+        })
+        .collect::<Vec<_>>();
+    pb.0.writeObjects(NSArray::arrayWithObjects(nil, &urls));
+}
+
+// any format NSImage reads (png, jpeg, gif, tiff, ...), false if data isn't an image
+pub unsafe fn set_current_image(pb: Pasteboard, data: &[u8]) -> bool {
+    let data = NSData::dataWithBytes_length_(
+        nil,
+        data.as_ptr() as *const c_void,
+        data.len() as NSUInteger,
+    );
+    let image: id = msg_send![class!(NSImage), alloc];
+    let image: id = msg_send![image, initWithData: data];
+    if image == nil {
+        return false;
+    }
+    pb.0.clearContents();
+    pb.0.writeObjects(NSArray::arrayWithObject(nil, image)) != 0
+}
+
+// clipr_core clipboard on top of named pasteboards
+pub struct Pasteboards;
+
+impl Clipboard for Pasteboards {
+    // files are put back as file references, not as their paths
+    fn set(&self, pasteboard: Option<&str>, item: &Item) -> Result<()> {
+        unsafe {
+            let pb = get_pasteboard(pasteboard.unwrap_or(GENERAL));
+            match &item.kind {
+                ItemKind::Text => set_current_entry(pb, item.value.clone()),
+                ItemKind::Files(paths) => set_current_files(pb, paths),
+                ItemKind::Image { mime } => {
+                    let data = clipr_common::image_data(&item.value)?;
+                    if !set_current_image(pb, &data) {
+                        bail!("{mime} image can't be read");
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<()> {
+        unsafe { clear(get_pasteboard(GENERAL)) };
+        Ok(())
+    }
+}
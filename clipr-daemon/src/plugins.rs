@@ -0,0 +1,55 @@
+use clipr_common::plugin::Plugins;
+use clipr_common::Config;
+
+// Plugins are linked at compile time, each one behind its own `plugin-*` feature.
+#[allow(clippy::vec_init_then_push, unused_variables)]
+pub fn registry(config: &Config) -> Plugins {
+    #[allow(unused_mut)]
+    let mut plugins: Plugins = vec![];
+    #[cfg(feature = "plugin-wc")]
+    plugins.push(Box::new(wc::Wc));
+    #[cfg(feature = "scripting")]
+    if let Some(dir) = config.scripts.as_ref() {
+        match crate::scripting::Scripts::load(std::path::Path::new(dir)) {
+            Ok(scripts) => plugins.push(Box::new(scripts)),
+            Err(err) => eprintln!("scripts disabled: {err}"),
+        }
+    }
+    plugins
+}
+
+#[cfg(feature = "plugin-wc")]
+mod wc {
+    use anyhow::{anyhow, Result};
+    use clipr_common::plugin::CommandPlugin;
+    use clipr_common::Entries;
+    use serde_json::json;
+
+    // `plugin wc [index]` - lines, words and chars of item
+    pub struct Wc;
+
+    impl CommandPlugin for Wc {
+        fn name(&self) -> &str {
+            "wc"
+        }
+
+        fn help(&self) -> &str {
+            "wc [index] - count lines, words and chars of item"
+        }
+
+        fn call(&self, entries: &mut Entries, args: Vec<String>) -> Result<serde_json::Value> {
+            let index = match args.first() {
+                Some(index) => index.parse()?,
+                None => 0,
+            };
+            let item = entries
+                .get(index)
+                .ok_or_else(|| anyhow!("item at {index:?} not found"))?;
+            Ok(json!({
+                "lines": item.value.lines().count(),
+                "words": item.value.split_whitespace().count(),
+                "chars": item.value.chars().count(),
+            }))
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use async_std::channel::{Receiver, Sender};
+use async_std::future;
+use clipr_common::relay::{new_origin, Cipher, Envelope, Push, RelayConfig, DEFAULT_INTERVAL};
+use std::time::{Duration, Instant};
+
+async fn push(url: &str, cipher: &Cipher, origin: &str, value: &str) -> Result<(), surf::Error> {
+    let body = Push {
+        origin: origin.to_string(),
+        data: cipher.seal(value)?,
+    };
+    surf::post(url).body_json(&body)?.await?;
+    Ok(())
+}
+
+async fn pull(url: &str, since: u64) -> Result<Vec<Envelope>, surf::Error> {
+    let uri = format!("{url}?since={since}");
+    surf::get(uri).recv_json().await
+}
+
+// Pushes locally captured values as soon as they arrive and polls the relay
+// for values captured by other daemons on the same channel.
+pub async fn relay_sync(
+    config: RelayConfig,
+    sender: Sender<clipr_common::Request>,
+    outbox: Receiver<String>,
+) {
+    let cipher = match Cipher::from_hex(&config.key) {
+        Ok(cipher) => cipher,
+        Err(err) => {
+            eprintln!("relay disabled: {err}");
+            return;
+        }
+    };
+    let origin = new_origin();
+    let url = config.channel_url();
+    let interval = Duration::from_secs(config.interval.unwrap_or(DEFAULT_INTERVAL));
+    let mut since: u64 = 0;
+    let mut last_pull = Instant::now() - interval;
+
+    loop {
+        let wait = interval.saturating_sub(last_pull.elapsed());
+        match future::timeout(wait, outbox.recv()).await {
+            Ok(Ok(value)) => {
+                if let Err(err) = push(&url, &cipher, &origin, &value).await {
+                    eprintln!("relay push failed: {err}");
+                }
+                continue;
+            }
+            Ok(Err(_)) => return,
+            Err(_) => {}
+        }
+
+        last_pull = Instant::now();
+        let envelopes = match pull(&url, since).await {
+            Ok(envelopes) => envelopes,
+            Err(err) => {
+                eprintln!("relay pull failed: {err}");
+                continue;
+            }
+        };
+
+        // relay answers with envelopes after `since` only, lower seq means
+        // it was restarted and counts from 1 again
+        if envelopes.iter().any(|envelope| envelope.seq <= since) {
+            eprintln!("relay restarted, pulling from start");
+            since = 0;
+        }
+        for envelope in envelopes {
+            since = since.max(envelope.seq);
+            if envelope.origin == origin {
+                continue;
+            }
+            match cipher.open(&envelope.data) {
+                Ok(value) => sender
+                    .send(clipr_common::Request::Sync(
+                        value,
+                        clipr_common::ItemKind::Text,
+                        None,
+                        None,
+                    ))
+                    .await
+                    .unwrap(),
+                Err(err) => eprintln!("relay envelope {} skipped: {err}", envelope.seq),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "relay-server")]
+pub mod server {
+    use clipr_common::relay::{Envelope, Push};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use tide::prelude::*;
+    use tide::{Body, Request};
+
+    // envelopes kept per channel, older ones are dropped
+    const MAX_ENVELOPES: usize = 256;
+
+    #[derive(Clone, Default)]
+    pub struct Store(Arc<Mutex<HashMap<String, Vec<Envelope>>>>);
+
+    #[derive(Deserialize)]
+    struct Since {
+        since: Option<u64>,
+    }
+
+    // Dumb store-and-forward endpoint, payloads are opaque to it.
+    pub fn app() -> tide::Server<Store> {
+        let mut app = tide::with_state(Store::default());
+        app.at("/:channel")
+            .post(|mut req: Request<Store>| async move {
+                let push: Push = req.body_json().await?;
+                let channel = req.param("channel")?.to_string();
+                let mut channels = req.state().0.lock().unwrap();
+                let envelopes = channels.entry(channel).or_default();
+                let seq = envelopes.last().map_or(1, |e| e.seq + 1);
+                envelopes.push(Envelope {
+                    seq,
+                    origin: push.origin,
+                    data: push.data,
+                });
+                if envelopes.len() > MAX_ENVELOPES {
+                    envelopes.drain(..envelopes.len() - MAX_ENVELOPES);
+                }
+                Body::from_json(&json!({ "seq": seq }))
+            })
+            .get(|req: Request<Store>| async move {
+                let since = req.query::<Since>()?.since.unwrap_or(0);
+                let channels = req.state().0.lock().unwrap();
+                let envelopes: Vec<&Envelope> = channels
+                    .get(req.param("channel")?)
+                    .map(|es| {
+                        // cursor from before restart, client starts over
+                        let since = if es.last().is_some_and(|e| e.seq < since) {
+                            0
+                        } else {
+                            since
+                        };
+                        es.iter().filter(|e| e.seq > since).collect()
+                    })
+                    .unwrap_or_default();
+                Body::from_json(&envelopes)
+            });
+        app
+    }
+}
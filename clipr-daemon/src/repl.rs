@@ -0,0 +1,150 @@
+use async_std::channel::Sender;
+use clap::{CommandFactory, Parser};
+use clipr_common::output::Verbosity;
+use clipr_common::{DisplayConfig, State};
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Context, Editor, Helper};
+use std::sync::Arc;
+use std::time::Instant;
+
+// Completes subcommands and tags (after `--tag` or as `tag`/`untag` argument),
+// input with unbalanced quotes continues on the next line.
+struct ReplHelper {
+    state: Arc<State>,
+}
+
+impl ReplHelper {
+    fn candidates(&self, words: &[&str], word: &str) -> Vec<String> {
+        let names = match words {
+            [] => clipr_common::Args::command()
+                .get_subcommands()
+                .map(|cmd| cmd.get_name().to_string())
+                .collect(),
+            [.., "--tag"] | ["tag" | "untag", _] => {
+                let entries = self.state.entries.read().unwrap();
+                entries
+                    .get_tag_counts()
+                    .into_iter()
+                    .map(|(tag, _)| tag)
+                    .collect()
+            }
+            _ => vec![],
+        };
+        names
+            .into_iter()
+            .filter(|name| name.starts_with(word))
+            .collect()
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let words = line[..start].split_whitespace().collect::<Vec<&str>>();
+        let pairs = self
+            .candidates(&words, &line[start..])
+            .into_iter()
+            .map(|name| Pair {
+                display: name.clone(),
+                replacement: format!("{name} "),
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Validator for ReplHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        match shellwords::split(ctx.input()) {
+            Err(shellwords::MismatchedQuotes) => Ok(ValidationResult::Incomplete),
+            Ok(_) => Ok(ValidationResult::Valid(None)),
+        }
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+pub async fn repl_loop(
+    state: Arc<State>,
+    sender: Sender<clipr_common::Request>,
+    display: DisplayConfig,
+) {
+    let mut rl = Editor::<ReplHelper>::new().unwrap();
+    rl.set_helper(Some(ReplHelper { state }));
+    let history = clipr_common::paths::repl_history_path();
+    if let Some(path) = &history {
+        // there is no history on first run
+        let _ = rl.load_history(path);
+    }
+    loop {
+        let readline = rl.readline(":> ");
+        match readline {
+            Ok(line) => {
+                if line.is_empty() {
+                    continue;
+                }
+
+                // `unlock <passphrase>` isn't kept, not even in memory
+                if !clipr_common::lock::has_passphrase(&line) {
+                    rl.add_history_entry(line.as_str());
+                    if let Some(path) = &history {
+                        let saved = path
+                            .parent()
+                            .map_or(Ok(()), std::fs::create_dir_all)
+                            .map_err(rustyline::error::ReadlineError::from)
+                            .and_then(|_| rl.save_history(path));
+                        if let Err(err) = saved {
+                            eprintln!("repl history isn't saved: {err}");
+                        }
+                    }
+                }
+
+                // quotes are balanced, see ReplHelper::validate
+                let mut cmd_line = shellwords::split(line.as_str()).unwrap();
+                let bin_name = std::env::args().next().unwrap();
+                cmd_line.insert(0, bin_name);
+
+                let (cmd, verbosity) = match clipr_common::Args::try_parse_from(cmd_line) {
+                    Ok(args) => (
+                        args.command.unwrap_or(clipr_common::Command::Help),
+                        Verbosity::new(args.quiet, args.verbose),
+                    ),
+                    Err(_) => (clipr_common::Command::Help, Verbosity::default()),
+                };
+
+                verbosity.request(&cmd, None);
+                let started = Instant::now();
+                let response = clipr_common::Request::send_cmd(&sender, cmd).await;
+                verbosity.response(started.elapsed());
+                match response {
+                    Some(clipr_common::Response::Stop) => return,
+                    Some(clipr_common::Response::Payload(val)) => {
+                        if verbosity.shows(&val) {
+                            println!("{}", val.format(&display))
+                        }
+                    }
+                    Some(clipr_common::Response::Error(err)) => println!("error: {err}"),
+                    _ => continue,
+                }
+            }
+            Err(_) => sender.send(clipr_common::Request::Quit).await.unwrap(),
+        }
+    }
+}
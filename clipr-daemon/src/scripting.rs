@@ -0,0 +1,169 @@
+use anyhow::{anyhow, Result};
+use clipr_common::plugin::CommandPlugin;
+use clipr_common::{Entries, Item, SourceApp};
+use rhai::{Array, Dynamic, Engine, Scope, AST};
+use std::collections::HashSet;
+use std::path::Path;
+
+// scripts run on capture path, endless loop must not hang daemon
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+// Rhai scripts from `scripts` directory, all `*.rhai` files share one namespace.
+//
+// fn filter(value, app)       - return false to skip captured value
+// fn tags(value, app)         - return array of tags for captured value
+// fn <name>(value)            - named transform, `plugin script <name> [index]`
+//
+// `app` is source application name or `()`. Script running over
+// MAX_OPERATIONS fails (captured value is kept as is).
+pub struct Scripts {
+    engine: Engine,
+    ast: AST,
+}
+
+fn app_name(app: Option<&SourceApp>) -> Dynamic {
+    app.map_or(Dynamic::UNIT, |app| app.name.clone().into())
+}
+
+impl Scripts {
+    pub fn load(dir: &Path) -> Result<Self> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let mut paths = std::fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "rhai"))
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        let mut ast = AST::empty();
+        for path in paths {
+            let script = engine
+                .compile_file(path.clone())
+                .map_err(|err| anyhow!("{}: {err}", path.display()))?;
+            ast += script;
+        }
+
+        Ok(Self { engine, ast })
+    }
+
+    fn has_fn(&self, name: &str, params: usize) -> bool {
+        self.ast
+            .iter_functions()
+            .any(|f| f.name == name && f.params.len() == params)
+    }
+
+    fn call<T: Clone + Send + Sync + 'static>(
+        &self,
+        name: &str,
+        args: impl rhai::FuncArgs,
+    ) -> Result<T> {
+        self.engine
+            .call_fn(&mut Scope::new(), &self.ast, name, args)
+            .map_err(|err| anyhow!("{name}: {err}"))
+    }
+}
+
+impl CommandPlugin for Scripts {
+    fn name(&self) -> &str {
+        "script"
+    }
+
+    fn help(&self) -> &str {
+        "script name [index] - apply named transform to item"
+    }
+
+    fn call(&self, entries: &mut Entries, args: Vec<String>) -> Result<serde_json::Value> {
+        let name = args
+            .first()
+            .ok_or_else(|| anyhow!("transform name is required"))?;
+        if !self.has_fn(name, 1) {
+            return Err(anyhow!("transform {name:?} not found"));
+        }
+        let index = match args.get(1) {
+            Some(index) => index.parse()?,
+            None => 0,
+        };
+        let item = entries
+            .get(index)
+            .ok_or_else(|| anyhow!("item at {index:?} not found"))?;
+        // value is a placeholder, real one isn't reachable from plugin
+        if item.keychain || item.spilled {
+            return Err(anyhow!("item at {index:?} is kept outside history"));
+        }
+        let value: String = self.call(name, (item.value.clone(),))?;
+        Ok(value.into())
+    }
+
+    fn filter(&self, value: &str, app: Option<&SourceApp>) -> bool {
+        if !self.has_fn("filter", 2) {
+            return true;
+        }
+        self.call("filter", (value.to_string(), app_name(app)))
+            .unwrap_or_else(|err| {
+                eprintln!("script failed: {err}");
+                true
+            })
+    }
+
+    fn on_insert(&self, item: &mut Item, app: Option<&SourceApp>) {
+        if !self.has_fn("tags", 2) {
+            return;
+        }
+        match self.call::<Array>("tags", (item.value.clone(), app_name(app))) {
+            Ok(tags) => item
+                .tags
+                .get_or_insert_with(HashSet::new)
+                .extend(tags.into_iter().map(|tag| tag.to_string())),
+            Err(err) => eprintln!("script failed: {err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_max_operations() {
+        let dir = std::env::temp_dir().join(format!("clipr-scripts-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("loop.rhai"),
+            "fn filter(value, app) { loop {} }\nfn tags(value, app) { loop {} }",
+        )
+        .unwrap();
+        let scripts = Scripts::load(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(scripts.filter("value", None));
+        let mut item = Item::new(String::from("value"));
+        scripts.on_insert(&mut item, None);
+        assert!(item.tags.is_none());
+    }
+
+    #[test]
+    fn test_call_placeholder() {
+        let dir = std::env::temp_dir().join(format!("clipr-transform-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("upper.rhai"),
+            "fn upper(value) { value.to_upper() }",
+        )
+        .unwrap();
+        let scripts = Scripts::load(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let mut entries = Entries::new();
+        entries.insert(String::from("value"));
+        let args = || vec![String::from("upper")];
+        assert_eq!(
+            CommandPlugin::call(&scripts, &mut entries, args()).unwrap(),
+            "VALUE"
+        );
+        entries.get(0).unwrap().keychain = true;
+        assert!(CommandPlugin::call(&scripts, &mut entries, args()).is_err());
+        entries.get(0).unwrap().keychain = false;
+        entries.get(0).unwrap().spilled = true;
+        assert!(CommandPlugin::call(&scripts, &mut entries, args()).is_err());
+    }
+}
@@ -0,0 +1,52 @@
+use anyhow::{bail, Result};
+use async_std::channel::Sender;
+use async_std::task;
+use clipr_common::Request;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+static TERMINATED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_terminate(_: libc::c_int) {
+    TERMINATED.store(true, Ordering::SeqCst);
+}
+
+// Pid file is how `clipr-cli daemon stop` finds daemon that doesn't answer,
+// one left by crashed daemon doesn't count.
+pub fn check_running(path: &Path) -> Result<()> {
+    let pid = fs::read_to_string(path)
+        .ok()
+        .and_then(|pid| pid.trim().parse::<libc::pid_t>().ok());
+    if let Some(pid) = pid.filter(|pid| unsafe { libc::kill(*pid, 0) } == 0) {
+        bail!(
+            "daemon is already running (pid {pid}, see {})",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+pub fn write_pid(path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, format!("{}\n", std::process::id()))?;
+    Ok(())
+}
+
+// SIGTERM (launchd, `clipr-cli daemon stop`) quits like `quit` does, so
+// history is saved on the way out.
+pub async fn terminate_loop(sender: Sender<Request>) {
+    unsafe {
+        libc::signal(
+            libc::SIGTERM,
+            on_terminate as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+    while !TERMINATED.load(Ordering::SeqCst) {
+        task::sleep(Duration::from_millis(200)).await;
+    }
+    let _ = sender.send(Request::Quit).await;
+}
@@ -0,0 +1,129 @@
+use crate::limits::RateLimit;
+use anyhow::Result;
+use async_std::channel::Sender;
+use async_std::io::BufReader;
+use async_std::net::{TcpListener, TcpStream};
+use async_std::prelude::*;
+use async_std::task;
+use clap::Parser;
+use clipr_common::{Access, DisplayConfig, HttpConfig, Payload, Response};
+use std::sync::Arc;
+
+// `ok <bytes>` line followed by formatted payload, or `error <message>` line
+// (message has no newlines), so clients can tell where reply ends.
+fn reply(response: Option<Response>, display: &DisplayConfig) -> Option<String> {
+    let payload = match response? {
+        Response::Stop => return None,
+        Response::Error(err) => return Some(error_line(&err)),
+        Response::Payload(
+            payload @ (Payload::NotFound { .. }
+            | Payload::Locked
+            | Payload::InvalidArgs { .. }
+            | Payload::Conflict { .. }),
+        ) => return Some(error_line(&String::from(&payload))),
+        Response::Payload(payload) => payload,
+        _ => return Some(error_line("unexpected response")),
+    };
+    Some(ok_line(&payload.format(display)))
+}
+
+fn ok_line(body: &str) -> String {
+    format!("ok {}\n{body}\n", body.len())
+}
+
+fn error_line(message: &str) -> String {
+    format!("error {}\n", message.lines().next().unwrap_or_default())
+}
+
+// Same tokens and rate limit as HTTP API (see HttpConfig), with tokens set
+// connection starts with `auth <token>` line.
+async fn handle(
+    stream: TcpStream,
+    sender: Sender<clipr_common::Request>,
+    display: DisplayConfig,
+    http: HttpConfig,
+    rate_limit: Arc<RateLimit>,
+) -> Result<()> {
+    let client = stream.peer_addr()?.ip().to_string();
+    let mut access = http.access(None);
+    let mut lines = BufReader::new(stream.clone()).lines();
+    let mut stream = stream;
+    while let Some(line) = lines.next().await {
+        let line = line?;
+        if !rate_limit.allows(client.clone()) {
+            stream
+                .write_all(error_line("too many requests").as_bytes())
+                .await?;
+            continue;
+        }
+        if let Some(token) = line.trim().strip_prefix("auth ") {
+            access = http.access(Some(token.trim()));
+            let reply = match access {
+                Some(Access::Full) => ok_line("full access"),
+                Some(Access::Read) => ok_line("read access"),
+                None => error_line("unknown token"),
+            };
+            stream.write_all(reply.as_bytes()).await?;
+            continue;
+        }
+        let Ok(mut cmd_line) = shellwords::split(line.trim()) else {
+            stream
+                .write_all(error_line("mismatched quotes").as_bytes())
+                .await?;
+            continue;
+        };
+        if cmd_line.is_empty() {
+            continue;
+        }
+        cmd_line.insert(0, String::from("clipr"));
+        let cmd = match clipr_common::Args::try_parse_from(cmd_line) {
+            Ok(args) => args.command.unwrap_or(clipr_common::Command::Help),
+            Err(err) => {
+                let message = err.to_string();
+                let message = message.strip_prefix("error: ").unwrap_or(&message);
+                stream.write_all(error_line(message).as_bytes()).await?;
+                continue;
+            }
+        };
+        let denied = match access {
+            None => Some("unauthorized, send `auth <token>` first"),
+            Some(Access::Read) if !cmd.is_read_access() => Some("forbidden for read-only token"),
+            _ => None,
+        };
+        if let Some(denied) = denied {
+            stream.write_all(error_line(denied).as_bytes()).await?;
+            continue;
+        }
+        match reply(
+            clipr_common::Request::send_cmd(&sender, cmd).await,
+            &display,
+        ) {
+            Some(reply) => stream.write_all(reply.as_bytes()).await?,
+            None => return Ok(()),
+        }
+    }
+    Ok(())
+}
+
+// Line protocol for environments without curl/jq: each line is a command as
+// typed in REPL (e.g. `nc 127.0.0.1 1518` then `get 3`).
+pub async fn text_server(
+    listen_on: String,
+    sender: Sender<clipr_common::Request>,
+    display: DisplayConfig,
+    http: HttpConfig,
+) -> Result<()> {
+    let listener = TcpListener::bind(listen_on).await?;
+    let rate_limit = Arc::new(RateLimit::new(http.rate_limit()));
+    let mut incoming = listener.incoming();
+    while let Some(stream) = incoming.next().await {
+        let (stream, sender, display) = (stream?, sender.clone(), display.clone());
+        let (http, rate_limit) = (http.clone(), rate_limit.clone());
+        task::spawn(async move {
+            if let Err(err) = handle(stream, sender, display, http, rate_limit).await {
+                eprintln!("text connection failed: {err}");
+            }
+        });
+    }
+    Ok(())
+}
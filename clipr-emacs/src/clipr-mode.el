@@ -13,9 +13,11 @@
   :type 'string
   :group 'clipr)
 
-(defcustom clipr-config-path "~/config/clipr.toml"
-  "Clipr config path."
-  :type 'string
+(defcustom clipr-config-path nil
+  "Clipr config path.
+When nil config is looked up in standard locations
+\(`$XDG_CONFIG_HOME/clipr/config.toml', `~/Library/Application Support/clipr/config.toml')."
+  :type '(choice (const :tag "Default" nil) file)
   :group 'clipr)
 
 (defcustom clipr-edit-temp-file "/tmp/.cliprb"
@@ -28,10 +30,27 @@
   :type 'integer
   :group 'clipr)
 
+(defcustom clipr-async-poll-interval 0.05
+  "Clipr async command poll interval (seconds)."
+  :type 'number
+  :group 'clipr)
+
+(defcustom clipr-new-items-poll-interval 0.5
+  "Clipr new items poll interval (seconds)."
+  :type 'number
+  :group 'clipr)
+
+(defvar clipr-new-item-hook nil
+  "Hook run with item plist for every item captured by daemon.
+Items are delivered after `clipr-notifications-start'.")
+
+(defvar clipr--notifications-timer nil)
+
 (defconst clipr-list-format
   [("Pos" 7 t)
    ("Date" 13 t)
    ("Tags" 16 t)
+   ("App" 12 t)
    ("Content" 35 nil)]
   "Clipr list format.")
 
@@ -41,6 +60,9 @@
 
 (defvar clipr--query-cmd clipr--default-query-cmd)
 
+(defvar clipr--hashes (make-hash-table)
+  "Item hashes by position, as of last refresh.")
+
 (defun clipr-create ()
   "Create Clipr."
   (let* ((clipr-buffer (get-buffer-create clipr-buffer-name))
@@ -50,27 +72,79 @@
           (funcall 'clipr-mode)))
     (select-window (display-buffer clipr-buffer))))
 
-(defun clipr-list-entries ()
-  "Get entries for Clipr."
+(defun clipr-cmd-async (cmd callback)
+  "Run CMD in background, CALLBACK is called with payload when it's done."
+  (clipr--poll (clipr-cmd-start cmd) callback))
+
+(defun clipr--poll (id callback)
+  (condition-case err
+      (let ((result (clipr-cmd-poll id)))
+        (if result
+            (funcall callback (cdr result))
+          (run-with-timer clipr-async-poll-interval nil #'clipr--poll id callback)))
+    (error (message "Clipr: %s" (error-message-string err)))))
+
+(defun clipr-notifications-start ()
+  "Start delivering new items to `clipr-new-item-hook'."
+  (interactive)
+  (clipr-subscribe)
+  (unless clipr--notifications-timer
+    (setq clipr--notifications-timer
+          (run-with-timer clipr-new-items-poll-interval
+                          clipr-new-items-poll-interval
+                          #'clipr--dispatch-new-items))))
+
+(defun clipr-notifications-stop ()
+  "Stop delivering new items."
+  (interactive)
+  (clipr-unsubscribe)
+  (when clipr--notifications-timer
+    (cancel-timer clipr--notifications-timer)
+    (setq clipr--notifications-timer nil)))
+
+(defun clipr--dispatch-new-items ()
+  (dolist (item (clipr-take-new-items))
+    (run-hook-with-args 'clipr-new-item-hook item)))
+
+(defun clipr--refresh-on-new-item (_item)
+  "Keep Clipr buffer up to date."
+  (when (get-buffer clipr-buffer-name)
+    (clipr-refresh)))
+
+(add-hook 'clipr-new-item-hook #'clipr--refresh-on-new-item)
+
+(defun clipr--entries (payload)
+  "Convert list PAYLOAD to Clipr entries."
+  (clrhash clipr--hashes)
   (-map
    (lambda (entry)
+     (puthash (plist-get entry :pos) (plist-get entry :hash) clipr--hashes)
      (let ((pos (plist-get entry :pos))
-           (content (plist-get entry :content))
-           (tags (plist-get entry :tags))
-           (date (plist-get entry :date)))
+           (content (clipr--highlight-matches (plist-get entry :content)
+                                              (plist-get entry :matches)))
+           (tags (string-join (plist-get entry :tags) ":"))
+           (date (plist-get entry :date))
+           (app (plist-get entry :app)))
        (list pos (vector
                   (cons (number-to-string pos) `(face default action ,clipr--default-action))
                   (cons date `(face default action ,clipr--default-action))
                   (cons tags `(face bold action ,clipr--default-action))
-                  (cons content `(face font-lock-comment-face action ,clipr--default-action))))))
-   (clipr-cmd clipr--query-cmd)))
+                  (cons app `(face italic action ,clipr--default-action))
+                  (cons content `(action ,clipr--default-action))))))
+   (plist-get payload :items)))
+
+(defun clipr--highlight-matches (content matches)
+  "Propertize CONTENT, MATCHES are (START END) ranges of select matches."
+  (let ((content (propertize content 'face 'font-lock-comment-face)))
+    (dolist (match matches content)
+      (add-face-text-property (car match) (cadr match) 'match nil content))))
 
 (defun clipr--read-tag ()
   (let ((tags (string-split (car (aref (tabulated-list-get-entry) 2)) ":")))
     (list (completing-read "Tag: " tags))))
 
 (defun clipr--read-all-tags ()
-  (let ((tags (string-split (clipr-cmd "tags") ":")))
+  (let ((tags (--map (plist-get it :name) (plist-get (clipr-cmd "tags") :tags))))
     (list (completing-read "Tag: " tags))))
 
 (defun clipr-show ()
@@ -81,22 +155,35 @@
 (defun clipr-refresh ()
   "Refresh Clipr."
   (interactive)
-  (with-current-buffer clipr-buffer-name
-    (tabulated-list-print :remember-pos)
-    (hl-line-highlight)))
+  (clipr-cmd-async clipr--query-cmd #'clipr--refresh-with))
 
-(defun clipr-select ()
+(defun clipr--refresh-with (payload)
+  (when (get-buffer clipr-buffer-name)
+    (with-current-buffer clipr-buffer-name
+      (setq tabulated-list-entries (clipr--entries payload))
+      (tabulated-list-print :remember-pos)
+      (hl-line-highlight))))
+
+(defun clipr--conflict ()
+  (message "Clipr: history changed, refreshing")
+  (clipr-refresh))
+
+(defun clipr-select-entry ()
   "Copy selected entry to clipboard."
   (interactive)
-  (let ((cmd (format "set %d" (tabulated-list-get-id))))
-    (clipr-cmd cmd)
-    (pulse-momentary-highlight-one-line)))
+  (let ((pos (tabulated-list-get-id)))
+    (condition-case nil
+        (progn
+          (clipr-set pos (gethash pos clipr--hashes))
+          (pulse-momentary-highlight-one-line)
+          t)
+      (error (clipr--conflict)))))
 
 (defun clipr-select-and-quit (arg)
   "Copy selected entry to clipboard."
   (interactive)
-  (clipr-select)
-  (clipr-kill))
+  (when (clipr-select-entry)
+    (clipr-kill)))
 
 (defun clipr-delete ()
   "Delete selected entries."
@@ -108,7 +195,13 @@
                            (tabulated-list-get-id start)
                            (tabulated-list-get-id end)))
         (goto-line (line-number-at-pos start)))
-    (clipr-cmd (format "del %d" (tabulated-list-get-id))))
+    (let* ((pos (tabulated-list-get-id))
+           (hash (gethash pos clipr--hashes))
+           (payload (clipr-cmd (if hash
+                                   (format "del %d --expected-hash %s" pos hash)
+                                 (format "del %d" pos)))))
+      (when (eq (plist-get payload :type) 'conflict)
+        (message "Clipr: history changed, item not deleted"))))
   (clipr-refresh))
 
 (defun clipr-kill ()
@@ -118,13 +211,13 @@
     (kill-buffer)
     (delete-window)))
 
-(defun clipr-tag (tag)
+(defun clipr-tag-entry (tag)
   "Tag selected entry."
   (interactive (clipr--read-tag))
-  (clipr-cmd (format "tag %d %s" (tabulated-list-get-id) tag))
+  (clipr-tag (tabulated-list-get-id) tag)
   (clipr-refresh))
 
-(defun clipr-untag (tag)
+(defun clipr-untag-entry (tag)
   "Untag selected entry."
   (interactive (clipr--read-tag))
   (clipr-cmd (format "untag %d %s" (tabulated-list-get-id) tag))
@@ -147,7 +240,7 @@
 (defun clipr-jump-to-tag (tag)
   "Jump to tag"
   (interactive (clipr--read-all-tags))
-  (let ((pos (plist-get (car (clipr-cmd (format "select --tag '%s'" tag))) :pos)))
+  (let ((pos (plist-get (car (clipr-select nil (list tag) nil nil)) :pos)))
     (goto-line (+ 1 pos))))
 
 (defun clipr-save ()
@@ -164,6 +257,13 @@
   (clipr-refresh)
   (message "State loaded."))
 
+(defun clipr-unlock (passphrase)
+  "Unlock daemon protected with PASSPHRASE (see `[lock]' config)."
+  (interactive (list (read-passwd "Passphrase: ")))
+  (let ((payload (clipr-cmd (format "unlock %s" (shell-quote-argument passphrase)))))
+    (message "Clipr: %s" (plist-get payload :message)))
+  (clipr-refresh))
+
 (defcustom clipr-edit-buffer-name "Clipr Edit"
   "Clipr edit buffer name."
   :type 'string
@@ -172,7 +272,7 @@
 (defun clipr-edit ()
   (interactive)
   (let* ((tags (string-split (car (aref (tabulated-list-get-entry) 2)) ":"))
-         (content (clipr-cmd (format "get %d" (tabulated-list-get-id))))
+         (content (clipr-get (tabulated-list-get-id)))
          (clipr-edit-buffer (get-buffer-create clipr-edit-buffer-name))
          (buffer-window (get-buffer-window clipr-edit-buffer))
          (new-window (or buffer-window (split-window-below))))
@@ -188,14 +288,15 @@
     (define-key map (kbd "RET") 'clipr-select-and-quit)
     (define-key map (kbd "d") 'clipr-delete)
     (define-key map (kbd "q") 'clipr-kill)
-    (define-key map (kbd "+") 'clipr-tag)
-    (define-key map (kbd "-") 'clipr-untag)
+    (define-key map (kbd "+") 'clipr-tag-entry)
+    (define-key map (kbd "-") 'clipr-untag-entry)
     (define-key map (kbd "f") 'clipr-filter-by-tag)
     (define-key map (kbd "c") 'clipr-filter-clear)
     (define-key map (kbd "j") 'clipr-jump-to-tag)
     (define-key map (kbd "S") 'clipr-save)
     (define-key map (kbd "L") 'clipr-load)
     (define-key map (kbd "E") 'clipr-edit)
+    (define-key map (kbd "U") 'clipr-unlock)
     map)
   "Keymap for Clipr.")
 
@@ -207,10 +308,11 @@
   (setq major-mode 'clipr-mode)
   (use-local-map clipr-mode-map)
   (setq tabulated-list-format clipr-list-format)
-  (setq tabulated-list-entries 'clipr-list-entries)
+  (setq tabulated-list-entries nil)
   (tabulated-list-init-header)
   (tabulated-list-print)
-  (hl-line-mode 1))
+  (hl-line-mode 1)
+  (clipr-refresh))
 
 (defun clipr-insert ()
   (interactive)
@@ -219,9 +321,7 @@
       (insert buf))
     (clipr-cmd (format "insert %s" clipr-edit-temp-file))
     ;; FIXME: buffer update workaround ;)
-    (run-with-timer 0.3 nil (lambda ()
-                              (with-current-buffer clipr-buffer-name
-                                (tabulated-list-print nil t))))
+    (run-with-timer 0.3 nil #'clipr-refresh)
     (clipr-edit-kill)))
 
 (defun clipr-edit-kill ()
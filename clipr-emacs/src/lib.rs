@@ -1,16 +1,31 @@
 use anyhow::bail;
+use async_std::prelude::*;
 use chrono::prelude::*;
 use clap::Parser;
-use clipr_common::{shorten, Command, Config, Payload};
+use clipr_client::Client;
+use clipr_common::{
+    paths, summarize, Command, Config, Filter, Item, ItemKind, ItemSummary, Payload,
+};
 use emacs::IntoLisp;
 use emacs::{Env, Result, Value};
-use std::path::Path;
-use std::sync::Arc;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Mutex;
 
 // Emacs won't load the module without this.
 emacs::plugin_is_GPL_compatible!();
 
-static DEFAULT_CONFIG_PATH: &str = "~/config/clipr.toml";
+// results of background commands, taken by `clipr-cmd-poll`
+static NEXT_ID: AtomicI64 = AtomicI64::new(0);
+static RESULTS: Mutex<BTreeMap<i64, anyhow::Result<Payload>>> = Mutex::new(BTreeMap::new());
+
+// items pushed by daemon, taken by `clipr-take-new-items`
+const MAX_NEW_ITEMS: usize = 256;
+static NEW_ITEMS: Mutex<VecDeque<Item>> = Mutex::new(VecDeque::new());
+static SUBSCRIPTION: Mutex<Option<async_std::task::JoinHandle<()>>> = Mutex::new(None);
+
+// (config path, client) built on first call, see `clipr-reconnect`
+static CLIENT: Mutex<Option<(Option<String>, Client)>> = Mutex::new(None);
 
 // Register the initialization hook that Emacs will call when it loads the module.
 #[emacs::module]
@@ -18,94 +33,482 @@ fn init(env: &Env) -> Result<Value<'_>> {
     env.message("Done loading!")
 }
 
-fn get_config_path(env: &Env) -> emacs::Result<emacs::Value<'_>> {
+// nil (or unbound) `clipr-config-path` means standard locations, see paths::config_dirs
+fn get_config_path(env: &Env) -> emacs::Result<Option<String>> {
     let var = env.intern("clipr-config-path")?;
     let is_bound: bool = env.call("boundp", [var])?.is_not_nil();
     if !is_bound {
-        return DEFAULT_CONFIG_PATH.to_string().into_lisp(env);
+        return Ok(None);
+    }
+
+    env.call("symbol-value", [var])?
+        .into_rust::<Option<String>>()
+}
+
+// (:key value ...)
+fn props<'a>(
+    env: &'a Env,
+    props: Vec<(&str, emacs::Value<'a>)>,
+) -> emacs::Result<emacs::Value<'a>> {
+    let mut result = vec![];
+    for (key, value) in props {
+        result.push(env.intern(&format!(":{key}"))?);
+        result.push(value);
     }
+    env.list(result.as_slice())
+}
+
+// (:type TYPE :key value ...)
+fn plist<'a>(
+    env: &'a Env,
+    type_: &str,
+    mut values: Vec<(&str, emacs::Value<'a>)>,
+) -> emacs::Result<emacs::Value<'a>> {
+    values.insert(0, ("type", env.intern(type_)?));
+    props(env, values)
+}
+
+fn strings_to_lisp<'a>(values: &[String], env: &'a Env) -> emacs::Result<emacs::Value<'a>> {
+    let values = values
+        .iter()
+        .map(|v| v.as_str().into_lisp(env))
+        .collect::<emacs::Result<Vec<emacs::Value>>>()?;
+    env.list(values.as_slice())
+}
+
+fn item_to_lisp<'a>(
+    index: usize,
+    item: &ItemSummary,
+    env: &'a Env,
+) -> emacs::Result<emacs::Value<'a>> {
+    let mut item_tags = item.tags.iter().flatten().cloned().collect::<Vec<String>>();
+    item_tags.sort();
 
-    let config_path: String = env
-        .call("symbol-value", [var])?
-        .into_rust::<String>()
-        .unwrap_or(DEFAULT_CONFIG_PATH.to_string());
+    let files = match &item.kind {
+        ItemKind::Files(paths) => paths
+            .iter()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect(),
+        _ => vec![],
+    };
+
+    let item_date: String = DateTime::<Local>::from(item.accessed_at)
+        .format("%d-%m-%Y")
+        .to_string();
+
+    props(
+        env,
+        vec![
+            ("pos", index.into_lisp(env)?),
+            ("id", item.id.as_str().into_lisp(env)?),
+            // string, u64 doesn't fit into fixnum
+            ("hash", item.hash.to_string().into_lisp(env)?),
+            ("content", item.preview.as_str().into_lisp(env)?),
+            ("tags", strings_to_lisp(&item_tags, env)?),
+            ("date", item_date.into_lisp(env)?),
+            (
+                "app",
+                item.source_app.clone().unwrap_or_default().into_lisp(env)?,
+            ),
+            ("pin", item.pin.map(String::from).into_lisp(env)?),
+            ("note", item.note.clone().into_lisp(env)?),
+            ("title", item.title.clone().into_lisp(env)?),
+            ("language", item.language.clone().into_lisp(env)?),
+            ("matches", matches_to_lisp(item, env)?),
+            ("favorite", item.favorite.into_lisp(env)?),
+            ("ephemeral", item.ephemeral.into_lisp(env)?),
+            ("files", strings_to_lisp(&files, env)?),
+            (
+                "access-counter",
+                i64::from(item.access_counter).into_lisp(env)?,
+            ),
+        ],
+    )
+}
+
+// (START END) char offsets into :content
+fn matches_to_lisp<'a>(item: &ItemSummary, env: &'a Env) -> emacs::Result<emacs::Value<'a>> {
+    let offset = |byte: usize| item.preview[..byte].chars().count();
+    let matches = item
+        .matches
+        .iter()
+        .map(|&(start, end)| env.list((offset(start), offset(end))))
+        .collect::<emacs::Result<Vec<emacs::Value>>>()?;
+    env.list(matches.as_slice())
+}
 
-    config_path.into_lisp(env)
+fn items_to_lisp<'a>(
+    items: &[(usize, ItemSummary)],
+    env: &'a Env,
+) -> emacs::Result<emacs::Value<'a>> {
+    let items = items
+        .iter()
+        .map(|(index, item)| item_to_lisp(*index, item, env))
+        .collect::<emacs::Result<Vec<emacs::Value>>>()?;
+    env.list(items.as_slice())
+}
+
+fn list_to_strings(mut list: emacs::Value<'_>) -> emacs::Result<Vec<String>> {
+    let mut result = vec![];
+    while list.is_not_nil() {
+        result.push(list.car()?);
+        list = list.cdr()?;
+    }
+    Ok(result)
+}
+
+// objects become plists with keyword keys, arrays become lists
+fn json_to_lisp<'a>(value: &serde_json::Value, env: &'a Env) -> emacs::Result<emacs::Value<'a>> {
+    match value {
+        serde_json::Value::Null => ().into_lisp(env),
+        serde_json::Value::Bool(b) => b.into_lisp(env),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => i.into_lisp(env),
+            None => n.as_f64().unwrap_or_default().into_lisp(env),
+        },
+        serde_json::Value::String(s) => s.as_str().into_lisp(env),
+        serde_json::Value::Array(values) => {
+            let values = values
+                .iter()
+                .map(|v| json_to_lisp(v, env))
+                .collect::<emacs::Result<Vec<emacs::Value>>>()?;
+            env.list(values.as_slice())
+        }
+        serde_json::Value::Object(map) => {
+            let mut result = vec![];
+            for (key, value) in map {
+                result.push(env.intern(&format!(":{key}"))?);
+                result.push(json_to_lisp(value, env)?);
+            }
+            env.list(result.as_slice())
+        }
+    }
 }
 
 fn payload_to_lisp<'a>(payload: &Payload, env: &'a Env) -> emacs::Result<emacs::Value<'a>> {
     match payload {
-        Payload::Ok => "ok".to_string().into_lisp(env),
-        Payload::Stop => "stop".to_string().into_lisp(env),
-        Payload::List {
-            value,
-            preview_length,
+        Payload::Ok => plist(env, "ok", vec![]),
+        Payload::Stop => plist(env, "stop", vec![]),
+        Payload::Locked => plist(env, "locked", vec![]),
+        Payload::InvalidArgs { reason } => plist(
+            env,
+            "invalid-args",
+            vec![("reason", reason.clone().into_lisp(env)?)],
+        ),
+        Payload::List { value, .. } => {
+            plist(env, "list", vec![("items", items_to_lisp(value, env)?)])
+        }
+        Payload::Ambiguous { value, .. } => plist(
+            env,
+            "ambiguous",
+            vec![("items", items_to_lisp(value, env)?)],
+        ),
+        Payload::Value { value } => {
+            plist(env, "value", vec![("value", value.clone().into_lisp(env)?)])
+        }
+        Payload::Stats { value } => plist(
+            env,
+            "stats",
+            vec![
+                ("chars", value.chars.into_lisp(env)?),
+                ("words", value.words.into_lisp(env)?),
+                ("lines", value.lines.into_lisp(env)?),
+                ("bytes", value.bytes.into_lisp(env)?),
+                ("language", value.language.clone().into_lisp(env)?),
+            ],
+        ),
+        Payload::Values { value } => {
+            let values = value
+                .iter()
+                .map(|v| v.as_deref().into_lisp(env))
+                .collect::<emacs::Result<Vec<emacs::Value>>>()?;
+            plist(
+                env,
+                "values",
+                vec![("values", env.list(values.as_slice())?)],
+            )
+        }
+        Payload::Message { value } => plist(
+            env,
+            "message",
+            vec![("message", value.as_str().into_lisp(env)?)],
+        ),
+        Payload::Status {
+            listen_on,
+            count,
+            peers,
         } => {
-            let pos = env.intern(":pos")?;
-            let content = env.intern(":content")?;
-            let tags = env.intern(":tags")?;
-            let date = env.intern(":date")?;
-
-            let mut result: Vec<emacs::Value> = vec![];
-
-            for (index, item) in value.iter() {
-                let item_tags = if let Some(tags) = &item.tags {
-                    let mut ts = tags.iter().cloned().collect::<Vec<String>>();
-                    ts.sort();
-                    ts.join(":")
-                } else {
-                    "".to_string()
-                };
-
-                let item_date: String = DateTime::<Local>::from(item.accessed_at)
-                    .format("%d-%m-%Y")
-                    .to_string();
-
-                let v = env.list((
-                    pos,
-                    *index,
-                    content,
-                    shorten(&item.value, *preview_length),
-                    tags,
-                    item_tags,
-                    date,
-                    item_date,
-                ))?;
-                result.push(v);
-            }
+            let peers = peers
+                .iter()
+                .flatten()
+                .map(|peer| {
+                    env.list((
+                        env.intern(":name")?,
+                        peer.name.as_str(),
+                        env.intern(":address")?,
+                        peer.address.as_str(),
+                    ))
+                })
+                .collect::<emacs::Result<Vec<emacs::Value>>>()?;
+            plist(
+                env,
+                "status",
+                vec![
+                    ("listen-on", listen_on.as_str().into_lisp(env)?),
+                    ("count", (*count).into_lisp(env)?),
+                    ("peers", env.list(peers.as_slice())?),
+                ],
+            )
+        }
+        Payload::NotFound { target } => plist(
+            env,
+            "not-found",
+            vec![("target", target.to_string().into_lisp(env)?)],
+        ),
+        Payload::Conflict { index, hash } => plist(
+            env,
+            "conflict",
+            vec![
+                ("index", index.into_lisp(env)?),
+                ("hash", hash.map(|hash| hash.to_string()).into_lisp(env)?),
+            ],
+        ),
+        Payload::Groups { value } => {
+            let groups = value
+                .iter()
+                .map(|group| items_to_lisp(&summarize(group, None), env))
+                .collect::<emacs::Result<Vec<emacs::Value>>>()?;
+            plist(
+                env,
+                "groups",
+                vec![("groups", env.list(groups.as_slice())?)],
+            )
+        }
+        Payload::History { value } => {
+            let days = value
+                .iter()
+                .map(|day| {
+                    props(
+                        env,
+                        vec![
+                            ("day", day.day.to_string().into_lisp(env)?),
+                            ("count", day.count.into_lisp(env)?),
+                            ("items", items_to_lisp(&day.items, env)?),
+                        ],
+                    )
+                })
+                .collect::<emacs::Result<Vec<emacs::Value>>>()?;
+            plist(env, "history", vec![("days", env.list(days.as_slice())?)])
+        }
+        Payload::Tags { value } => {
+            let tags = value
+                .iter()
+                .map(|(tag, count)| {
+                    env.list((
+                        env.intern(":name")?,
+                        tag.as_str(),
+                        env.intern(":count")?,
+                        *count,
+                    ))
+                })
+                .collect::<emacs::Result<Vec<emacs::Value>>>()?;
+            plist(env, "tags", vec![("tags", env.list(tags.as_slice())?)])
+        }
+        Payload::Plugin { name, value } => plist(
+            env,
+            "plugin",
+            vec![
+                ("name", name.as_str().into_lisp(env)?),
+                ("value", json_to_lisp(value, env)?),
+            ],
+        ),
+    }
+}
+
+fn connect(config_path: Option<String>) -> emacs::Result<Client> {
+    let path = config_path.as_deref().map(paths::expand_tilde);
+    let config = Config::load(path.as_deref())?;
+    let client = Client::from_config(&config)?;
+    async_std::task::block_on(client.check_version())?;
+    *CLIENT.lock().unwrap() = Some((config_path, client.clone()));
+    Ok(client)
+}
 
-            Ok(env.list(result.as_slice())?)
+// Cached client, rebuilt only when `clipr-config-path` changes.
+fn get_client(env: &Env) -> emacs::Result<Client> {
+    let config_path = get_config_path(env)?;
+    if let Some((path, client)) = CLIENT.lock().unwrap().as_ref() {
+        if *path == config_path {
+            return Ok(client.clone());
         }
-        Payload::Value { value } => match value {
-            Some(v) => v.to_string().into_lisp(env),
-            _ => "".to_string().into_lisp(env),
-        },
-        Payload::Message { value } => value.to_string().into_lisp(env),
     }
+    connect(config_path)
 }
 
-// Define a function callable by Lisp code.
+// Re-read config and rebuild client (e.g. after daemon host/port change).
 #[emacs::defun]
-fn cmd(env: &Env, value: String) -> emacs::Result<emacs::Value<'_>> {
-    let config_path = get_config_path(env)?.into_rust::<String>()?;
-    let config = Arc::new(Config::load_config(Path::new(&config_path))?);
-    let mut cmd_line = shellwords::split(value.as_str()).unwrap();
+fn reconnect(env: &Env) -> emacs::Result<emacs::Value<'_>> {
+    let config_path = get_config_path(env)?;
+    let client = connect(config_path)?;
+    env.message(format!("Clipr: connected to {}", client.base_url()))
+}
+
+fn parse_cmd(value: &str) -> Command {
+    let mut cmd_line = shellwords::split(value).unwrap();
     cmd_line.insert(0, "$bin_name".to_string());
 
-    let cmd = match clipr_common::Args::try_parse_from(cmd_line) {
+    match clipr_common::Args::try_parse_from(cmd_line) {
         Ok(args) => args.command.unwrap(),
         Err(_) => clipr_common::Command::Help,
-    };
+    }
+}
+
+// Define a function callable by Lisp code.
+#[emacs::defun]
+fn cmd(env: &Env, value: String) -> emacs::Result<emacs::Value<'_>> {
+    let client = get_client(env)?;
+    let cmd = parse_cmd(&value);
 
-    match async_std::task::block_on(call(config, cmd)) {
+    match async_std::task::block_on(client.call(cmd)) {
         Ok(payload) => payload_to_lisp(&payload, env),
         Err(err) => bail!(err),
     }
 }
 
-async fn call(config: Arc<Config>, cmd: Command) -> anyhow::Result<Payload, surf::Error> {
-    let uri = format!("http://{}/command", config.listen_on());
-    let req = surf::post(uri).body_json(&cmd)?;
-    let rep: Payload = req.recv_json().await?;
-    Ok(rep)
+// Run command in background, returns id for `clipr-cmd-poll`.
+#[emacs::defun]
+fn cmd_start(env: &Env, value: String) -> emacs::Result<i64> {
+    let client = get_client(env)?;
+    let cmd = parse_cmd(&value);
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
+    async_std::task::spawn(async move {
+        let result = client.call(cmd).await;
+        RESULTS.lock().unwrap().insert(id, result);
+    });
+
+    Ok(id)
+}
+
+// nil while command is running, (t . payload) when it's done.
+#[emacs::defun]
+fn cmd_poll(env: &Env, id: i64) -> emacs::Result<emacs::Value<'_>> {
+    let result = RESULTS.lock().unwrap().remove(&id);
+    match result {
+        None => ().into_lisp(env),
+        Some(Ok(payload)) => env.call("cons", (env.intern("t")?, payload_to_lisp(&payload, env)?)),
+        Some(Err(err)) => bail!(err),
+    }
+}
+
+// Typed commands, nil stands for default value of optional argument.
+
+// (clipr-list FROM-INDEX TO-INDEX PREVIEW-LENGTH) => list of item plists
+#[emacs::defun]
+fn list(
+    env: &Env,
+    from_index: Option<usize>,
+    to_index: Option<usize>,
+    preview_length: Option<usize>,
+) -> emacs::Result<emacs::Value<'_>> {
+    let client = get_client(env)?;
+    let items = async_std::task::block_on(client.list(from_index, to_index, preview_length))?;
+    items_to_lisp(&items, env)
+}
+
+// (clipr-get INDEX) => item value
+#[emacs::defun]
+fn get(env: &Env, index: usize) -> emacs::Result<String> {
+    let client = get_client(env)?;
+    async_std::task::block_on(client.get(index))
+}
+
+// (clipr-set INDEX &optional HASH) puts item on pasteboard,
+// fails if HASH (see item :hash) doesn't match item at INDEX
+#[emacs::defun]
+fn set(env: &Env, index: usize, hash: Option<String>) -> emacs::Result<()> {
+    let client = get_client(env)?;
+    let expected_hash = hash.map(|hash| hash.parse()).transpose()?;
+    async_std::task::block_on(client.set(index, expected_hash))
+}
+
+// (clipr-tag INDEX TAG)
+#[emacs::defun]
+fn tag(env: &Env, index: usize, tag: String) -> emacs::Result<()> {
+    let client = get_client(env)?;
+    async_std::task::block_on(client.tag(index, &tag))
+}
+
+// (clipr-select PIN TAGS VALUE APP) => list of item plists, TAGS is list of strings
+#[emacs::defun]
+fn select<'a>(
+    env: &'a Env,
+    pin: Option<String>,
+    tags: emacs::Value<'a>,
+    value: Option<String>,
+    app: Option<String>,
+) -> emacs::Result<emacs::Value<'a>> {
+    let client = get_client(env)?;
+    let filter = Filter {
+        pin,
+        tag: list_to_strings(tags)?,
+        value,
+        app,
+        ..Filter::default()
+    };
+    let items = async_std::task::block_on(client.select(filter))?;
+    items_to_lisp(&items, env)
+}
+
+async fn listen(client: Client) {
+    let mut items = match client.subscribe().await {
+        Ok(items) => items,
+        Err(err) => return eprintln!("clipr: can't subscribe: {err}"),
+    };
+    while let Some(item) = items.next().await {
+        match item {
+            Ok(item) => {
+                let mut new_items = NEW_ITEMS.lock().unwrap();
+                if new_items.len() == MAX_NEW_ITEMS {
+                    new_items.pop_front();
+                }
+                new_items.push_back(item);
+            }
+            Err(err) => eprintln!("clipr: bad event: {err}"),
+        }
+    }
+}
+
+// Listen to daemon events in background (restarts listener if it's already running).
+#[emacs::defun]
+fn subscribe(env: &Env) -> emacs::Result<()> {
+    let client = get_client(env)?;
+    let handle = async_std::task::spawn(listen(client));
+    let previous = SUBSCRIPTION.lock().unwrap().replace(handle);
+    if let Some(previous) = previous {
+        async_std::task::block_on(previous.cancel());
+    }
+    Ok(())
+}
+
+#[emacs::defun]
+fn unsubscribe(_env: &Env) -> emacs::Result<()> {
+    let handle = SUBSCRIPTION.lock().unwrap().take();
+    if let Some(handle) = handle {
+        async_std::task::block_on(handle.cancel());
+    }
+    NEW_ITEMS.lock().unwrap().clear();
+    Ok(())
+}
+
+// (clipr-take-new-items) => items captured since last call, oldest first
+#[emacs::defun]
+fn take_new_items(env: &Env) -> emacs::Result<emacs::Value<'_>> {
+    let items: Vec<(usize, Item)> = NEW_ITEMS
+        .lock()
+        .unwrap()
+        .drain(..)
+        .map(|item| (0, item))
+        .collect();
+    items_to_lisp(&summarize(&items, None), env)
 }
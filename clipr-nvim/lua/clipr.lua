@@ -0,0 +1,82 @@
+-- Neovim frontend for clipr, talks to daemon through `clipr-cli --format json`.
+local M = {}
+
+M.options = {
+  cli = "clipr-cli",
+  -- path to clipr config.toml (nil for defaults)
+  config = nil,
+  preview_length = 80,
+}
+
+function M.setup(opts)
+  M.options = vim.tbl_extend("force", M.options, opts or {})
+end
+
+local function call(args)
+  local argv = { M.options.cli, "--format", "json" }
+  if M.options.config then
+    vim.list_extend(argv, { "-c", vim.fn.expand(M.options.config) })
+  end
+  vim.list_extend(argv, args)
+
+  local output = vim.fn.system(argv)
+  if vim.v.shell_error ~= 0 then
+    error("clipr: " .. output)
+  end
+
+  local payload = vim.json.decode(output, { luanil = { object = true, array = true } })
+  if payload.type == "message" then
+    error("clipr: " .. payload.value)
+  end
+  return payload
+end
+
+-- list of { index = N, item = { preview = ..., tags = ..., pin = ... } },
+-- full value is fetched with M.get
+function M.list(from_index, to_index, preview_length)
+  local args = { "list", tostring(from_index or 0) }
+  if to_index then
+    table.insert(args, tostring(to_index))
+    if preview_length then
+      table.insert(args, tostring(preview_length))
+    end
+  end
+  local payload = call(args)
+  return vim.tbl_map(function(pair)
+    return { index = pair[1], item = pair[2] }
+  end, payload.value)
+end
+
+function M.get(index)
+  return call({ "get", tostring(index or 0) }).value
+end
+
+function M.set(index)
+  call({ "set", tostring(index or 0) })
+end
+
+-- insert item value after cursor
+function M.paste(index)
+  local value = M.get(index)
+  vim.api.nvim_put(vim.split(value, "\n", { plain = true }), "", true, true)
+end
+
+local function format_entry(entry)
+  local value = entry.item.preview
+  local pin = entry.item.pin and ("[" .. entry.item.pin .. "] ") or ""
+  return string.format("%3d: %s%s", entry.index, pin, value)
+end
+
+-- browse history, chosen item is put on pasteboard
+function M.pick()
+  vim.ui.select(M.list(0, 128, M.options.preview_length), {
+    prompt = "Clipr",
+    format_item = format_entry,
+  }, function(entry)
+    if entry then
+      M.set(entry.index)
+    end
+  end)
+end
+
+return M
@@ -0,0 +1,20 @@
+if vim.g.loaded_clipr then
+  return
+end
+vim.g.loaded_clipr = true
+
+local function index_arg(opts)
+  return tonumber(opts.args) or 0
+end
+
+vim.api.nvim_create_user_command("Clipr", function()
+  require("clipr").pick()
+end, { desc = "Browse clipr history" })
+
+vim.api.nvim_create_user_command("CliprSet", function(opts)
+  require("clipr").set(index_arg(opts))
+end, { nargs = "?", desc = "Put clipr item on pasteboard" })
+
+vim.api.nvim_create_user_command("CliprPaste", function(opts)
+  require("clipr").paste(index_arg(opts))
+end, { nargs = "?", desc = "Paste clipr item after cursor" })